@@ -1,6 +1,11 @@
 use chimera_node::config::Settings;
 use chimera_node::health_monitor::Protocol;
+use chimera_node::identity::{
+    new_identity as request_new_identity, run_identity_schedule, NewIdentityOptions,
+};
+use chimera_node::ports;
 use chimera_node::process_manager::ProcessManager;
+use chimera_node::registry;
 use chimera_node::socks5::Socks5Server;
 use chrono::Local;
 use serde::{Deserialize, Serialize};
@@ -20,8 +25,10 @@ pub struct LogEntry {
 
 /// Application state shared across Tauri commands
 pub struct AppState {
-    pub process_manager: Option<ProcessManager>,
+    pub process_manager: Option<Arc<ProcessManager>>,
+    pub socks5_server: Option<Socks5Server>,
     pub socks5_handle: Option<JoinHandle<()>>,
+    pub identity_handle: Option<JoinHandle<()>>,
     pub running: bool,
     pub proxy_port: u16,
     pub logs: Vec<LogEntry>,
@@ -31,7 +38,9 @@ impl Default for AppState {
     fn default() -> Self {
         Self {
             process_manager: None,
+            socks5_server: None,
             socks5_handle: None,
+            identity_handle: None,
             running: false,
             proxy_port: 9050,
             logs: Vec::new(),
@@ -91,72 +100,64 @@ async fn start_daemon(
     }
 
     // Load settings
-    let settings = Settings::new().map_err(|e| format!("Config error: {}", e))?;
+    let mut settings = Settings::new().map_err(|e| format!("Config error: {}", e))?;
     let proxy_port = app_state.proxy_port;
+    settings.server.port = proxy_port;
 
     // Build enabled protocols set
     let mut enabled_protocols = HashSet::new();
     for p in protocols {
-        match p.as_str() {
-            "tor" => { enabled_protocols.insert(Protocol::Tor); },
-            "i2p" => { enabled_protocols.insert(Protocol::I2p); },
-            "nym" => { enabled_protocols.insert(Protocol::Nym); },
-            "lokinet" => { enabled_protocols.insert(Protocol::Lokinet); },
-            "ipfs" => { enabled_protocols.insert(Protocol::Ipfs); },
-            "zeronet" => { enabled_protocols.insert(Protocol::ZeroNet); },
-            "freenet" => { enabled_protocols.insert(Protocol::Freenet); },
-            "retroshare" => { enabled_protocols.insert(Protocol::RetroShare); },
-            "gnunet" => { enabled_protocols.insert(Protocol::GnuNet); },
-            "tribler" => { enabled_protocols.insert(Protocol::Tribler); },
-            _ => { eprintln!("Unknown protocol requested: {}", p); }
+        match p.parse::<Protocol>() {
+            Ok(protocol) => {
+                enabled_protocols.insert(protocol);
+            }
+            Err(_) => {
+                eprintln!("Unknown protocol requested: {}", p);
+            }
         }
     }
 
+    // Resolve "auto" ports and refuse to start on a conflict
+    ports::allocate(&mut settings, &enabled_protocols).map_err(|e| format!("Port error: {}", e))?;
+
     // Create ProcessManager
-    let pm = ProcessManager::new(
-        settings.chain_mode.clone(),
-        enabled_protocols,
-        settings.tor.clone(),
-        settings.i2p.clone(),
-        settings.nym.clone(),
-        settings.lokinet.clone(),
-        settings.ipfs.clone(),
-        settings.zeronet.clone(),
-        settings.freenet.clone(),
-        settings.retroshare.clone(),
-        settings.gnunet.clone(),
-        settings.tribler.clone(),
-    );
+    let pm = ProcessManager::new(&settings, enabled_protocols);
+
+    // Create the SOCKS5 proxy server before anything is spawned, so a bad
+    // chain or route leaves no process behind
+    let socks5_server = Socks5Server::new(proxy_port)
+        .with_registry(pm.registry())
+        .with_isolation(settings.tor.isolation)
+        .with_tor_pool(pm.tor())
+        .with_addressbook(pm.i2p().addressbook())
+        .with_stats(pm.stats())
+        .with_chains(&settings.chains, &settings.routes)
+        .map_err(|e| format!("Chain error: {}", e))?;
 
     // Start all protocol processes
     pm.start_processes()
         .await
         .map_err(|e| format!("Start error: {}", e))?;
 
-    // Create and start SOCKS5 proxy server
-    let socks5_server = Socks5Server::new(
-        proxy_port,
-        settings.tor.socks_port,
-        settings.i2p.socks_port,
-        settings.lokinet.socks_port,
-        settings.nym.socks_port,
-        settings.ipfs.gateway_port,
-        settings.zeronet.port,
-        settings.freenet.fcp_port,
-        settings.gnunet.socks_port,
-        settings.retroshare.api_url.clone(),
-        settings.tribler.api_url.clone(),
-    );
-
     // Spawn SOCKS5 server in background task
+    let server = socks5_server.clone();
     let handle = tokio::spawn(async move {
-        if let Err(e) = socks5_server.run().await {
+        if let Err(e) = server.run().await {
             eprintln!("SOCKS5 server error: {}", e);
         }
     });
 
+    let pm = Arc::new(pm);
+    let identity_handle = tokio::spawn(run_identity_schedule(
+        pm.clone(),
+        socks5_server.clone(),
+        settings.identity.clone(),
+    ));
+
     app_state.process_manager = Some(pm);
+    app_state.socks5_server = Some(socks5_server);
     app_state.socks5_handle = Some(handle);
+    app_state.identity_handle = Some(identity_handle);
     app_state.running = true;
 
     Ok(format!("Daemon started on 127.0.0.1:{}", proxy_port))
@@ -175,13 +176,77 @@ async fn stop_daemon(state: State<'_, Arc<Mutex<AppState>>>) -> Result<String, S
     if let Some(handle) = app_state.socks5_handle.take() {
         handle.abort();
     }
+    if let Some(handle) = app_state.identity_handle.take() {
+        handle.abort();
+    }
 
     app_state.process_manager = None;
+    app_state.socks5_server = None;
     app_state.running = false;
 
     Ok("Daemon stopped".into())
 }
 
+/// New identity on all networks (Tor NEWNYM, fresh Nym client, new isolation tokens)
+#[tauri::command]
+async fn new_identity(
+    state: State<'_, Arc<Mutex<AppState>>>,
+    close_connections: bool,
+) -> Result<String, String> {
+    let app_state = state.lock().await;
+
+    let (Some(pm), Some(server)) = (&app_state.process_manager, &app_state.socks5_server) else {
+        return Err("Daemon not running".into());
+    };
+
+    let options = NewIdentityOptions { close_connections };
+    let report = request_new_identity(pm, server, options).await;
+    if report.failed.is_empty() {
+        Ok(format!("New identity: {}", report))
+    } else {
+        Err(format!("New identity incomplete: {}", report))
+    }
+}
+
+/// A network the GUI can show and toggle
+#[derive(Serialize)]
+struct NetworkInfo {
+    id: String,
+    name: String,
+    endpoint: String,
+    /// "stream", "content", "messaging", "tunnel"; known once started
+    capabilities: Vec<String>,
+}
+
+/// Every network in the registry, in routing order
+#[tauri::command]
+async fn get_networks(state: State<'_, Arc<Mutex<AppState>>>) -> Result<Vec<NetworkInfo>, String> {
+    let app_state = state.lock().await;
+    let networks = match &app_state.process_manager {
+        // What each adapter reports now
+        Some(pm) => pm
+            .registry()
+            .iter()
+            .map(|e| NetworkInfo {
+                id: e.info.name(),
+                name: e.info.display_name.to_string(),
+                endpoint: e.endpoint().to_string(),
+                capabilities: e.capabilities().iter().map(|c| c.to_string()).collect(),
+            })
+            .collect(),
+        None => registry::BUILTIN
+            .iter()
+            .map(|info| NetworkInfo {
+                id: info.name(),
+                name: info.display_name.to_string(),
+                endpoint: format!("{:?}", info.endpoint).to_lowercase(),
+                capabilities: Vec::new(),
+            })
+            .collect(),
+    };
+    Ok(networks)
+}
+
 /// Get status of all protocols
 #[tauri::command]
 async fn get_status(
@@ -193,28 +258,16 @@ async fn get_status(
     status.insert("daemon".to_string(), app_state.running);
     status.insert("proxy".to_string(), app_state.running);
 
-    if let Some(pm) = &app_state.process_manager {
-        let health_map = pm.health_state.read().await;
-        
-        status.insert("tor".to_string(), *health_map.get(&Protocol::Tor).unwrap_or(&false));
-        status.insert("i2p".to_string(), *health_map.get(&Protocol::I2p).unwrap_or(&false));
-        status.insert("nym".to_string(), *health_map.get(&Protocol::Nym).unwrap_or(&false));
-        status.insert("lokinet".to_string(), *health_map.get(&Protocol::Lokinet).unwrap_or(&false));
-        status.insert("ipfs".to_string(), *health_map.get(&Protocol::Ipfs).unwrap_or(&false));
-        status.insert("zeronet".to_string(), *health_map.get(&Protocol::ZeroNet).unwrap_or(&false));
-        status.insert("freenet".to_string(), *health_map.get(&Protocol::Freenet).unwrap_or(&false));
-        status.insert("gnunet".to_string(), *health_map.get(&Protocol::GnuNet).unwrap_or(&false));
-        status.insert("retroshare".to_string(), *health_map.get(&Protocol::RetroShare).unwrap_or(&false));
-        status.insert("tribler".to_string(), *health_map.get(&Protocol::Tribler).unwrap_or(&false));
-    } else {
-         // If daemon is stopped, all are false
-        let protocols = vec![
-            "tor", "i2p", "nym", "lokinet", "ipfs", "zeronet", 
-            "freenet", "gnunet", "retroshare", "tribler"
-        ];
-        for p in protocols {
-            status.insert(p.to_string(), false);
-        }
+    // If daemon is stopped, all are false
+    let health_map = match &app_state.process_manager {
+        Some(pm) => pm.health_state.read().await.clone(),
+        None => HashMap::new(),
+    };
+    for info in registry::BUILTIN {
+        status.insert(
+            info.name(),
+            *health_map.get(&info.protocol).unwrap_or(&false),
+        );
     }
 
     Ok(status)
@@ -259,7 +312,9 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             start_daemon,
             stop_daemon,
+            new_identity,
             get_status,
+            get_networks,
             get_proxy_config,
             set_proxy_port,
             get_logs,
@@ -12,7 +12,9 @@ function App() {
     error,
     startDaemon,
     stopDaemon,
+    newIdentity,
     clearLogs,
+    networks,
     selectedProtocols,
     setSelectedProtocols
   } = useChimera();
@@ -54,6 +56,7 @@ function App() {
         <HeroStatus running={status.daemon} />
 
         <ProtocolGrid
+          networks={networks}
           status={status}
           selected={selectedProtocols}
           onToggle={handleToggle}
@@ -65,6 +68,7 @@ function App() {
           loading={loading}
           onStart={startDaemon}
           onStop={stopDaemon}
+          onNewIdentity={() => newIdentity()}
         />
 
         <LogTerminal logs={logs} onClear={clearLogs} />
@@ -1,13 +1,14 @@
-import { Play, Square } from 'lucide-react';
+import { Play, RefreshCw, Square } from 'lucide-react';
 
 interface ControlPanelProps {
     running: boolean;
     loading: boolean;
     onStart: () => void;
     onStop: () => void;
+    onNewIdentity: () => void;
 }
 
-export function ControlPanel({ running, loading, onStart, onStop }: ControlPanelProps) {
+export function ControlPanel({ running, loading, onStart, onStop, onNewIdentity }: ControlPanelProps) {
     return (
         <div className="glass-panel p-6 rounded-2xl mb-6 flex items-center justify-between">
             <div>
@@ -30,18 +31,29 @@ export function ControlPanel({ running, loading, onStart, onStop }: ControlPanel
                         INITIALIZE
                     </button>
                 ) : (
-                    <button
-                        onClick={onStop}
-                        disabled={loading}
-                        className="flex items-center gap-2 px-6 py-3 bg-zinc-800 hover:bg-red-900/30 text-zinc-300 hover:text-red-400 border border-zinc-700 hover:border-red-500/50 font-bold rounded-lg transition-all disabled:opacity-50 disabled:cursor-not-allowed"
-                    >
-                        {loading ? (
-                            <div className="h-5 w-5 border-2 border-zinc-500/30 border-t-zinc-500 rounded-full animate-spin" />
-                        ) : (
-                            <Square size={20} fill="currentColor" />
-                        )}
-                        TERMINATE
-                    </button>
+                    <>
+                        <button
+                            onClick={onNewIdentity}
+                            disabled={loading}
+                            title="New circuits on Tor, fresh Nym identity, new isolation tokens"
+                            className="flex items-center gap-2 px-6 py-3 bg-zinc-800 hover:bg-violet-900/30 text-zinc-300 hover:text-violet-300 border border-zinc-700 hover:border-violet-500/50 font-bold rounded-lg transition-all disabled:opacity-50 disabled:cursor-not-allowed"
+                        >
+                            <RefreshCw size={20} />
+                            NEW IDENTITY
+                        </button>
+                        <button
+                            onClick={onStop}
+                            disabled={loading}
+                            className="flex items-center gap-2 px-6 py-3 bg-zinc-800 hover:bg-red-900/30 text-zinc-300 hover:text-red-400 border border-zinc-700 hover:border-red-500/50 font-bold rounded-lg transition-all disabled:opacity-50 disabled:cursor-not-allowed"
+                        >
+                            {loading ? (
+                                <div className="h-5 w-5 border-2 border-zinc-500/30 border-t-zinc-500 rounded-full animate-spin" />
+                            ) : (
+                                <Square size={20} fill="currentColor" />
+                            )}
+                            TERMINATE
+                        </button>
+                    </>
                 )}
             </div>
         </div>
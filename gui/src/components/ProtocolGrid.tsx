@@ -1,30 +1,50 @@
 import { motion } from 'framer-motion';
 import { Globe, Shield, Radio, Server, Layers, Check, X } from 'lucide-react';
+import type { NetworkInfo } from '../hooks/useChimera';
 
 interface ProtocolProps {
+    networks: NetworkInfo[];
     status: Record<string, boolean>;
     selected: string[];
     onToggle: (id: string) => void;
     disabled: boolean;
 }
 
-const protocols = [
-    { id: 'tor', name: 'Tor', icon: Shield, color: 'text-violet-400' },
-    { id: 'i2p', name: 'I2P', icon: Globe, color: 'text-yellow-400' },
-    { id: 'nym', name: 'Nym', icon: Radio, color: 'text-orange-500' },
-    { id: 'lokinet', name: 'Lokinet', icon: Server, color: 'text-green-500' },
-    { id: 'ipfs', name: 'IPFS', icon: Layers, color: 'text-cyan-400' },
-    { id: 'zeronet', name: 'ZeroNet', icon: Globe, color: 'text-pink-400' },
-    { id: 'freenet', name: 'Freenet', icon: Layers, color: 'text-blue-500' },
-    { id: 'retroshare', name: 'RetroShare', icon: Shield, color: 'text-emerald-400' },
-    { id: 'gnunet', name: 'GNUnet', icon: Server, color: 'text-teal-400' },
-    { id: 'tribler', name: 'Tribler', icon: Layers, color: 'text-red-400' },
-];
+// Icon and color per network id; the list itself comes from the registry
+const styles: Record<string, { icon: typeof Globe; color: string }> = {
+    tor: { icon: Shield, color: 'text-violet-400' },
+    i2p: { icon: Globe, color: 'text-yellow-400' },
+    nym: { icon: Radio, color: 'text-orange-500' },
+    lokinet: { icon: Server, color: 'text-green-500' },
+    ipfs: { icon: Layers, color: 'text-cyan-400' },
+    zeronet: { icon: Globe, color: 'text-pink-400' },
+    freenet: { icon: Layers, color: 'text-blue-500' },
+    retroshare: { icon: Shield, color: 'text-emerald-400' },
+    gnunet: { icon: Server, color: 'text-teal-400' },
+    tribler: { icon: Layers, color: 'text-red-400' },
+    v2ray: { icon: Shield, color: 'text-sky-400' },
+    trojan: { icon: Shield, color: 'text-rose-400' },
+    outline: { icon: Shield, color: 'text-lime-400' },
+    psiphon: { icon: Shield, color: 'text-amber-400' },
+    mysterium: { icon: Radio, color: 'text-fuchsia-400' },
+    lantern: { icon: Radio, color: 'text-yellow-500' },
+    sentinel: { icon: Radio, color: 'text-indigo-400' },
+    wireguard: { icon: Server, color: 'text-red-500' },
+    yggdrasil: { icon: Globe, color: 'text-green-400' },
+    cjdns: { icon: Globe, color: 'text-teal-500' },
+    session: { icon: Radio, color: 'text-emerald-500' },
+    snowflake: { icon: Layers, color: 'text-sky-300' },
+    obfs4: { icon: Layers, color: 'text-violet-300' },
+    meek: { icon: Layers, color: 'text-blue-300' },
+};
 
-export function ProtocolGrid({ status, selected, onToggle, disabled }: ProtocolProps) {
+const fallbackStyle = { icon: Globe, color: 'text-zinc-400' };
+
+export function ProtocolGrid({ networks, status, selected, onToggle, disabled }: ProtocolProps) {
     return (
         <div className="grid grid-cols-2 lg:grid-cols-5 gap-4 mb-6">
-            {protocols.map((p, i) => {
+            {networks.map((network, i) => {
+                const p = { ...network, ...(styles[network.id] ?? fallbackStyle) };
                 const isSelected = selected.includes(p.id);
                 const isActive = status[p.id];
 
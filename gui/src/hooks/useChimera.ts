@@ -13,14 +13,30 @@ export interface AppStatus {
     [key: string]: boolean;
 }
 
+export interface NetworkInfo {
+    id: string;
+    name: string;
+    endpoint: string;
+    capabilities: string[];
+}
+
 export function useChimera() {
     const [status, setStatus] = useState<AppStatus>({ daemon: false, proxy: false });
     const [logs, setLogs] = useState<LogEntry[]>([]);
     const [loading, setLoading] = useState(false);
     const [error, setError] = useState<string | null>(null);
-    const [selectedProtocols, setSelectedProtocols] = useState<string[]>([
-        'tor', 'i2p', 'nym', 'lokinet', 'ipfs', 'zeronet', 'freenet', 'retroshare', 'gnunet', 'tribler'
-    ]);
+    const [networks, setNetworks] = useState<NetworkInfo[]>([]);
+    const [selectedProtocols, setSelectedProtocols] = useState<string[]>([]);
+
+    // The registry decides which networks exist; all start selected
+    useEffect(() => {
+        invoke<NetworkInfo[]>('get_networks')
+            .then((n) => {
+                setNetworks(n);
+                setSelectedProtocols(n.map((network) => network.id));
+            })
+            .catch((e) => console.error('Failed to fetch networks:', e));
+    }, []);
 
     const fetchStatus = useCallback(async () => {
         try {
@@ -66,6 +82,19 @@ export function useChimera() {
         }
     };
 
+    const newIdentity = async (closeConnections = false) => {
+        setLoading(true);
+        setError(null);
+        try {
+            await invoke('new_identity', { closeConnections });
+            await fetchLogs();
+        } catch (e: any) {
+            setError(e.toString());
+        } finally {
+            setLoading(false);
+        }
+    };
+
     const clearLogs = async () => {
         try {
             await invoke('clear_logs');
@@ -92,7 +121,9 @@ export function useChimera() {
         error,
         startDaemon,
         stopDaemon,
+        newIdentity,
         clearLogs,
+        networks,
         selectedProtocols,
         setSelectedProtocols
     };
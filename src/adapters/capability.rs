@@ -0,0 +1,159 @@
+//! Optional capabilities on top of `ProtocolAdapter`.
+//!
+//! An adapter implements the traits that apply to its network and returns
+//! itself from the matching `ProtocolAdapter::as_*` method, so callers
+//! holding a `dyn ProtocolAdapter` (e.g. from the registry) can ask what it
+//! can do at runtime.
+
+use super::IpPrefix;
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use std::any::Any;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+
+/// What an adapter can do besides start, stop and report health
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    StreamTransport,
+    ContentStore,
+    Messaging,
+    TunnelInterface,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::StreamTransport,
+        Capability::ContentStore,
+        Capability::Messaging,
+        Capability::TunnelInterface,
+    ];
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Capability::StreamTransport => "stream",
+            Capability::ContentStore => "content",
+            Capability::Messaging => "messaging",
+            Capability::TunnelInterface => "tunnel",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Carries TCP streams to arbitrary destinations
+#[async_trait]
+pub trait StreamTransport: Send + Sync {
+    /// Open a connection to `host:port` through the network
+    async fn open(&self, host: &str, port: u16) -> Result<TransportStream>;
+}
+
+/// A connection opened by a `StreamTransport`. Anything the transport
+/// attaches with `with_guard` (e.g. the Tor pool's lease counting the
+/// connection against its instance) is held until the stream is dropped.
+pub struct TransportStream {
+    stream: TcpStream,
+    _guard: Option<Box<dyn Any + Send + Sync>>,
+}
+
+impl TransportStream {
+    pub fn with_guard(mut self, guard: impl Any + Send + Sync) -> Self {
+        self._guard = Some(Box::new(guard));
+        self
+    }
+
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+}
+
+impl From<TcpStream> for TransportStream {
+    fn from(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            _guard: None,
+        }
+    }
+}
+
+impl fmt::Debug for TransportStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransportStream")
+            .field("stream", &self.stream)
+            .field("guarded", &self._guard.is_some())
+            .finish()
+    }
+}
+
+impl AsyncRead for TransportStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TransportStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Stores and serves content by URI
+#[async_trait]
+pub trait ContentStore: Send + Sync {
+    /// Fetch the content at `uri` (`ipfs://<cid>`, `USK@...`)
+    async fn get(&self, uri: &str) -> Result<Vec<u8>>;
+
+    /// Store `data`; returns the URI it can be fetched by
+    async fn put(&self, data: &[u8]) -> Result<String>;
+}
+
+/// A message received by a [`Messaging`] network
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub data: Vec<u8>,
+    /// Pass to [`Messaging::send`] to answer, when the sender allows it
+    pub reply_to: Option<String>,
+}
+
+/// Sends and receives messages
+#[async_trait]
+pub trait Messaging: Send + Sync {
+    /// Our address, to hand to peers
+    async fn address(&self) -> Result<String>;
+
+    /// Send `data` to an address or a [`Message::reply_to`]
+    async fn send(&self, to: &str, data: &[u8]) -> Result<()>;
+
+    /// Messages received from now on
+    async fn receive(&self) -> Result<BoxStream<'static, Message>>;
+}
+
+/// Provides a network interface
+pub trait TunnelInterface: Send + Sync {
+    fn interfaces(&self) -> Vec<String>;
+
+    /// Address ranges reached through the interfaces
+    fn prefixes(&self) -> Vec<IpPrefix>;
+}
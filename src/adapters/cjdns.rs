@@ -1,10 +1,21 @@
-use super::ProtocolAdapter;
+use super::{
+    Daemon, Endpoint, IpPrefix, Probe, ProtocolAdapter, StreamTransport, TransportStream,
+    TunnelInterface,
+};
+use crate::config::AdapterMode;
+use anyhow::{anyhow, bail};
 use async_trait::async_trait;
 use log::info;
+use std::net::{IpAddr, Ipv6Addr};
 use std::process::{Child, Command, Stdio};
 use std::sync::Arc;
+use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 
+/// cjdns node addresses
+const MESH_PREFIX: IpPrefix =
+    IpPrefix::new(IpAddr::V6(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0)), 8);
+
 /// CJDNS Encrypted Mesh Network Adapter
 /// Provides encrypted IPv6 mesh networking
 pub struct CjdnsAdapter {
@@ -12,6 +23,7 @@ pub struct CjdnsAdapter {
     binary_path: String,
     config_path: Option<String>,
     process: Arc<Mutex<Option<Child>>>,
+    daemon: Daemon,
 }
 
 impl CjdnsAdapter {
@@ -21,8 +33,18 @@ impl CjdnsAdapter {
             binary_path,
             config_path,
             process: Arc::new(Mutex::new(None)),
+            daemon: Daemon::default(),
         }
     }
+
+    pub fn with_mode(mut self, mode: AdapterMode) -> Self {
+        self.daemon = Daemon::new(mode);
+        self
+    }
+
+    fn probe(&self) -> Probe {
+        Probe::Prefix(MESH_PREFIX)
+    }
 }
 
 #[async_trait]
@@ -33,6 +55,10 @@ impl ProtocolAdapter for CjdnsAdapter {
             return Ok(());
         }
 
+        if !self.daemon.should_spawn("CJDNS", &self.probe()).await? {
+            return Ok(());
+        }
+
         info!("Starting CJDNS...");
         let config = self.config_path.as_deref().unwrap_or("cjdroute.conf");
 
@@ -50,6 +76,7 @@ impl ProtocolAdapter for CjdnsAdapter {
     }
 
     async fn stop(&self) -> anyhow::Result<()> {
+        self.daemon.detach();
         let mut proc_lock = self.process.lock().await;
         if let Some(ref mut child) = *proc_lock {
             child.kill()?;
@@ -60,12 +87,52 @@ impl ProtocolAdapter for CjdnsAdapter {
     }
 
     async fn is_healthy(&self) -> bool {
+        if self.daemon.is_attached() {
+            return self.probe().answers().await;
+        }
         let proc_lock = self.process.lock().await;
         proc_lock.is_some()
     }
 
-    fn get_proxy_addr(&self) -> String {
+    fn endpoint(&self) -> Endpoint {
         // CJDNS creates a TUN interface, no SOCKS proxy
-        String::new()
+        Endpoint::Tun {
+            interface: self.interfaces().join(","),
+            prefixes: self.prefixes(),
+            dns: None,
+        }
+    }
+
+    fn as_stream_transport(&self) -> Option<&dyn StreamTransport> {
+        Some(self)
+    }
+
+    fn as_tunnel_interface(&self) -> Option<&dyn TunnelInterface> {
+        Some(self)
+    }
+}
+
+/// Direct connections to mesh addresses
+#[async_trait]
+impl StreamTransport for CjdnsAdapter {
+    async fn open(&self, host: &str, port: u16) -> anyhow::Result<TransportStream> {
+        let ip: IpAddr = host
+            .parse()
+            .map_err(|_| anyhow!("{} is not a cjdns address", host))?;
+        if !MESH_PREFIX.contains(ip) {
+            bail!("{} is outside {}", ip, MESH_PREFIX);
+        }
+        Ok(TcpStream::connect((ip, port)).await?.into())
+    }
+}
+
+impl TunnelInterface for CjdnsAdapter {
+    /// Picked by cjdroute unless `tunDevice` is set
+    fn interfaces(&self) -> Vec<String> {
+        vec!["auto".to_string()]
+    }
+
+    fn prefixes(&self) -> Vec<IpPrefix> {
+        vec![MESH_PREFIX]
     }
 }
@@ -0,0 +1,303 @@
+//! Typed description of where a network can be reached.
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use tokio::net::TcpStream;
+
+/// What a network exposes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointKind {
+    /// SOCKS5 proxy; can carry any TCP stream and be a chain's first hop
+    Socks5,
+    /// HTTP proxy
+    HttpProxy,
+    /// HTTP gateway serving the network's content (IPFS, FProxy, ZeroNet)
+    HttpGateway,
+    /// Network interface; traffic is routed to its addresses directly
+    Tun,
+    /// Pluggable transport used by another network
+    Transport,
+    /// Control or REST API only
+    Api,
+    None,
+}
+
+/// An IP network such as `200::/7`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpPrefix {
+    pub addr: IpAddr,
+    pub len: u8,
+}
+
+impl IpPrefix {
+    pub const fn new(addr: IpAddr, len: u8) -> Self {
+        Self { addr, len }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.len as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.len as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for IpPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+impl FromStr for IpPrefix {
+    type Err = anyhow::Error;
+
+    /// `addr/len`; a bare address is a single host
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (addr, len) = match s.trim().split_once('/') {
+            Some((addr, len)) => (addr.parse::<IpAddr>()?, Some(len.parse::<u8>()?)),
+            None => (s.trim().parse::<IpAddr>()?, None),
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let len = len.unwrap_or(max);
+        if len > max {
+            anyhow::bail!("prefix length {} is too long for {}", len, addr);
+        }
+        Ok(Self { addr, len })
+    }
+}
+
+/// `host:port` of a service that may run on another machine, such as
+/// `freenet.host`. Names stay unresolved until a connection is made, so
+/// building an endpoint never waits on DNS.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HostPort {
+    /// Name or IP literal, without IPv6 brackets
+    pub host: String,
+    pub port: u16,
+}
+
+impl HostPort {
+    pub fn new(host: &str, port: u16) -> Self {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        Self {
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    /// Resolve without blocking the runtime; IP literals don't touch DNS
+    pub async fn resolve(&self) -> std::io::Result<SocketAddr> {
+        tokio::net::lookup_host((self.host.as_str(), self.port))
+            .await?
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("{} has no address", self.host),
+                )
+            })
+    }
+
+    /// Resolve and connect
+    pub async fn connect(&self) -> std::io::Result<TcpStream> {
+        TcpStream::connect((self.host.as_str(), self.port)).await
+    }
+}
+
+impl From<SocketAddr> for HostPort {
+    fn from(addr: SocketAddr) -> Self {
+        Self {
+            host: addr.ip().to_string(),
+            port: addr.port(),
+        }
+    }
+}
+
+impl fmt::Display for HostPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl FromStr for HostPort {
+    type Err = anyhow::Error;
+
+    /// `host:port`, with IPv6 literals in brackets
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (host, port) = s
+            .trim()
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow::anyhow!("{:?} is not host:port", s))?;
+        if host.is_empty() || (host.contains(':') && !host.starts_with('[')) {
+            anyhow::bail!("{:?} is not host:port", s);
+        }
+        Ok(Self::new(host, port.parse()?))
+    }
+}
+
+/// Where a network can be reached from this machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Socks5(SocketAddr),
+    HttpProxy(SocketAddr),
+    HttpGateway(HostPort),
+    /// Addresses in `prefixes` are dialled directly through `interface`;
+    /// `dns` resolves the network's names to such addresses
+    Tun {
+        interface: String,
+        prefixes: Vec<IpPrefix>,
+        dns: Option<SocketAddr>,
+    },
+    /// SOCKS endpoint of a pluggable transport; needs bridge arguments
+    Transport(SocketAddr),
+    Api(HostPort),
+    /// Nothing reachable (not started, or no endpoint at all)
+    None,
+}
+
+impl Endpoint {
+    pub fn kind(&self) -> EndpointKind {
+        match self {
+            Endpoint::Socks5(_) => EndpointKind::Socks5,
+            Endpoint::HttpProxy(_) => EndpointKind::HttpProxy,
+            Endpoint::HttpGateway(_) => EndpointKind::HttpGateway,
+            Endpoint::Tun { .. } => EndpointKind::Tun,
+            Endpoint::Transport(_) => EndpointKind::Transport,
+            Endpoint::Api(_) => EndpointKind::Api,
+            Endpoint::None => EndpointKind::None,
+        }
+    }
+
+    /// Where to dial: the proxy, gateway or API, or a TUN network's resolver
+    pub fn addr(&self) -> Option<HostPort> {
+        match self {
+            Endpoint::Socks5(addr) | Endpoint::HttpProxy(addr) | Endpoint::Transport(addr) => {
+                Some((*addr).into())
+            }
+            Endpoint::HttpGateway(addr) | Endpoint::Api(addr) => Some(addr.clone()),
+            Endpoint::Tun { dns, .. } => dns.map(HostPort::from),
+            Endpoint::None => None,
+        }
+    }
+
+    /// SOCKS5 proxy address, if this is one
+    pub fn socks5(&self) -> Option<SocketAddr> {
+        match self {
+            Endpoint::Socks5(addr) => Some(*addr),
+            _ => None,
+        }
+    }
+
+    /// Whether `ip` is reached through this network's interface
+    pub fn routes(&self, ip: IpAddr) -> bool {
+        match self {
+            Endpoint::Tun { prefixes, .. } => prefixes.iter().any(|p| p.contains(ip)),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Socks5(addr) => write!(f, "socks5 {}", addr),
+            Endpoint::HttpProxy(addr) => write!(f, "http proxy {}", addr),
+            Endpoint::HttpGateway(addr) => write!(f, "http gateway {}", addr),
+            Endpoint::Tun {
+                interface,
+                prefixes,
+                dns,
+            } => {
+                write!(f, "tun {}", interface)?;
+                for prefix in prefixes {
+                    write!(f, " {}", prefix)?;
+                }
+                if let Some(dns) = dns {
+                    write!(f, " dns {}", dns)?;
+                }
+                Ok(())
+            }
+            Endpoint::Transport(addr) => write!(f, "transport {}", addr),
+            Endpoint::Api(addr) => write!(f, "api {}", addr),
+            Endpoint::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Host and port of an `http(s)://host[:port]` URL
+pub fn url_addr(url: &str) -> Option<HostPort> {
+    let url = reqwest::Url::parse(url).ok()?;
+    Some(HostPort::new(url.host_str()?, url.port_or_known_default()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_host_port_resolves_at_connect_time() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let target = HostPort::new("localhost", port);
+        assert!(target.resolve().await.unwrap().ip().is_loopback());
+        target.connect().await.unwrap();
+    }
+
+    #[test]
+    fn test_prefix_contains() {
+        let ygg = IpPrefix::new("200::".parse().unwrap(), 7);
+        assert!(ygg.contains("201:abcd::1".parse().unwrap()));
+        assert!(ygg.contains("300::1".parse().unwrap()));
+        assert!(!ygg.contains("fc00::1".parse().unwrap()));
+        assert!(!ygg.contains("10.0.0.1".parse().unwrap()));
+
+        let range = IpPrefix::new("10.67.0.0".parse().unwrap(), 16);
+        assert!(range.contains("10.67.3.4".parse().unwrap()));
+        assert!(!range.contains("10.68.0.1".parse().unwrap()));
+        assert!(IpPrefix::new("0.0.0.0".parse().unwrap(), 0).contains("1.2.3.4".parse().unwrap()));
+
+        assert_eq!(
+            "fc00::/8".parse::<IpPrefix>().unwrap().to_string(),
+            "fc00::/8"
+        );
+        assert_eq!("10.0.0.1".parse::<IpPrefix>().unwrap().len, 32);
+        assert!("10.0.0.0/33".parse::<IpPrefix>().is_err());
+    }
+
+    #[test]
+    fn test_addresses() {
+        assert_eq!(
+            url_addr("http://127.0.0.1:43110"),
+            Some("127.0.0.1:43110".parse().unwrap())
+        );
+        assert_eq!(url_addr("http://[::1]"), Some("[::1]:80".parse().unwrap()));
+        assert_eq!(url_addr("http://[::1]").unwrap().to_string(), "[::1]:80");
+        // Names are kept as configured, not resolved
+        let fproxy = url_addr("http://freenet.lan:8888").unwrap();
+        assert_eq!(fproxy, HostPort::new("freenet.lan", 8888));
+        assert_eq!(fproxy.to_string(), "freenet.lan:8888");
+        assert!("::1".parse::<HostPort>().is_err());
+        assert!(":80".parse::<HostPort>().is_err());
+
+        let tun = Endpoint::Tun {
+            interface: "chimera0".to_string(),
+            prefixes: vec![],
+            dns: Some("127.0.0.1:1053".parse().unwrap()),
+        };
+        assert_eq!(tun.addr(), Some("127.0.0.1:1053".parse().unwrap()));
+        assert_eq!(tun.socks5(), None);
+        assert_eq!(tun.to_string(), "tun chimera0 dns 127.0.0.1:1053");
+    }
+}
@@ -1,10 +1,12 @@
-use super::ProtocolAdapter;
+use super::{ContentStore, Endpoint, HostPort, ProtocolAdapter};
 use crate::config::FreenetSettings;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
 use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 
@@ -16,6 +18,8 @@ use tokio::sync::Mutex;
 pub struct FreenetAdapter {
     settings: FreenetSettings,
     connected: Arc<Mutex<bool>>,
+    /// Numbers FCP request identifiers
+    requests: AtomicU64,
 }
 
 impl FreenetAdapter {
@@ -23,6 +27,7 @@ impl FreenetAdapter {
         Self {
             settings,
             connected: Arc::new(Mutex::new(false)),
+            requests: AtomicU64::new(0),
         }
     }
 
@@ -46,6 +51,58 @@ impl FreenetAdapter {
 
         Ok(response.contains("NodeHello"))
     }
+
+    /// Send one request on a fresh FCP connection and read messages until
+    /// one of `done`. Failure messages become errors.
+    async fn fcp_request(
+        &self,
+        request: &str,
+        data: Option<&[u8]>,
+        done: &[&str],
+    ) -> Result<FcpMessage> {
+        let addr = format!("{}:{}", self.settings.host, self.settings.fcp_port);
+        let mut stream = BufReader::new(TcpStream::connect(&addr).await?);
+        stream
+            .write_all(b"ClientHello\nName=Chimera\nExpectedVersion=2.0\nEndMessage\n")
+            .await?;
+        let hello = read_message(&mut stream).await?;
+        if hello.name != "NodeHello" {
+            bail!("Freenet node answered {} to ClientHello", hello.name);
+        }
+
+        let identifier = format!(
+            "chimera-{}-{}",
+            std::process::id(),
+            self.requests.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut message = format!("{}\nIdentifier={}\n", request, identifier).into_bytes();
+        match data {
+            Some(data) => {
+                message.extend_from_slice(format!("DataLength={}\nData\n", data.len()).as_bytes());
+                message.extend_from_slice(data);
+            }
+            None => message.extend_from_slice(b"EndMessage\n"),
+        }
+        stream.write_all(&message).await?;
+
+        loop {
+            let reply = read_message(&mut stream).await?;
+            if done.contains(&reply.name.as_str()) {
+                return Ok(reply);
+            }
+            if reply.name.ends_with("Failed") || reply.name == "ProtocolError" {
+                bail!(
+                    "{}: {}",
+                    reply.name,
+                    reply
+                        .fields
+                        .get("CodeDescription")
+                        .map(String::as_str)
+                        .unwrap_or("no description")
+                );
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -98,9 +155,16 @@ impl ProtocolAdapter for FreenetAdapter {
         Ok(())
     }
 
-    fn get_proxy_addr(&self) -> String {
-        // Freenet uses FProxy (HTTP) for content access
-        format!("{}:{}", self.settings.host, self.settings.fproxy_port)
+    fn endpoint(&self) -> Endpoint {
+        // FProxy serves content over HTTP; the node may be on another host
+        Endpoint::HttpGateway(HostPort::new(
+            &self.settings.host,
+            self.settings.fproxy_port,
+        ))
+    }
+
+    fn as_content_store(&self) -> Option<&dyn ContentStore> {
+        Some(self)
     }
 
     async fn is_healthy(&self) -> bool {
@@ -108,3 +172,101 @@ impl ProtocolAdapter for FreenetAdapter {
         *connected
     }
 }
+
+/// Content through FCP, with the data sent and returned inline
+#[async_trait]
+impl ContentStore for FreenetAdapter {
+    /// `USK@...`, `SSK@...`, `CHK@...` or `KSK@...`, optionally `freenet:`-prefixed
+    async fn get(&self, uri: &str) -> Result<Vec<u8>> {
+        let key = uri.strip_prefix("freenet:").unwrap_or(uri);
+        let request = format!("ClientGet\nURI={}\nReturnType=direct\nVerbosity=0", key);
+        let reply = self.fcp_request(&request, None, &["AllData"]).await?;
+        Ok(reply.data.unwrap_or_default())
+    }
+
+    /// Inserts `data` under a content hash key; returns the `CHK@` URI
+    async fn put(&self, data: &[u8]) -> Result<String> {
+        let request = "ClientPut\nURI=CHK@\nUploadFrom=direct\nVerbosity=0";
+        let reply = self
+            .fcp_request(request, Some(data), &["PutSuccessful"])
+            .await?;
+        reply
+            .fields
+            .get("URI")
+            .cloned()
+            .ok_or_else(|| anyhow!("PutSuccessful without a URI"))
+    }
+}
+
+/// An FCP message: its name, fields, and the payload when it ended in `Data`
+#[derive(Debug)]
+struct FcpMessage {
+    name: String,
+    fields: HashMap<String, String>,
+    data: Option<Vec<u8>>,
+}
+
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<FcpMessage> {
+    let mut name = String::new();
+    let mut fields = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            bail!("Freenet node closed the FCP connection");
+        }
+        let line = line.trim_end();
+        if name.is_empty() {
+            if !line.is_empty() {
+                name = line.to_string();
+            }
+            continue;
+        }
+        match line {
+            "EndMessage" => {
+                return Ok(FcpMessage {
+                    name,
+                    fields,
+                    data: None,
+                })
+            }
+            "Data" => {
+                let len = fields
+                    .get("DataLength")
+                    .and_then(|l| l.parse::<usize>().ok())
+                    .ok_or_else(|| anyhow!("{} has Data but no DataLength", name))?;
+                let mut data = vec![0u8; len];
+                reader.read_exact(&mut data).await?;
+                return Ok(FcpMessage {
+                    name,
+                    fields,
+                    data: Some(data),
+                });
+            }
+            _ => {
+                if let Some((key, value)) = line.split_once('=') {
+                    fields.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_message() {
+        let mut input: &[u8] = b"DataFound\nIdentifier=x\nEndMessage\n\
+                                 AllData\nIdentifier=x\nDataLength=5\nData\nhello";
+        let found = read_message(&mut input).await.unwrap();
+        assert_eq!(found.name, "DataFound");
+        assert_eq!(found.fields["Identifier"], "x");
+        assert!(found.data.is_none());
+
+        let all = read_message(&mut input).await.unwrap();
+        assert_eq!(all.name, "AllData");
+        assert_eq!(all.data.as_deref(), Some(&b"hello"[..]));
+        assert!(read_message(&mut input).await.is_err());
+    }
+}
@@ -1,10 +1,11 @@
-use super::ProtocolAdapter;
+use super::{Daemon, Endpoint, Probe, ProtocolAdapter, StreamTransport, TransportStream};
 use crate::config::GnunetSettings;
 use anyhow::Result;
 use async_trait::async_trait;
 use log::{info, warn};
-use std::process::Command;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::process::Command;
 use tokio::sync::Mutex;
 
 /// GNUnet Adapter
@@ -15,16 +16,31 @@ use tokio::sync::Mutex;
 /// or via DNS interception.
 pub struct GnunetAdapter {
     settings: GnunetSettings,
-    connected: Arc<Mutex<bool>>,
+    daemon: Daemon,
+    /// GNUnet's services were started by us and are stopped again
+    started: Arc<Mutex<bool>>,
 }
 
 impl GnunetAdapter {
     pub fn new(settings: GnunetSettings) -> Self {
         Self {
+            daemon: Daemon::new(settings.mode),
             settings,
-            connected: Arc::new(Mutex::new(false)),
+            started: Arc::new(Mutex::new(false)),
         }
     }
+
+    /// gnunet-gns-proxy, which an external GNUnet must be running
+    fn probe(&self) -> Probe {
+        Probe::Socks(SocketAddr::from(([127, 0, 0, 1], self.settings.socks_port)))
+    }
+
+    async fn arm(&self, flag: &str) -> Result<std::process::Output> {
+        Ok(Command::new(&self.settings.binary_path)
+            .arg(flag)
+            .output()
+            .await?)
+    }
 }
 
 #[async_trait]
@@ -34,24 +50,25 @@ impl ProtocolAdapter for GnunetAdapter {
             return Ok(());
         }
 
-        info!("Initializing GNUnet adapter...");
-
-        // Check if gnunet-arm is running or accessible
-        // Using strict command execution to check version
-        let output = Command::new("gnunet-arm").arg("-I").output();
+        if !self.daemon.should_spawn("GNUnet", &self.probe()).await? {
+            return Ok(());
+        }
 
-        match output {
+        info!("Starting GNUnet services...");
+        match self.arm("-s").await {
+            Ok(o) if o.status.success() => {
+                info!("GNUnet services started.");
+                *self.started.lock().await = true;
+            }
             Ok(o) => {
-                if o.status.success() {
-                    info!("GNUnet service detected.");
-                    let mut connected = self.connected.lock().await;
-                    *connected = true;
-                } else {
-                    warn!("GNUnet service check returned error status.");
-                }
+                warn!(
+                    "gnunet-arm -s failed: {}",
+                    String::from_utf8_lossy(&o.stderr).trim()
+                );
+                return Ok(());
             }
             Err(e) => {
-                warn!("Failed to execute gnunet-arm: {}", e);
+                warn!("Failed to execute {}: {}", self.settings.binary_path, e);
                 warn!("Is GNUnet installed and in PATH?");
                 return Ok(());
             }
@@ -65,20 +82,39 @@ impl ProtocolAdapter for GnunetAdapter {
     }
 
     async fn stop(&self) -> Result<()> {
-        let mut connected = self.connected.lock().await;
-        if *connected {
-            *connected = false;
-            info!("GNUnet adapter stopped.");
+        self.daemon.detach();
+        let mut started = self.started.lock().await;
+        if *started {
+            *started = false;
+            self.arm("-e").await?;
+            info!("GNUnet services stopped.");
         }
         Ok(())
     }
 
-    fn get_proxy_addr(&self) -> String {
-        format!("127.0.0.1:{}", self.settings.socks_port)
+    fn endpoint(&self) -> Endpoint {
+        Endpoint::Socks5(SocketAddr::from(([127, 0, 0, 1], self.settings.socks_port)))
+    }
+
+    fn as_stream_transport(&self) -> Option<&dyn StreamTransport> {
+        Some(self)
     }
 
     async fn is_healthy(&self) -> bool {
-        let connected = self.connected.lock().await;
-        *connected
+        if self.daemon.is_attached() {
+            return self.probe().answers().await;
+        }
+        let started = self.started.lock().await;
+        *started
+    }
+}
+
+#[async_trait]
+impl StreamTransport for GnunetAdapter {
+    async fn open(&self, host: &str, port: u16) -> Result<TransportStream> {
+        let proxy = SocketAddr::from(([127, 0, 0, 1], self.settings.socks_port));
+        Ok(crate::socks5::connect_through(proxy, host, port)
+            .await?
+            .into())
     }
 }
@@ -1,26 +1,143 @@
-use super::ProtocolAdapter;
-use crate::config::I2pSettings;
-use anyhow::Result;
+pub mod addressbook;
+pub mod destination;
+pub mod i2pcontrol;
+pub mod sam;
+pub mod tunnels;
+
+use super::{Daemon, Endpoint, Probe, ProtocolAdapter, StreamTransport, TransportStream};
+use crate::config::{ContributeSettings, I2pSettings};
+use crate::stats::Stats;
+use addressbook::AddressBook;
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
-use log::{info, warn};
-use std::path::Path;
+use i2pcontrol::{I2pControlClient, RouterCommand, RouterStats};
+use log::{debug, info, warn};
+use sam::SamClient;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::process::{Child, Command};
 use tokio::sync::Mutex;
 
+/// Data directory Chimera hands to i2pd (`--datadir`)
+pub const DATA_DIR: &str = "data/i2p";
+/// i2pd.conf Chimera writes into the data directory (`--conf`)
+pub const I2PD_CONF: &str = "i2pd.conf";
+
 pub struct I2pAdapter {
     settings: I2pSettings,
     process: Arc<Mutex<Option<Child>>>,
+    daemon: Daemon,
+    addressbook: Arc<AddressBook>,
+    /// Configured or generated I2PControl password; `None` disables
+    /// I2PControl on the i2pd Chimera starts
+    i2pcontrol_password: Option<String>,
+    i2pcontrol: Option<Arc<I2pControlClient>>,
+    stats: Option<Arc<Stats>>,
+    /// Transit/floodfill options from `[contribute]`
+    contribution_args: Vec<String>,
 }
 
 impl I2pAdapter {
     pub fn new(settings: I2pSettings) -> Self {
+        let addressbook = AddressBook::new(
+            settings.addressbook.clone(),
+            Path::new(DATA_DIR).join("chimera-hosts.txt"),
+        )
+        .with_http_proxy(format!("http://127.0.0.1:{}", settings.http_proxy_port));
+        let i2pcontrol_password = match settings.i2pcontrol_password.as_str() {
+            "" => random_password(),
+            configured => Ok(configured.to_string()),
+        }
+        .map_err(|e| warn!("I2PControl disabled: {}", e))
+        .ok();
+        let i2pcontrol = i2pcontrol_password.as_ref().and_then(|password| {
+            I2pControlClient::new(
+                format!("https://127.0.0.1:{}/", settings.i2pcontrol_port),
+                password.clone(),
+            )
+            .map_err(|e| warn!("I2PControl client unavailable: {}", e))
+            .ok()
+            .map(Arc::new)
+        });
         Self {
+            daemon: Daemon::new(settings.mode),
             settings,
             process: Arc::new(Mutex::new(None)),
+            addressbook: Arc::new(addressbook),
+            i2pcontrol_password,
+            i2pcontrol,
+            stats: None,
+            contribution_args: Vec::new(),
         }
     }
+
+    /// Apply transit, floodfill and bandwidth settings from `[contribute]`
+    pub fn with_contribution(mut self, contribute: &ContributeSettings) -> Self {
+        self.contribution_args = crate::contribute::i2pd_args(contribute);
+        self
+    }
+
+    /// Publish router statistics into shared stats on every health check
+    pub fn with_stats(mut self, stats: Arc<Stats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    fn i2pcontrol(&self) -> Result<&I2pControlClient> {
+        self.i2pcontrol
+            .as_deref()
+            .ok_or_else(|| anyhow!("I2PControl client unavailable"))
+    }
+
+    /// Current router statistics via I2PControl
+    pub async fn router_stats(&self) -> Result<RouterStats> {
+        self.i2pcontrol()?.router_info().await
+    }
+
+    /// Graceful restart, restart or reseed through I2PControl
+    pub async fn router_command(&self, command: RouterCommand) -> Result<()> {
+        self.i2pcontrol()?.router_command(command).await?;
+        info!("I2P router accepted {:?}", command);
+        Ok(())
+    }
+
+    /// Published server tunnels and their `.b32.i2p` addresses (`None`
+    /// until i2pd has created the tunnel's keys)
+    pub fn server_tunnel_addresses(&self) -> Vec<(String, Option<String>)> {
+        self.settings
+            .server_tunnels
+            .iter()
+            .map(|t| {
+                let addr = tunnels::b32_for(Path::new(DATA_DIR), t).unwrap_or_else(|e| {
+                    warn!("Cannot read keys of I2P tunnel {}: {}", t.name, e);
+                    None
+                });
+                (t.name.clone(), addr)
+            })
+            .collect()
+    }
+
+    /// Names Chimera translates to `.b32.i2p` before connecting
+    pub fn addressbook(&self) -> Arc<AddressBook> {
+        self.addressbook.clone()
+    }
+
+    /// Client for i2pd's SAM v3 bridge
+    pub fn sam(&self) -> SamClient {
+        SamClient::new(
+            SocketAddr::from(([127, 0, 0, 1], self.settings.sam_port)),
+            SocketAddr::from(([127, 0, 0, 1], self.settings.sam_udp_port)),
+        )
+    }
+
+    /// SOCKS listener an external i2pd must answer on
+    fn probe(&self) -> Probe {
+        Probe::Socks(SocketAddr::from(([127, 0, 0, 1], self.settings.socks_port)))
+    }
 }
 
 #[async_trait]
@@ -30,6 +147,14 @@ impl ProtocolAdapter for I2pAdapter {
             return Ok(());
         }
 
+        if !self.daemon.should_spawn("I2P", &self.probe()).await? {
+            if !self.settings.server_tunnels.is_empty() || !self.contribution_args.is_empty() {
+                warn!("I2P is external; configure server tunnels and transit on its i2pd");
+            }
+            tokio::spawn(self.addressbook.clone().run_subscription_refresh());
+            return Ok(());
+        }
+
         if !Path::new(&self.settings.binary_path).exists() {
             warn!(
                 "I2PD binary not found at {}. Skipping I2P start.",
@@ -45,45 +170,96 @@ impl ProtocolAdapter for I2pAdapter {
                 "--httpproxy.port={}",
                 self.settings.http_proxy_port
             ))
-            .arg("--datadir=data/i2p");
+            .arg("--sam.enabled=true")
+            .arg(format!("--sam.port={}", self.settings.sam_port))
+            .arg(format!("--sam.portudp={}", self.settings.sam_udp_port))
+            .arg(format!(
+                "--i2pcontrol.enabled={}",
+                self.i2pcontrol_password.is_some()
+            ))
+            .arg(format!(
+                "--i2pcontrol.port={}",
+                self.settings.i2pcontrol_port
+            ))
+            .arg(format!("--datadir={}", DATA_DIR))
+            .args(&self.contribution_args);
 
-        let data_dir = Path::new("data/i2p");
+        let data_dir = Path::new(DATA_DIR);
         if !data_dir.exists() {
             info!("Creating I2P data directory: {:?}", data_dir);
             std::fs::create_dir_all(data_dir)?;
         }
 
+        // The I2PControl password stays off the command line
+        let conf = write_conf(data_dir, self.i2pcontrol_password.as_deref())?;
+        cmd.arg(format!("--conf={}", conf.display()));
+
+        // Server tunnels come only from chimera.toml
+        let tunconf = tunnels::prepare(data_dir, &self.settings.server_tunnels)?;
+        cmd.arg(format!("--tunconf={}", tunconf.display()))
+            .arg(format!(
+                "--tunnelsdir={}",
+                data_dir.join("tunnels.d").display()
+            ));
+
         cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
 
         let mut child = cmd.spawn()?;
 
         if let Some(stdout) = child.stdout.take() {
-             tokio::spawn(async move {
-                 use tokio::io::{AsyncBufReadExt, BufReader};
-                 let mut reader = BufReader::new(stdout).lines();
-                 while let Ok(Some(line)) = reader.next_line().await {
-                     info!("[I2PD] {}", line);
-                 }
-             });
+            tokio::spawn(async move {
+                use tokio::io::{AsyncBufReadExt, BufReader};
+                let mut reader = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = reader.next_line().await {
+                    info!("[I2PD] {}", line);
+                }
+            });
         }
         if let Some(stderr) = child.stderr.take() {
-             tokio::spawn(async move {
-                 use tokio::io::{AsyncBufReadExt, BufReader};
-                 let mut reader = BufReader::new(stderr).lines();
-                 while let Ok(Some(line)) = reader.next_line().await {
-                     log::warn!("[I2PD] {}", line);
-                 }
-             });
+            tokio::spawn(async move {
+                use tokio::io::{AsyncBufReadExt, BufReader};
+                let mut reader = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = reader.next_line().await {
+                    log::warn!("[I2PD] {}", line);
+                }
+            });
         }
 
         let mut proc_lock = self.process.lock().await;
         *proc_lock = Some(child);
 
         info!("I2PD started successfully.");
+
+        tokio::spawn(self.addressbook.clone().run_subscription_refresh());
+
+        // Report addresses once i2pd has written the tunnel keys
+        if !self.settings.server_tunnels.is_empty() {
+            let tunnels = self.settings.server_tunnels.clone();
+            tokio::spawn(async move {
+                let mut pending = tunnels;
+                for _ in 0..60 {
+                    pending.retain(|t| match tunnels::b32_for(Path::new(DATA_DIR), t) {
+                        Ok(Some(addr)) => {
+                            info!("I2P server tunnel '{}' published at {}", t.name, addr);
+                            false
+                        }
+                        _ => true,
+                    });
+                    if pending.is_empty() {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                for t in pending {
+                    warn!("I2P server tunnel '{}' has no keys yet", t.name);
+                }
+            });
+        }
         Ok(())
     }
 
     async fn stop(&self) -> Result<()> {
+        self.daemon.detach();
         let mut proc_lock = self.process.lock().await;
         if let Some(mut child) = proc_lock.take() {
             info!("Stopping I2PD...");
@@ -92,12 +268,105 @@ impl ProtocolAdapter for I2pAdapter {
         Ok(())
     }
 
-    fn get_proxy_addr(&self) -> String {
-        format!("127.0.0.1:{}", self.settings.socks_port)
+    fn endpoint(&self) -> Endpoint {
+        Endpoint::Socks5(SocketAddr::from(([127, 0, 0, 1], self.settings.socks_port)))
+    }
+
+    fn as_stream_transport(&self) -> Option<&dyn StreamTransport> {
+        Some(self)
     }
 
+    /// Healthy once the router reports no network error and has built its
+    /// own tunnels
     async fn is_healthy(&self) -> bool {
-        let proc_lock = self.process.lock().await;
-        proc_lock.is_some()
+        let attached = self.daemon.is_attached();
+        if !attached && self.process.lock().await.is_none() {
+            return false;
+        }
+        match self.router_stats().await {
+            Ok(router) => {
+                if let Some(stats) = &self.stats {
+                    stats.record_network("i2p", &router);
+                }
+                router.network_ok() && router.tunnels_ready()
+            }
+            Err(e) => {
+                debug!("I2PControl query failed: {}", e);
+                // An external i2pd may not run I2PControl
+                attached && self.probe().answers().await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StreamTransport for I2pAdapter {
+    async fn open(&self, host: &str, port: u16) -> Result<TransportStream> {
+        let proxy = SocketAddr::from(([127, 0, 0, 1], self.settings.socks_port));
+        Ok(crate::socks5::connect_through(proxy, host, port)
+            .await?
+            .into())
+    }
+}
+
+/// 128-bit hex password for the I2PControl of an i2pd Chimera starts
+fn random_password() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| anyhow!("no randomness for the I2PControl password: {}", e))?;
+    Ok(data_encoding::HEXLOWER.encode(&bytes))
+}
+
+/// Write i2pd.conf, readable only by its owner; returns its path
+fn write_conf(data_dir: &Path, i2pcontrol_password: Option<&str>) -> Result<PathBuf> {
+    let mut conf = String::from("# Generated by Chimera; edits are overwritten\n");
+    if let Some(password) = i2pcontrol_password {
+        if password.contains(['\n', '\r']) {
+            bail!("i2p.i2pcontrol_password must be a single line");
+        }
+        conf.push_str(&format!("\n[i2pcontrol]\npassword = {}\n", password));
+    }
+
+    let path = data_dir.join(I2PD_CONF);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // An i2pd.conf from before keeps its old mode otherwise
+        if path.exists() {
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(&path)?.write_all(conf.as_bytes())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conf_carries_password_privately() {
+        let dir = std::env::temp_dir().join(format!("chimera-i2pd-conf-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let password = random_password().unwrap();
+        assert_eq!(password.len(), 32);
+        assert_ne!(password, random_password().unwrap());
+
+        let path = write_conf(&dir, Some(&password)).unwrap();
+        let conf = std::fs::read_to_string(&path).unwrap();
+        assert!(conf.contains(&format!("[i2pcontrol]\npassword = {}\n", password)));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert!(write_conf(&dir, Some("a\n[http]")).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
@@ -0,0 +1,429 @@
+//! Chimera-side I2P address book.
+//!
+//! Human-readable `.i2p` names are translated to `.b32.i2p` before the
+//! upstream CONNECT so they work even when i2pd's own address book has never
+//! heard of them. Sources, highest precedence first:
+//!
+//! 1. petnames from `[i2p.addressbook.petnames]`
+//! 2. hosts.txt subscriptions (fetched through i2pd's HTTP proxy, cached on disk)
+//! 3. the jump service, asked on demand for names nobody else knows
+//!
+//! Jump lookups happen while a client waits for its CONNECT, so they time out
+//! quickly and names the service doesn't know are not asked for again for a
+//! while.
+
+use super::destination::b32_from_base64;
+use crate::config::I2pAddressBookSettings;
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// Jump lookups hold up a client's CONNECT
+const JUMP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Subscriptions are fetched in the background and may be large
+const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long a failed jump lookup is remembered
+const JUMP_MISS_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Where a name's address came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressSource {
+    Petname,
+    Subscription(String),
+    JumpService(String),
+}
+
+impl fmt::Display for AddressSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressSource::Petname => write!(f, "petname"),
+            AddressSource::Subscription(url) => write!(f, "subscription {}", url),
+            AddressSource::JumpService(url) => write!(f, "jump service {}", url),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressEntry {
+    /// `xxx.b32.i2p`
+    pub b32: String,
+    pub source: AddressSource,
+}
+
+/// Parse hosts.txt (`name=base64dest`, optionally followed by `#!` metadata)
+pub fn parse_hosts_txt(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|line| {
+            let entry = line.split("#!").next()?;
+            let (name, dest) = entry.split_once('=')?;
+            let name = name.trim().to_ascii_lowercase();
+            if !name.ends_with(".i2p") || name.ends_with(".b32.i2p") {
+                return None;
+            }
+            Some((name, dest.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Base64 destination carried by a jump service redirect
+/// (`...?i2paddresshelper=<dest>`)
+pub fn address_helper(location: &str) -> Option<String> {
+    let url = reqwest::Url::parse(location).ok()?;
+    url.query_pairs()
+        .find(|(k, _)| k == "i2paddresshelper")
+        .map(|(_, v)| v.into_owned())
+}
+
+/// A petname value may be a `.b32.i2p` address or a full destination
+fn petname_b32(value: &str) -> Result<String> {
+    let value = value.trim();
+    if value.ends_with(".b32.i2p") {
+        Ok(value.to_ascii_lowercase())
+    } else {
+        b32_from_base64(value)
+    }
+}
+
+pub struct AddressBook {
+    settings: I2pAddressBookSettings,
+    /// i2pd's HTTP proxy, through which subscriptions and the jump service are fetched
+    http_proxy: Option<String>,
+    /// Cache of subscription and jump entries; petnames are never cached
+    cache_path: PathBuf,
+    petnames: HashMap<String, String>,
+    entries: RwLock<HashMap<String, AddressEntry>>,
+    /// Names the jump service failed to resolve, and when
+    misses: RwLock<HashMap<String, Instant>>,
+}
+
+impl fmt::Debug for AddressBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AddressBook")
+            .field("petnames", &self.petnames.len())
+            .field("entries", &self.len())
+            .finish()
+    }
+}
+
+impl AddressBook {
+    /// Load petnames and the on-disk cache
+    pub fn new(settings: I2pAddressBookSettings, cache_path: impl Into<PathBuf>) -> Self {
+        let mut petnames = HashMap::new();
+        for (name, value) in &settings.petnames {
+            match petname_b32(value) {
+                Ok(b32) => {
+                    petnames.insert(name.to_ascii_lowercase(), b32);
+                }
+                Err(e) => warn!("Ignoring I2P petname {}: {}", name, e),
+            }
+        }
+
+        let book = Self {
+            settings,
+            http_proxy: None,
+            cache_path: cache_path.into(),
+            petnames,
+            entries: RwLock::new(HashMap::new()),
+            misses: RwLock::new(HashMap::new()),
+        };
+        book.load_cache();
+        book
+    }
+
+    /// Fetch `.i2p` URLs through this HTTP proxy (e.g. "http://127.0.0.1:4444")
+    pub fn with_http_proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.http_proxy = Some(proxy.into());
+        self
+    }
+
+    /// Number of names known besides petnames
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.petnames.is_empty() && self.len() == 0
+    }
+
+    /// Local lookup only (petnames, subscriptions, earlier jump results)
+    pub fn lookup(&self, name: &str) -> Option<AddressEntry> {
+        let name = name.to_ascii_lowercase();
+        if let Some(b32) = self.petnames.get(&name) {
+            return Some(AddressEntry {
+                b32: b32.clone(),
+                source: AddressSource::Petname,
+            });
+        }
+        self.entries.read().unwrap().get(&name).cloned()
+    }
+
+    /// Local lookup, falling back to the jump service if one is configured
+    /// and hasn't failed for this name recently
+    pub async fn resolve(&self, name: &str) -> Option<AddressEntry> {
+        if let Some(entry) = self.lookup(name) {
+            return Some(entry);
+        }
+        let name = name.to_ascii_lowercase();
+        if let Some(failed) = self.misses.read().unwrap().get(&name) {
+            if failed.elapsed() < JUMP_MISS_TTL {
+                return None;
+            }
+        }
+        match self.jump(&name).await {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug!("Jump service lookup for {} failed: {}", name, e);
+                let mut misses = self.misses.write().unwrap();
+                misses.retain(|_, failed| failed.elapsed() < JUMP_MISS_TTL);
+                misses.insert(name, Instant::now());
+                None
+            }
+        }
+    }
+
+    /// Ask the jump service for a name and remember the answer
+    pub async fn jump(&self, name: &str) -> Result<Option<AddressEntry>> {
+        let Some(jump) = &self.settings.jump_service else {
+            return Ok(None);
+        };
+        let name = name.to_ascii_lowercase();
+        let client = self.http_client(reqwest::redirect::Policy::none(), JUMP_TIMEOUT)?;
+        let response = client.get(format!("{}{}", jump, name)).send().await?;
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .ok_or_else(|| anyhow!("{} does not know {}", jump, name))?;
+        let dest = address_helper(location)
+            .ok_or_else(|| anyhow!("jump redirect without i2paddresshelper"))?;
+
+        let entry = AddressEntry {
+            b32: b32_from_base64(&dest)?,
+            source: AddressSource::JumpService(jump.clone()),
+        };
+        info!("Jump service resolved {} to {}", name, entry.b32);
+        self.entries.write().unwrap().insert(name, entry.clone());
+        self.save_cache();
+        Ok(Some(entry))
+    }
+
+    /// Merge a hosts.txt document; existing names are kept (first seen wins)
+    pub fn merge_hosts_txt(&self, source_url: &str, text: &str) -> usize {
+        let added = self.insert_hosts(source_url, text);
+        if added > 0 {
+            self.save_cache();
+        }
+        added
+    }
+
+    fn insert_hosts(&self, source_url: &str, text: &str) -> usize {
+        let mut entries = self.entries.write().unwrap();
+        let mut added = 0;
+        for (name, dest) in parse_hosts_txt(text) {
+            if entries.contains_key(&name) {
+                continue;
+            }
+            match b32_from_base64(&dest) {
+                Ok(b32) => {
+                    entries.insert(
+                        name,
+                        AddressEntry {
+                            b32,
+                            source: AddressSource::Subscription(source_url.to_string()),
+                        },
+                    );
+                    added += 1;
+                }
+                Err(e) => debug!("Skipping {} from {}: {}", name, source_url, e),
+            }
+        }
+        added
+    }
+
+    /// Fetch every subscription once
+    pub async fn refresh_subscriptions(&self) -> Result<usize> {
+        let client =
+            self.http_client(reqwest::redirect::Policy::limited(5), SUBSCRIPTION_TIMEOUT)?;
+        let mut added = 0;
+        for url in &self.settings.subscriptions {
+            let fetched = async {
+                let response = client.get(url).send().await?.error_for_status()?;
+                response.text().await
+            };
+            match fetched.await {
+                Ok(text) => added += self.merge_hosts_txt(url, &text),
+                Err(e) => warn!("I2P subscription {} failed: {}", url, e),
+            }
+        }
+        if added > 0 {
+            info!("I2P address book: {} new names", added);
+        }
+        Ok(added)
+    }
+
+    /// Refresh subscriptions forever on the configured interval
+    pub async fn run_subscription_refresh(self: std::sync::Arc<Self>) {
+        if self.settings.subscriptions.is_empty() {
+            return;
+        }
+        let interval = Duration::from_secs(self.settings.refresh_interval_secs.max(60));
+        loop {
+            if let Err(e) = self.refresh_subscriptions().await {
+                warn!("I2P address book refresh failed: {}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// `.i2p` sites are only reachable through i2pd's HTTP proxy. Every
+    /// scheme goes through it (https by CONNECT) so no lookup leaks to the
+    /// clearnet.
+    fn http_client(
+        &self,
+        redirect: reqwest::redirect::Policy,
+        timeout: Duration,
+    ) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .redirect(redirect)
+            .timeout(timeout);
+        if let Some(proxy) = &self.http_proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        Ok(builder.build()?)
+    }
+
+    fn load_cache(&self) {
+        let Ok(text) = std::fs::read_to_string(&self.cache_path) else {
+            return;
+        };
+        let mut entries = self.entries.write().unwrap();
+        for line in text.lines() {
+            // name=b32=source
+            let mut parts = line.splitn(3, '=');
+            let (Some(name), Some(b32), Some(source)) = (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            let source = match source.split_once(' ') {
+                Some(("jump", url)) => AddressSource::JumpService(url.to_string()),
+                Some(("subscription", url)) => AddressSource::Subscription(url.to_string()),
+                _ => continue,
+            };
+            entries.insert(
+                name.to_string(),
+                AddressEntry {
+                    b32: b32.to_string(),
+                    source,
+                },
+            );
+        }
+    }
+
+    fn save_cache(&self) {
+        let entries = self.entries.read().unwrap();
+        let mut names: Vec<_> = entries.iter().collect();
+        names.sort_by(|a, b| a.0.cmp(b.0));
+        let text: String = names
+            .into_iter()
+            .map(|(name, e)| {
+                let source = match &e.source {
+                    AddressSource::JumpService(url) => format!("jump {}", url),
+                    AddressSource::Subscription(url) => format!("subscription {}", url),
+                    AddressSource::Petname => return String::new(),
+                };
+                format!("{}={}={}\n", name, e.b32, source)
+            })
+            .collect();
+        if let Some(parent) = self.cache_path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Err(e) = std::fs::write(&self.cache_path, text) {
+            warn!("Cannot write I2P address book cache: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_encoding::BASE64;
+
+    /// I2P-base64 destination with a NULL certificate
+    fn dest(fill: u8) -> String {
+        let mut bytes = vec![fill; 384];
+        bytes.extend_from_slice(&[0, 0, 0]);
+        BASE64.encode(&bytes).replace('+', "-").replace('/', "~")
+    }
+
+    fn book(petnames: &[(&str, &str)]) -> AddressBook {
+        let settings = I2pAddressBookSettings {
+            petnames: petnames
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+        let cache = std::env::temp_dir().join(format!(
+            "chimera-hosts-{}-{}.txt",
+            std::process::id(),
+            petnames.len()
+        ));
+        let _ = std::fs::remove_file(&cache);
+        AddressBook::new(settings, cache)
+    }
+
+    #[test]
+    fn test_parse_hosts_txt() {
+        let text = format!(
+            "# comment\nstats.i2p={}\nzzz.i2p={}#!sig=abc\nbad line\nx.b32.i2p={}\n",
+            dest(1),
+            dest(2),
+            dest(3)
+        );
+        let hosts = parse_hosts_txt(&text);
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[0], ("stats.i2p".to_string(), dest(1)));
+        assert_eq!(hosts[1].1, dest(2));
+    }
+
+    #[test]
+    fn test_petnames_take_precedence() {
+        let pet = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa.b32.i2p";
+        let book = book(&[("Stats.i2p", pet)]);
+        let added = book.merge_hosts_txt(
+            "http://sub/hosts.txt",
+            &format!("stats.i2p={}\nzzz.i2p={}\n", dest(1), dest(2)),
+        );
+        assert_eq!(added, 2);
+
+        let stats = book.lookup("stats.i2p").unwrap();
+        assert_eq!(stats.b32, pet);
+        assert_eq!(stats.source, AddressSource::Petname);
+
+        let zzz = book.lookup("ZZZ.i2p").unwrap();
+        assert_eq!(zzz.b32, b32_from_base64(&dest(2)).unwrap());
+        assert_eq!(
+            zzz.source,
+            AddressSource::Subscription("http://sub/hosts.txt".to_string())
+        );
+        assert!(book.lookup("unknown.i2p").is_none());
+
+        // The cache survives a restart
+        let reloaded = AddressBook::new(Default::default(), book.cache_path.clone());
+        assert_eq!(reloaded.lookup("zzz.i2p"), Some(zzz));
+        std::fs::remove_file(&book.cache_path).unwrap();
+    }
+
+    #[test]
+    fn test_address_helper() {
+        let loc = format!("http://stats.i2p/?i2paddresshelper={}", dest(9));
+        assert_eq!(address_helper(&loc), Some(dest(9)));
+        assert_eq!(address_helper("http://stats.i2p/"), None);
+    }
+}
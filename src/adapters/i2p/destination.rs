@@ -0,0 +1,103 @@
+//! I2P destination encoding helpers.
+//!
+//! Destinations are written in I2P's base64 alphabet (`-` and `~` instead of
+//! `+` and `/`). A `.b32.i2p` address is the lowercase, unpadded base32 of the
+//! SHA-256 of the binary destination.
+
+use anyhow::{anyhow, bail, Result};
+use data_encoding::{BASE32_NOPAD, BASE64};
+use sha2::{Digest, Sha256};
+
+/// Public key (256) + signing key (128) + certificate type (1) + length (2)
+const DESTINATION_MIN_LEN: usize = 387;
+
+/// Decode I2P base64 (`-`/`~` alphabet)
+pub fn decode_base64(encoded: &str) -> Result<Vec<u8>> {
+    let standard: String = encoded
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '-' => '+',
+            '~' => '/',
+            c => c,
+        })
+        .collect();
+    BASE64
+        .decode(standard.as_bytes())
+        .map_err(|e| anyhow!("invalid I2P base64: {}", e))
+}
+
+/// Length of the destination at the start of `bytes` (a destination or a
+/// private key file, which begins with one)
+pub fn destination_len(bytes: &[u8]) -> Result<usize> {
+    if bytes.len() < DESTINATION_MIN_LEN {
+        bail!("I2P destination too short ({} bytes)", bytes.len());
+    }
+    let cert_len = u16::from_be_bytes([bytes[385], bytes[386]]) as usize;
+    let len = DESTINATION_MIN_LEN + cert_len;
+    if bytes.len() < len {
+        bail!("I2P destination certificate truncated");
+    }
+    Ok(len)
+}
+
+/// `.b32.i2p` address of a binary destination or private key file
+pub fn b32_address(bytes: &[u8]) -> Result<String> {
+    let len = destination_len(bytes)?;
+    let hash = Sha256::digest(&bytes[..len]);
+    Ok(format!(
+        "{}.b32.i2p",
+        BASE32_NOPAD.encode(&hash).to_ascii_lowercase()
+    ))
+}
+
+/// `.b32.i2p` address of an I2P-base64 destination
+pub fn b32_from_base64(destination: &str) -> Result<String> {
+    b32_address(&decode_base64(destination)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Destination with a KEY certificate carrying 4 bytes of payload
+    fn sample_destination() -> Vec<u8> {
+        let mut dest = vec![0xABu8; 384];
+        dest.push(5); // KEY certificate
+        dest.extend_from_slice(&4u16.to_be_bytes());
+        dest.extend_from_slice(&[0, 7, 0, 4]);
+        dest
+    }
+
+    #[test]
+    fn test_b32_ignores_private_key_material() {
+        let dest = sample_destination();
+        let mut keys = dest.clone();
+        keys.extend_from_slice(&[0x11; 288]);
+
+        let addr = b32_address(&dest).unwrap();
+        assert!(addr.ends_with(".b32.i2p"));
+        assert_eq!(addr.len(), 52 + ".b32.i2p".len());
+        assert_eq!(b32_address(&keys).unwrap(), addr);
+    }
+
+    #[test]
+    fn test_i2p_base64_alphabet() {
+        let dest = sample_destination();
+        let encoded: String = BASE64
+            .encode(&dest)
+            .chars()
+            .map(|c| match c {
+                '+' => '-',
+                '/' => '~',
+                c => c,
+            })
+            .collect();
+        assert_eq!(decode_base64(&encoded).unwrap(), dest);
+        assert_eq!(
+            b32_from_base64(&encoded).unwrap(),
+            b32_address(&dest).unwrap()
+        );
+        assert!(b32_address(&dest[..100]).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! I2PControl JSON-RPC client (https://geti2p.net/en/docs/api/i2pcontrol).
+//!
+//! i2pd serves I2PControl over HTTPS with a self-signed certificate on
+//! loopback. Requests carry a token from `Authenticate`; an expired token is
+//! renewed transparently.
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+const API_VERSION: u64 = 1;
+
+/// i2p.router.net.status codes from 8 upwards are ERROR_* states
+const NET_STATUS_FIRST_ERROR: i64 = 8;
+
+// Token missing, unknown or expired
+const TOKEN_ERRORS: [i64; 3] = [-32002, -32003, -32004];
+
+/// Router statistics from `RouterInfo`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RouterStats {
+    pub version: String,
+    pub status: String,
+    pub uptime_ms: u64,
+    /// i2p.router.net.status (0 = OK, 1 = testing, 2 = firewalled, ...)
+    pub net_status: i64,
+    pub participating_tunnels: u64,
+    pub known_peers: u64,
+    pub active_peers: u64,
+    /// Bytes per second over the last second
+    pub inbound_bps: f64,
+    pub outbound_bps: f64,
+}
+
+impl RouterStats {
+    /// The router reports no network error
+    pub fn network_ok(&self) -> bool {
+        self.net_status < NET_STATUS_FIRST_ERROR
+    }
+
+    /// The router has built its own tunnels. i2pd reports i2p.router.status
+    /// "1" once its client destination has inbound and outbound tunnels and
+    /// "0" before; other routers report a free-form status.
+    pub fn tunnels_ready(&self) -> bool {
+        self.status != "0"
+    }
+}
+
+/// Router management actions (`RouterManager`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouterCommand {
+    /// Restart once participating tunnels have expired
+    RestartGraceful,
+    Restart,
+    Reseed,
+}
+
+impl RouterCommand {
+    fn key(self) -> &'static str {
+        match self {
+            RouterCommand::RestartGraceful => "RestartGraceful",
+            RouterCommand::Restart => "Restart",
+            RouterCommand::Reseed => "Reseed",
+        }
+    }
+}
+
+pub struct I2pControlClient {
+    url: String,
+    password: String,
+    http: reqwest::Client,
+    token: Mutex<Option<String>>,
+}
+
+impl I2pControlClient {
+    /// `url` is the JSON-RPC endpoint, e.g. "https://127.0.0.1:7650/"
+    pub fn new(url: impl Into<String>, password: impl Into<String>) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            // i2pd generates a self-signed certificate; the endpoint is loopback only
+            .danger_accept_invalid_certs(true)
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(Self {
+            url: url.into(),
+            password: password.into(),
+            http,
+            token: Mutex::new(None),
+        })
+    }
+
+    async fn rpc(&self, method: &str, params: Value) -> Result<Value> {
+        let request = json!({
+            "id": 1,
+            "method": method,
+            "params": params,
+            "jsonrpc": "2.0",
+        });
+        let response: Value = self
+            .http
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if let Some(error) = response.get("error") {
+            let code = error.get("code").and_then(Value::as_i64).unwrap_or(0);
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error");
+            return Err(RpcError {
+                code,
+                message: message.to_string(),
+            }
+            .into());
+        }
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| anyhow!("I2PControl {} returned no result", method))
+    }
+
+    async fn authenticate(&self) -> Result<String> {
+        let result = self
+            .rpc(
+                "Authenticate",
+                json!({ "API": API_VERSION, "Password": self.password }),
+            )
+            .await?;
+        let token = result
+            .get("Token")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("I2PControl Authenticate returned no token"))?
+            .to_string();
+        *self.token.lock().await = Some(token.clone());
+        Ok(token)
+    }
+
+    /// Call an authenticated method, renewing the token once if needed
+    pub async fn call(&self, method: &str, mut params: Map<String, Value>) -> Result<Value> {
+        let cached = self.token.lock().await.clone();
+        let token = match cached {
+            Some(token) => token,
+            None => self.authenticate().await?,
+        };
+        params.insert("Token".to_string(), Value::String(token));
+
+        match self.rpc(method, Value::Object(params.clone())).await {
+            Err(e) if is_token_error(&e) => {
+                let token = self.authenticate().await?;
+                params.insert("Token".to_string(), Value::String(token));
+                self.rpc(method, Value::Object(params)).await
+            }
+            other => other,
+        }
+    }
+
+    pub async fn router_info(&self) -> Result<RouterStats> {
+        let keys = [
+            "i2p.router.version",
+            "i2p.router.status",
+            "i2p.router.uptime",
+            "i2p.router.net.status",
+            "i2p.router.net.tunnels.participating",
+            "i2p.router.netdb.knownpeers",
+            "i2p.router.netdb.activepeers",
+            "i2p.router.net.bw.inbound.1s",
+            "i2p.router.net.bw.outbound.1s",
+        ];
+        let params = keys.iter().map(|k| (k.to_string(), Value::Null)).collect();
+        let result = self.call("RouterInfo", params).await?;
+
+        let text = |k: &str| match result.get(k) {
+            Some(Value::String(s)) => s.clone(),
+            Some(v) if !v.is_null() => v.to_string(),
+            _ => String::new(),
+        };
+        let number = |k: &str| result.get(k).and_then(Value::as_f64).unwrap_or(0.0);
+
+        Ok(RouterStats {
+            version: text("i2p.router.version"),
+            status: text("i2p.router.status"),
+            uptime_ms: number("i2p.router.uptime") as u64,
+            net_status: number("i2p.router.net.status") as i64,
+            participating_tunnels: number("i2p.router.net.tunnels.participating") as u64,
+            known_peers: number("i2p.router.netdb.knownpeers") as u64,
+            active_peers: number("i2p.router.netdb.activepeers") as u64,
+            inbound_bps: number("i2p.router.net.bw.inbound.1s"),
+            outbound_bps: number("i2p.router.net.bw.outbound.1s"),
+        })
+    }
+
+    pub async fn router_command(&self, command: RouterCommand) -> Result<()> {
+        let mut params = Map::new();
+        params.insert(command.key().to_string(), Value::Null);
+        let result = self.call("RouterManager", params).await?;
+        if result.get(command.key()).is_none() {
+            bail!("router did not acknowledge {}", command.key());
+        }
+        Ok(())
+    }
+}
+
+/// JSON-RPC error returned by the router
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "I2PControl error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+fn is_token_error(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<RpcError>()
+        .is_some_and(|e| TOKEN_ERRORS.contains(&e.code))
+}
@@ -0,0 +1,392 @@
+//! SAM v3 bridge client (https://geti2p.net/en/docs/api/samv3).
+//!
+//! Every SAM exchange starts on a fresh TCP connection with `HELLO`. A
+//! session lives as long as its control connection stays open; stream
+//! sockets opened with `STREAM CONNECT`/`STREAM ACCEPT` turn into raw data
+//! channels once SAM answers `RESULT=OK`. Datagram sessions receive through a
+//! local UDP socket SAM forwards to and send through SAM's UDP port.
+
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::Mutex;
+
+const SAM_VERSION_MIN: &str = "3.1";
+const SAM_VERSION_MAX: &str = "3.3";
+
+/// Ed25519, the signature type i2pd and Java I2P recommend
+pub const SIGNATURE_TYPE_ED25519: u8 = 7;
+
+/// One parsed SAM reply line, e.g. `STREAM STATUS RESULT=OK`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamReply {
+    pub topic: String,
+    pub kind: String,
+    pub args: HashMap<String, String>,
+}
+
+impl SamReply {
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut tokens = tokenize(line.trim_end()).into_iter();
+        let topic = tokens.next().ok_or_else(|| anyhow!("empty SAM reply"))?;
+        let kind = tokens.next().unwrap_or_default();
+        let args = tokens
+            .map(|t| match t.split_once('=') {
+                Some((k, v)) => (k.to_string(), v.to_string()),
+                None => (t, String::new()),
+            })
+            .collect();
+        Ok(Self { topic, kind, args })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.args.get(key).map(String::as_str)
+    }
+
+    /// Fail unless `RESULT=OK`, carrying SAM's MESSAGE if it sent one
+    pub fn ensure_ok(self) -> Result<Self> {
+        match self.get("RESULT") {
+            Some("OK") | None => Ok(self),
+            Some(result) => Err(anyhow!(
+                "SAM {} {} failed: {}{}",
+                self.topic,
+                self.kind,
+                result,
+                self.get("MESSAGE")
+                    .map(|m| format!(" ({})", m))
+                    .unwrap_or_default()
+            )),
+        }
+    }
+}
+
+/// Split on spaces, keeping `KEY="quoted value"` together
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ' ' if !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// A HELLO'd connection to the bridge. Lines are read byte by byte so
+/// nothing past the reply is buffered once the socket carries stream data.
+struct SamConnection {
+    stream: TcpStream,
+}
+
+impl SamConnection {
+    async fn open(addr: SocketAddr) -> Result<Self> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .map_err(|e| anyhow!("SAM bridge {} unreachable: {}", addr, e))?;
+        let mut conn = Self { stream };
+        let reply = conn
+            .request(&format!(
+                "HELLO VERSION MIN={} MAX={}",
+                SAM_VERSION_MIN, SAM_VERSION_MAX
+            ))
+            .await?;
+        if reply.topic != "HELLO" {
+            bail!("unexpected SAM greeting: {} {}", reply.topic, reply.kind);
+        }
+        Ok(conn)
+    }
+
+    async fn request(&mut self, command: &str) -> Result<SamReply> {
+        self.stream
+            .write_all(format!("{}\n", command).as_bytes())
+            .await?;
+        self.read_reply().await
+    }
+
+    async fn read_reply(&mut self) -> Result<SamReply> {
+        SamReply::parse(&self.read_line().await?)?.ensure_ok()
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        let mut line = Vec::new();
+        loop {
+            match self.stream.read_u8().await? {
+                b'\n' => break,
+                b => line.push(b),
+            }
+        }
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+/// Keys from `DEST GENERATE`, both I2P-base64
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DestinationKeys {
+    pub public: String,
+    pub private: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStyle {
+    Stream,
+    Datagram,
+}
+
+/// Entry point for SAM operations against one bridge
+#[derive(Debug, Clone)]
+pub struct SamClient {
+    addr: SocketAddr,
+    udp_addr: SocketAddr,
+}
+
+impl SamClient {
+    /// `addr` is the SAM TCP port, `udp_addr` the port SAM accepts
+    /// datagrams to send on (7656 and 7655 by default)
+    pub fn new(addr: SocketAddr, udp_addr: SocketAddr) -> Self {
+        Self { addr, udp_addr }
+    }
+
+    /// Whether the bridge answers HELLO
+    pub async fn probe(&self) -> bool {
+        SamConnection::open(self.addr).await.is_ok()
+    }
+
+    /// Resolve a name (`example.i2p`, `xxx.b32.i2p`) to a full destination
+    pub async fn naming_lookup(&self, name: &str) -> Result<String> {
+        let mut conn = SamConnection::open(self.addr).await?;
+        lookup(&mut conn, name).await
+    }
+
+    /// Create a fresh destination keypair
+    pub async fn generate_destination(&self, signature_type: u8) -> Result<DestinationKeys> {
+        let mut conn = SamConnection::open(self.addr).await?;
+        let reply = conn
+            .request(&format!("DEST GENERATE SIGNATURE_TYPE={}", signature_type))
+            .await?;
+        match (reply.get("PUB"), reply.get("PRIV")) {
+            (Some(public), Some(private)) => Ok(DestinationKeys {
+                public: public.to_string(),
+                private: private.to_string(),
+            }),
+            _ => bail!("DEST REPLY without PUB/PRIV"),
+        }
+    }
+
+    /// Stream session. `private_keys` = `None` for a transient destination.
+    pub async fn create_stream_session(
+        &self,
+        id: &str,
+        private_keys: Option<&str>,
+    ) -> Result<SamSession> {
+        self.create_session(id, SessionStyle::Stream, private_keys, "")
+            .await
+    }
+
+    /// Repliable datagram session; incoming datagrams arrive on a local UDP
+    /// socket SAM forwards to
+    pub async fn create_datagram_session(
+        &self,
+        id: &str,
+        private_keys: Option<&str>,
+    ) -> Result<SamSession> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let forward = socket.local_addr()?;
+        let extra = format!(" PORT={} HOST={}", forward.port(), forward.ip());
+        let mut session = self
+            .create_session(id, SessionStyle::Datagram, private_keys, &extra)
+            .await?;
+        session.udp = Some(socket);
+        Ok(session)
+    }
+
+    async fn create_session(
+        &self,
+        id: &str,
+        style: SessionStyle,
+        private_keys: Option<&str>,
+        extra: &str,
+    ) -> Result<SamSession> {
+        let mut control = SamConnection::open(self.addr).await?;
+        let style_name = match style {
+            SessionStyle::Stream => "STREAM",
+            SessionStyle::Datagram => "DATAGRAM",
+        };
+        let destination = private_keys.unwrap_or("TRANSIENT");
+        let reply = control
+            .request(&format!(
+                "SESSION CREATE STYLE={} ID={} DESTINATION={} SIGNATURE_TYPE={}{}",
+                style_name, id, destination, SIGNATURE_TYPE_ED25519, extra
+            ))
+            .await?;
+        let private_keys = reply
+            .get("DESTINATION")
+            .ok_or_else(|| anyhow!("SESSION STATUS without DESTINATION"))?
+            .to_string();
+
+        // NAMING LOOKUP NAME=ME on the session socket yields our public destination
+        let destination = lookup(&mut control, "ME").await?;
+
+        Ok(SamSession {
+            client: self.clone(),
+            id: id.to_string(),
+            style,
+            private_keys,
+            destination,
+            control: Mutex::new(control),
+            udp: None,
+        })
+    }
+}
+
+async fn lookup(conn: &mut SamConnection, name: &str) -> Result<String> {
+    let reply = conn
+        .request(&format!("NAMING LOOKUP NAME={}", name))
+        .await?;
+    reply
+        .get("VALUE")
+        .map(|v| v.to_string())
+        .ok_or_else(|| anyhow!("NAMING REPLY for {} without VALUE", name))
+}
+
+/// A SAM session; closed when dropped
+pub struct SamSession {
+    client: SamClient,
+    id: String,
+    style: SessionStyle,
+    private_keys: String,
+    destination: String,
+    control: Mutex<SamConnection>,
+    udp: Option<UdpSocket>,
+}
+
+impl SamSession {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn style(&self) -> SessionStyle {
+        self.style
+    }
+
+    /// Public destination peers connect or reply to
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// Private keys, for recreating the same destination later
+    pub fn private_keys(&self) -> &str {
+        &self.private_keys
+    }
+
+    /// Resolve a name through this session's control socket
+    pub async fn lookup(&self, name: &str) -> Result<String> {
+        lookup(&mut *self.control.lock().await, name).await
+    }
+
+    /// Open a stream to a destination or name; the returned socket carries
+    /// the peer's data directly
+    pub async fn connect(&self, destination: &str) -> Result<TcpStream> {
+        self.require(SessionStyle::Stream)?;
+        let mut conn = SamConnection::open(self.client.addr).await?;
+        conn.request(&format!(
+            "STREAM CONNECT ID={} DESTINATION={} SILENT=false",
+            self.id, destination
+        ))
+        .await?;
+        Ok(conn.stream)
+    }
+
+    /// Wait for one incoming stream; returns the peer destination and socket
+    pub async fn accept(&self) -> Result<(String, TcpStream)> {
+        self.require(SessionStyle::Stream)?;
+        let mut conn = SamConnection::open(self.client.addr).await?;
+        conn.request(&format!("STREAM ACCEPT ID={} SILENT=false", self.id))
+            .await?;
+        // First line once a peer arrives: "<destination> FROM_PORT=n TO_PORT=n"
+        let header = conn.read_line().await?;
+        let peer = header
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| anyhow!("STREAM ACCEPT without peer destination"))?
+            .to_string();
+        Ok((peer, conn.stream))
+    }
+
+    /// Send one repliable datagram
+    pub async fn send_datagram(&self, destination: &str, payload: &[u8]) -> Result<()> {
+        let socket = self.udp()?;
+        let mut packet = format!("3.0 {} {}\n", self.id, destination).into_bytes();
+        packet.extend_from_slice(payload);
+        socket.send_to(&packet, self.client.udp_addr).await?;
+        Ok(())
+    }
+
+    /// Receive one datagram; returns the sender destination and payload
+    pub async fn recv_datagram(&self) -> Result<(String, Vec<u8>)> {
+        let socket = self.udp()?;
+        let mut buf = vec![0u8; 65536];
+        let (n, _) = socket.recv_from(&mut buf).await?;
+        let newline = buf[..n]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| anyhow!("forwarded datagram without header"))?;
+        let header = String::from_utf8_lossy(&buf[..newline]);
+        let sender = header
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| anyhow!("forwarded datagram without sender"))?
+            .to_string();
+        Ok((sender, buf[newline + 1..n].to_vec()))
+    }
+
+    fn require(&self, style: SessionStyle) -> Result<()> {
+        if self.style != style {
+            bail!("session {} is {:?}, not {:?}", self.id, self.style, style);
+        }
+        Ok(())
+    }
+
+    fn udp(&self) -> Result<&UdpSocket> {
+        self.require(SessionStyle::Datagram)?;
+        self.udp
+            .as_ref()
+            .ok_or_else(|| anyhow!("datagram session without UDP socket"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reply() {
+        let reply = SamReply::parse("HELLO REPLY RESULT=OK VERSION=3.3\n").unwrap();
+        assert_eq!(reply.topic, "HELLO");
+        assert_eq!(reply.kind, "REPLY");
+        assert_eq!(reply.get("VERSION"), Some("3.3"));
+        assert!(reply.ensure_ok().is_ok());
+    }
+
+    #[test]
+    fn test_parse_quoted_error() {
+        let reply =
+            SamReply::parse(r#"STREAM STATUS RESULT=CANT_REACH_PEER MESSAGE="peer not found""#)
+                .unwrap();
+        assert_eq!(reply.get("MESSAGE"), Some("peer not found"));
+        let err = reply.ensure_ok().unwrap_err().to_string();
+        assert!(err.contains("CANT_REACH_PEER"));
+        assert!(err.contains("peer not found"));
+    }
+}
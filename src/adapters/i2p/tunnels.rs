@@ -0,0 +1,117 @@
+//! i2pd server tunnels for services declared in `[[i2p.server_tunnels]]`.
+//!
+//! Chimera owns `tunnels.conf` inside the I2P data directory and rewrites it
+//! on every start. Destination keys live under `keys/`; i2pd creates each key
+//! file the first time its tunnel starts and reuses it afterwards, so the
+//! `.b32.i2p` address stays the same across restarts.
+
+use super::destination::b32_address;
+use crate::config::{I2pServerTunnel, I2pTunnelKind};
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+pub const TUNNELS_CONF: &str = "tunnels.conf";
+pub const KEYS_DIR: &str = "keys";
+
+/// Key file of a tunnel, relative to the data directory unless absolute
+pub fn key_file(tunnel: &I2pServerTunnel) -> String {
+    tunnel
+        .key_file
+        .clone()
+        .unwrap_or_else(|| format!("{}/{}.dat", KEYS_DIR, tunnel.name))
+}
+
+/// Render tunnels.conf
+pub fn render(tunnels: &[I2pServerTunnel]) -> Result<String> {
+    let mut names = HashSet::new();
+    let mut conf =
+        String::from("# Generated by Chimera from [[i2p.server_tunnels]]; edits are overwritten\n");
+    for tunnel in tunnels {
+        if tunnel.name.is_empty() || tunnel.name.contains(['[', ']', '\n']) {
+            bail!("invalid I2P tunnel name {:?}", tunnel.name);
+        }
+        if !names.insert(&tunnel.name) {
+            bail!("duplicate I2P tunnel name {:?}", tunnel.name);
+        }
+        let (host, port) = tunnel
+            .target
+            .rsplit_once(':')
+            .and_then(|(h, p)| Some((h, p.parse::<u16>().ok()?)))
+            .ok_or_else(|| {
+                anyhow!(
+                    "I2P tunnel {}: target must be host:port, got {:?}",
+                    tunnel.name,
+                    tunnel.target
+                )
+            })?;
+        let kind = match tunnel.kind {
+            I2pTunnelKind::Server => "server",
+            I2pTunnelKind::Http => "http",
+        };
+
+        writeln!(conf, "\n[{}]", tunnel.name)?;
+        writeln!(conf, "type = {}", kind)?;
+        writeln!(conf, "host = {}", host)?;
+        writeln!(conf, "port = {}", port)?;
+        writeln!(conf, "keys = {}", key_file(tunnel))?;
+        writeln!(conf, "inbound.length = {}", tunnel.inbound_length)?;
+        writeln!(conf, "outbound.length = {}", tunnel.outbound_length)?;
+    }
+    Ok(conf)
+}
+
+/// Published address of a tunnel, once i2pd has created its keys
+pub fn b32_for(data_dir: &Path, tunnel: &I2pServerTunnel) -> Result<Option<String>> {
+    let path = data_dir.join(key_file(tunnel));
+    if !path.exists() {
+        return Ok(None);
+    }
+    b32_address(&std::fs::read(&path)?).map(Some)
+}
+
+/// Write tunnels.conf and the keys directory; returns the tunnels.conf path
+pub fn prepare(data_dir: &Path, tunnels: &[I2pServerTunnel]) -> Result<PathBuf> {
+    let keys = data_dir.join(KEYS_DIR);
+    std::fs::create_dir_all(&keys)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&keys, std::fs::Permissions::from_mode(0o700))?;
+    }
+    let path = data_dir.join(TUNNELS_CONF);
+    std::fs::write(&path, render(tunnels)?)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tunnel(name: &str, target: &str) -> I2pServerTunnel {
+        I2pServerTunnel {
+            name: name.to_string(),
+            target: target.to_string(),
+            kind: I2pTunnelKind::Http,
+            inbound_length: 2,
+            outbound_length: 3,
+            key_file: None,
+        }
+    }
+
+    #[test]
+    fn test_render_tunnels_conf() {
+        let conf = render(&[tunnel("site", "127.0.0.1:8080")]).unwrap();
+        assert!(conf.contains("[site]\ntype = http\nhost = 127.0.0.1\nport = 8080\n"));
+        assert!(conf.contains("keys = keys/site.dat\n"));
+        assert!(conf.contains("inbound.length = 2\noutbound.length = 3\n"));
+    }
+
+    #[test]
+    fn test_render_rejects_bad_entries() {
+        assert!(render(&[tunnel("site", "localhost")]).is_err());
+        assert!(render(&[tunnel("a", "127.0.0.1:1"), tunnel("a", "127.0.0.1:2")]).is_err());
+        assert!(render(&[tunnel("x]\n[y", "127.0.0.1:1")]).is_err());
+    }
+}
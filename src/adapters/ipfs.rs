@@ -1,27 +1,113 @@
-use super::ProtocolAdapter;
+use super::{endpoint, ContentStore, Daemon, Endpoint, HostPort, Probe, ProtocolAdapter};
 use crate::config::IpfsSettings;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use async_trait::async_trait;
 use log::{info, warn};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::Stdio;
 use std::sync::Arc;
+use tokio::process::{Child, Command};
 use tokio::sync::Mutex;
 
 /// IPFS adapter using Kubo RPC API via HTTP
 ///
 /// Connects to a local Kubo node's API (default: http://127.0.0.1:5001)
-/// and provides gateway access for `ipfs://` and `ipns://` URLs.
+/// and provides gateway access for `ipfs://` and `ipns://` URLs. In managed
+/// mode the node is an `ipfs daemon` child on `ipfs.repo_path`.
 pub struct IpfsAdapter {
     settings: IpfsSettings,
     connected: Arc<Mutex<bool>>,
+    process: Arc<Mutex<Option<Child>>>,
+    daemon: Daemon,
 }
 
 impl IpfsAdapter {
     pub fn new(settings: IpfsSettings) -> Self {
         Self {
+            daemon: Daemon::new(settings.mode),
             settings,
             connected: Arc::new(Mutex::new(false)),
+            process: Arc::new(Mutex::new(None)),
         }
     }
+
+    /// Kubo RPC API base URL
+    pub fn api_url(&self) -> &str {
+        &self.settings.api_url
+    }
+
+    /// `ipfs` on the managed repository
+    fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.settings.binary_path);
+        cmd.env("IPFS_PATH", &self.settings.repo_path);
+        cmd
+    }
+
+    async fn run(&self, args: &[&str]) -> Result<()> {
+        let output = self
+            .command()
+            .args(args)
+            .output()
+            .await
+            .with_context(|| format!("cannot run {}", self.settings.binary_path))?;
+        if !output.status.success() {
+            bail!(
+                "ipfs {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Run `ipfs daemon`, creating the repository on first start and
+    /// pointing its API and gateway at the configured addresses
+    async fn spawn_daemon(&self, api: SocketAddr) -> Result<()> {
+        if !Path::new(&self.settings.repo_path).join("config").exists() {
+            info!("Creating IPFS repository at {}", self.settings.repo_path);
+            self.run(&["init"]).await?;
+        }
+        let gateway = SocketAddr::new(api.ip(), self.settings.gateway_port);
+        self.run(&["config", "Addresses.API", &multiaddr(api)])
+            .await?;
+        self.run(&["config", "Addresses.Gateway", &multiaddr(gateway)])
+            .await?;
+
+        info!("Starting IPFS daemon...");
+        let mut child = self
+            .command()
+            .arg("daemon")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(async move {
+                use tokio::io::{AsyncBufReadExt, BufReader};
+                let mut reader = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = reader.next_line().await {
+                    info!("[IPFS] {}", line);
+                }
+            });
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(async move {
+                use tokio::io::{AsyncBufReadExt, BufReader};
+                let mut reader = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = reader.next_line().await {
+                    log::warn!("[IPFS] {}", line);
+                }
+            });
+        }
+
+        *self.process.lock().await = Some(child);
+        *self.connected.lock().await = true;
+        info!("IPFS daemon started (API {}, gateway {})", api, gateway);
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -33,6 +119,15 @@ impl ProtocolAdapter for IpfsAdapter {
 
         info!("Initializing IPFS adapter...");
 
+        let api = endpoint::url_addr(&self.settings.api_url)
+            .ok_or_else(|| anyhow!("ipfs.api_url {} has no address", self.settings.api_url))?
+            .resolve()
+            .await
+            .with_context(|| format!("resolving ipfs.api_url {}", self.settings.api_url))?;
+        if self.daemon.should_spawn("IPFS", &Probe::Tcp(api)).await? {
+            return self.spawn_daemon(api).await;
+        }
+
         // Test connection to Kubo API
         let client = reqwest::Client::new();
         let id_url = format!("{}/api/v0/id", self.settings.api_url);
@@ -73,6 +168,11 @@ impl ProtocolAdapter for IpfsAdapter {
     }
 
     async fn stop(&self) -> Result<()> {
+        self.daemon.detach();
+        if let Some(mut child) = self.process.lock().await.take() {
+            info!("Stopping IPFS daemon...");
+            child.kill().await?;
+        }
         let mut connected = self.connected.lock().await;
         if *connected {
             *connected = false;
@@ -81,9 +181,18 @@ impl ProtocolAdapter for IpfsAdapter {
         Ok(())
     }
 
-    fn get_proxy_addr(&self) -> String {
-        // IPFS uses HTTP gateway, not SOCKS5
-        format!("127.0.0.1:{}", self.settings.gateway_port)
+    fn endpoint(&self) -> Endpoint {
+        // Kubo serves its gateway on the API's host
+        match endpoint::url_addr(&self.settings.api_url) {
+            Some(api) => {
+                Endpoint::HttpGateway(HostPort::new(&api.host, self.settings.gateway_port))
+            }
+            None => Endpoint::None,
+        }
+    }
+
+    fn as_content_store(&self) -> Option<&dyn ContentStore> {
+        Some(self)
     }
 
     async fn is_healthy(&self) -> bool {
@@ -91,3 +200,97 @@ impl ProtocolAdapter for IpfsAdapter {
         *connected
     }
 }
+
+/// Content through the Kubo RPC API
+#[async_trait]
+impl ContentStore for IpfsAdapter {
+    /// `ipfs://<cid>[/path]`, `ipns://<name>`, `/ipfs/...` or a bare CID
+    async fn get(&self, uri: &str) -> Result<Vec<u8>> {
+        let resp = reqwest::Client::new()
+            .post(format!("{}/api/v0/cat", self.settings.api_url))
+            .query(&[("arg", content_path(uri))])
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(resp.bytes().await?.to_vec())
+    }
+
+    /// Adds and pins `data`; returns `ipfs://<cid>`
+    async fn put(&self, data: &[u8]) -> Result<String> {
+        let boundary = format!("chimera{:016x}", RandomState::new().build_hasher().finish());
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"data\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            boundary
+        )
+        .into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        let json: serde_json::Value = reqwest::Client::new()
+            .post(format!("{}/api/v0/add", self.settings.api_url))
+            .query(&[("pin", "true")])
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let cid = json
+            .get("Hash")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Kubo add returned no hash: {}", json))?;
+        Ok(format!("ipfs://{}", cid))
+    }
+}
+
+/// `/ip4/<ip>/tcp/<port>` as Kubo's config expects
+fn multiaddr(addr: SocketAddr) -> String {
+    let family = if addr.is_ipv4() { "ip4" } else { "ip6" };
+    format!("/{}/{}/tcp/{}", family, addr.ip(), addr.port())
+}
+
+/// IPFS path (`/ipfs/<cid>/...`, `/ipns/<name>/...`) for a URI
+fn content_path(uri: &str) -> String {
+    if let Some(rest) = uri.strip_prefix("ipfs://") {
+        format!("/ipfs/{}", rest)
+    } else if let Some(rest) = uri.strip_prefix("ipns://") {
+        format!("/ipns/{}", rest)
+    } else if uri.starts_with('/') {
+        uri.to_string()
+    } else {
+        format!("/ipfs/{}", uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_path() {
+        assert_eq!(content_path("ipfs://bafy/readme"), "/ipfs/bafy/readme");
+        assert_eq!(
+            content_path("ipns://docs.ipfs.tech"),
+            "/ipns/docs.ipfs.tech"
+        );
+        assert_eq!(content_path("/ipfs/bafy"), "/ipfs/bafy");
+        assert_eq!(content_path("bafy"), "/ipfs/bafy");
+    }
+
+    #[test]
+    fn test_multiaddr() {
+        assert_eq!(
+            multiaddr("127.0.0.1:5001".parse().unwrap()),
+            "/ip4/127.0.0.1/tcp/5001"
+        );
+        assert_eq!(
+            multiaddr("[::1]:8080".parse().unwrap()),
+            "/ip6/::1/tcp/8080"
+        );
+    }
+}
@@ -1,27 +1,42 @@
-use super::ProtocolAdapter;
+pub mod dns;
+pub mod ini;
+
+use super::{
+    Daemon, Endpoint, IpPrefix, Probe, ProtocolAdapter, StreamTransport, TransportStream,
+    TunnelInterface,
+};
 use crate::config::LokinetSettings;
 use anyhow::Result;
 use async_trait::async_trait;
 use log::{info, warn};
 use std::fs;
+use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
 use std::process::Stdio;
 use std::sync::Arc;
+use tokio::net::TcpStream;
 use tokio::process::{Child, Command};
 use tokio::sync::Mutex;
 
 pub struct LokinetAdapter {
     settings: LokinetSettings,
     process: Arc<Mutex<Option<Child>>>,
+    daemon: Daemon,
 }
 
 impl LokinetAdapter {
     pub fn new(settings: LokinetSettings) -> Self {
         Self {
+            daemon: Daemon::new(settings.mode),
             settings,
             process: Arc::new(Mutex::new(None)),
         }
     }
+
+    /// Lokinet's DNS resolver
+    pub fn resolver(&self) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], self.settings.dns_port))
+    }
 }
 
 #[async_trait]
@@ -31,7 +46,17 @@ impl ProtocolAdapter for LokinetAdapter {
             return Ok(());
         }
 
-        warn!("⚠️  Lokinet integration is EXPERIMENTAL. VPN/Tun mode may not fully support SOCKS5 proxy routing.");
+        warn!("⚠️  Lokinet integration is EXPERIMENTAL. .loki/.snode traffic goes through its interface, which needs admin privileges.");
+
+        // An external Lokinet must resolve on `dns_port`; its lokinet.ini is
+        // left alone
+        if !self
+            .daemon
+            .should_spawn("Lokinet", &Probe::Dns(self.resolver()))
+            .await?
+        {
+            return Ok(());
+        }
 
         if !Path::new(&self.settings.binary_path).exists() {
             warn!(
@@ -49,12 +74,26 @@ impl ProtocolAdapter for LokinetAdapter {
             fs::create_dir_all(data_dir)?;
         }
 
-        // Copy template config to data dir
+        // Render lokinet.ini from the settings and any hand-edited file
+        let hand_edited = match fs::read_to_string(&self.settings.config_file) {
+            Ok(text) => Some(text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let (config, replaced) = ini::render(&self.settings, hand_edited.as_deref())?;
+        for key in replaced {
+            warn!(
+                "{} sets {}, which Chimera overrides with its own value",
+                self.settings.config_file, key
+            );
+        }
         let config_path = data_dir.join("lokinet.ini");
-        if Path::new("chimera.lokinet.ini").exists() {
-            fs::copy("chimera.lokinet.ini", &config_path)?;
-        } else {
-            warn!("chimera.lokinet.ini template not found!");
+        fs::write(&config_path, config)?;
+        // May hold the exit auth token
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&config_path, fs::Permissions::from_mode(0o600))?;
         }
 
         // Lokinet usually runs as a system service or needs admin privileges
@@ -89,13 +128,14 @@ impl ProtocolAdapter for LokinetAdapter {
         *proc_lock = Some(child);
 
         info!(
-            "Lokinet started successfully (SOCKS5 on port {}).",
-            self.settings.socks_port
+            "Lokinet started successfully (DNS on port {}).",
+            self.settings.dns_port
         );
         Ok(())
     }
 
     async fn stop(&self) -> Result<()> {
+        self.daemon.detach();
         let mut proc_lock = self.process.lock().await;
         if let Some(mut child) = proc_lock.take() {
             info!("Stopping Lokinet...");
@@ -104,14 +144,52 @@ impl ProtocolAdapter for LokinetAdapter {
         Ok(())
     }
 
-    fn get_proxy_addr(&self) -> String {
-        // Return the configured SOCKS5 port for Lokinet
-        // Note: Requires exit node configuration in lokinet.ini for full SOCKS5 support
-        format!("127.0.0.1:{}", self.settings.socks_port)
+    fn endpoint(&self) -> Endpoint {
+        // Lokinet has no proxy; routing asks its resolver for a range address
+        Endpoint::Tun {
+            interface: ini::IFNAME.to_string(),
+            prefixes: self.prefixes(),
+            dns: Some(self.resolver()),
+        }
+    }
+
+    fn as_stream_transport(&self) -> Option<&dyn StreamTransport> {
+        Some(self)
+    }
+
+    fn as_tunnel_interface(&self) -> Option<&dyn TunnelInterface> {
+        Some(self)
     }
 
     async fn is_healthy(&self) -> bool {
+        if self.daemon.is_attached() {
+            return Probe::Dns(self.resolver()).answers().await;
+        }
         let proc_lock = self.process.lock().await;
         proc_lock.is_some()
     }
 }
+
+/// `.loki`/`.snode` names are resolved through Lokinet's DNS, then dialled
+/// directly through its interface
+#[async_trait]
+impl StreamTransport for LokinetAdapter {
+    async fn open(&self, host: &str, port: u16) -> Result<TransportStream> {
+        let ip = match host.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => dns::resolve(self.resolver(), host).await?.into(),
+        };
+        Ok(TcpStream::connect((ip, port)).await?.into())
+    }
+}
+
+impl TunnelInterface for LokinetAdapter {
+    fn interfaces(&self) -> Vec<String> {
+        vec![ini::IFNAME.to_string()]
+    }
+
+    /// Lokinet picks its range when it starts; names reach it through DNS
+    fn prefixes(&self) -> Vec<IpPrefix> {
+        Vec::new()
+    }
+}
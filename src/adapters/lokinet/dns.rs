@@ -0,0 +1,202 @@
+//! Minimal DNS client for Lokinet's resolver (RFC 1035, A records over UDP).
+//!
+//! Lokinet answers `.loki` and `.snode` names with an address from its range
+//! (e.g. 10.67.0.0/16), routed through its TUN interface; connecting to that
+//! address reaches the service.
+
+use anyhow::{anyhow, bail, Result};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+/// Lokinet may need a moment to look up a path to a new service
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+const ATTEMPTS: usize = 3;
+
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+
+/// Whether Lokinet resolves this name itself
+pub fn is_lokinet_name(host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    host.ends_with(".loki") || host.ends_with(".snode")
+}
+
+/// Ask the resolver at `resolver` for the IPv4 address of `name`
+pub async fn resolve(resolver: SocketAddr, name: &str) -> Result<Ipv4Addr> {
+    let socket = UdpSocket::bind(match resolver {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    })
+    .await?;
+    socket.connect(resolver).await?;
+
+    let id = RandomState::new().build_hasher().finish() as u16;
+    let query = build_query(id, name)?;
+    let mut buf = [0u8; 512];
+    for _ in 0..ATTEMPTS {
+        socket.send(&query).await?;
+        // Ignore stray datagrams with another ID until the timeout
+        let answer = tokio::time::timeout(QUERY_TIMEOUT, async {
+            loop {
+                let len = socket.recv(&mut buf).await?;
+                if len >= 2 && u16::from_be_bytes([buf[0], buf[1]]) == id {
+                    return parse_response(id, &buf[..len]);
+                }
+            }
+        })
+        .await;
+        if let Ok(result) = answer {
+            return result;
+        }
+    }
+    bail!("Lokinet DNS at {} did not answer for {}", resolver, name)
+}
+
+/// Whether a resolver answers at `resolver`, whatever the answer. Waits
+/// for the reply; callers bound the wait.
+pub async fn ping(resolver: SocketAddr) -> bool {
+    let Ok(socket) = UdpSocket::bind(match resolver {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    })
+    .await
+    else {
+        return false;
+    };
+    let id = RandomState::new().build_hasher().finish() as u16;
+    let Ok(query) = build_query(id, "localhost.loki") else {
+        return false;
+    };
+    let mut buf = [0u8; 512];
+    socket.connect(resolver).await.is_ok()
+        && socket.send(&query).await.is_ok()
+        && matches!(socket.recv(&mut buf).await, Ok(len) if len >= 2 && buf[..2] == id.to_be_bytes())
+}
+
+/// A/IN query with recursion desired
+pub fn build_query(id: u16, name: &str) -> Result<Vec<u8>> {
+    let mut packet = Vec::with_capacity(18 + name.len());
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&[0x01, 0x00]); // RD
+    packet.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // QDCOUNT = 1
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("invalid DNS name {:?}", name);
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_A.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(packet)
+}
+
+/// Offset just past the (possibly compressed) name starting at `pos`
+fn skip_name(packet: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        let len = *packet
+            .get(pos)
+            .ok_or_else(|| anyhow!("truncated DNS name"))? as usize;
+        match len {
+            0 => return Ok(pos + 1),
+            // Compression pointer: two bytes, ends the name
+            l if l & 0xC0 == 0xC0 => return Ok(pos + 2),
+            l => pos += 1 + l,
+        }
+    }
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Result<u16> {
+    packet
+        .get(pos..pos + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| anyhow!("truncated DNS response"))
+}
+
+/// First A record of the answer section
+pub fn parse_response(id: u16, packet: &[u8]) -> Result<Ipv4Addr> {
+    if packet.len() < 12 || read_u16(packet, 0)? != id {
+        bail!("malformed DNS response");
+    }
+    if packet[2] & 0x80 == 0 {
+        bail!("DNS packet is not a response");
+    }
+    match packet[3] & 0x0F {
+        0 => {}
+        3 => bail!("no such name (NXDOMAIN)"),
+        rcode => bail!("DNS error (RCODE {})", rcode),
+    }
+    let questions = read_u16(packet, 4)?;
+    let answers = read_u16(packet, 6)?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(packet, pos)? + 4;
+    }
+    for _ in 0..answers {
+        pos = skip_name(packet, pos)?;
+        let rtype = read_u16(packet, pos)?;
+        let class = read_u16(packet, pos + 2)?;
+        let rdlen = read_u16(packet, pos + 8)? as usize;
+        let rdata = packet
+            .get(pos + 10..pos + 10 + rdlen)
+            .ok_or_else(|| anyhow!("truncated DNS record"))?;
+        if rtype == TYPE_A && class == CLASS_IN && rdlen == 4 {
+            return Ok(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]));
+        }
+        pos += 10 + rdlen;
+    }
+    bail!("no A record in the answer")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Response to `query` with a CNAME followed by an A record
+    fn response(query: &[u8], addr: [u8; 4]) -> Vec<u8> {
+        let mut packet = query.to_vec();
+        packet[2] = 0x81; // QR, RD
+        packet[3] = 0x80; // RA, NOERROR
+        packet[7] = 2; // ANCOUNT
+                       // CNAME pointing back at the question name
+        packet.extend_from_slice(&[0xC0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xC0, 12]);
+        packet.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+        packet.extend_from_slice(&addr);
+        packet
+    }
+
+    #[test]
+    fn test_query_and_response() {
+        let query = build_query(0x1234, "example.loki.").unwrap();
+        assert_eq!(&query[..4], &[0x12, 0x34, 0x01, 0x00]);
+        assert_eq!(&query[12..26], b"\x07example\x04loki\x00");
+
+        let answer = response(&query, [10, 67, 0, 5]);
+        assert_eq!(
+            parse_response(0x1234, &answer).unwrap(),
+            Ipv4Addr::new(10, 67, 0, 5)
+        );
+        assert!(parse_response(0x4321, &answer).is_err());
+
+        let mut nxdomain = query.clone();
+        nxdomain[2] = 0x81;
+        nxdomain[3] = 0x83;
+        assert!(parse_response(0x1234, &nxdomain)
+            .unwrap_err()
+            .to_string()
+            .contains("NXDOMAIN"));
+        assert!(build_query(1, "bad..loki").is_err());
+    }
+
+    #[test]
+    fn test_lokinet_names() {
+        assert!(is_lokinet_name("abc.loki"));
+        assert!(is_lokinet_name("ABC.snode."));
+        assert!(!is_lokinet_name("loki.network"));
+    }
+}
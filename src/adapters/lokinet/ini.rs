@@ -0,0 +1,285 @@
+//! lokinet.ini rendered from `[lokinet]`.
+//!
+//! Three layers, lowest precedence first:
+//!
+//! 1. Chimera's defaults (API bind, data directory)
+//! 2. the hand-edited `lokinet.config_file`, for options Chimera has no
+//!    setting for
+//! 3. keys Chimera owns: the interface name routing reports, and keys
+//!    derived from the typed settings: DNS bind on `dns_port`, upstream
+//!    DNS, exit node and auth token, bootstrap file
+//!
+//! A key set in a higher layer replaces every value of that key below it.
+
+use crate::config::LokinetSettings;
+use anyhow::{anyhow, bail, Result};
+use std::fmt::Write;
+
+/// Ordered sections of `key=value` lines; keys may repeat (e.g. `upstream`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ini {
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl Ini {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut ini = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                ini.section(name.trim());
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                bail!("line {}: expected key=value, got {:?}", number + 1, line);
+            };
+            let Some((_, entries)) = ini.sections.last_mut() else {
+                bail!("line {}: {:?} is outside any section", number + 1, line);
+            };
+            entries.push((key.trim().to_string(), value.trim().to_string()));
+        }
+        Ok(ini)
+    }
+
+    fn section(&mut self, name: &str) -> &mut Vec<(String, String)> {
+        let index = match self.sections.iter().position(|(n, _)| n == name) {
+            Some(index) => index,
+            None => {
+                self.sections.push((name.to_string(), Vec::new()));
+                self.sections.len() - 1
+            }
+        };
+        &mut self.sections[index].1
+    }
+
+    /// Every value of `key` in `section`
+    pub fn get(&self, section: &str, key: &str) -> Vec<&str> {
+        self.sections
+            .iter()
+            .filter(|(n, _)| n == section)
+            .flat_map(|(_, entries)| entries.iter())
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    /// Replace all values of `key`, keeping its place in the section
+    pub fn set(&mut self, section: &str, key: &str, values: &[String]) {
+        let entries = self.section(section);
+        let at = entries
+            .iter()
+            .position(|(k, _)| k == key)
+            .unwrap_or(entries.len());
+        entries.retain(|(k, _)| k != key);
+        let at = at.min(entries.len());
+        entries.splice(at..at, values.iter().map(|v| (key.to_string(), v.clone())));
+    }
+
+    /// Keys as `(section, key)`, each once, in order
+    fn keys(&self) -> Vec<(String, String)> {
+        let mut keys: Vec<(String, String)> = Vec::new();
+        for (section, entries) in &self.sections {
+            for (key, _) in entries {
+                let pair = (section.clone(), key.clone());
+                if !keys.contains(&pair) {
+                    keys.push(pair);
+                }
+            }
+        }
+        keys
+    }
+
+    /// Lay `over` on top of this file; returns the `section.key`s it
+    /// changed
+    pub fn merge(&mut self, over: &Ini) -> Vec<String> {
+        let mut replaced = Vec::new();
+        for (section, key) in over.keys() {
+            let values: Vec<String> = over
+                .get(&section, &key)
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+            let current = self.get(&section, &key);
+            if !current.is_empty() && current != values {
+                replaced.push(format!("{}.{}", section, key));
+            }
+            self.set(&section, &key, &values);
+        }
+        replaced
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, entries) in &self.sections {
+            let _ = writeln!(out, "\n[{}]", name);
+            for (key, value) in entries {
+                let _ = writeln!(out, "{}={}", key, value);
+            }
+        }
+        out
+    }
+}
+
+/// Interface Chimera names Lokinet's TUN device
+pub const IFNAME: &str = "chimera0";
+
+/// Layer 1
+pub fn defaults() -> Ini {
+    let mut ini = Ini::default();
+    ini.set("router", "data-dir", &["data/lokinet".to_string()]);
+    ini.set("api", "enabled", &["true".to_string()]);
+    ini.set("api", "bind", &["127.0.0.1:1190".to_string()]);
+    ini
+}
+
+/// Layer 3
+pub fn from_settings(settings: &LokinetSettings) -> Result<Ini> {
+    let mut ini = Ini::default();
+    ini.set("dns", "bind", &[format!("127.0.0.1:{}", settings.dns_port)]);
+    if !settings.upstream_dns.is_empty() {
+        ini.set("dns", "upstream", &settings.upstream_dns);
+    }
+    // Routing and STATUS name this interface
+    ini.set("network", "ifname", &[IFNAME.to_string()]);
+    match (&settings.exit_node, &settings.exit_auth) {
+        (Some(exit), auth) => {
+            if !exit.ends_with(".loki") {
+                bail!("lokinet.exit_node must be a .loki address, got {:?}", exit);
+            }
+            ini.set("network", "exit-node", std::slice::from_ref(exit));
+            if let Some(token) = auth {
+                ini.set("network", "exit-auth", &[format!("{}:{}", exit, token)]);
+            }
+        }
+        (None, Some(_)) => bail!("lokinet.exit_auth is set without lokinet.exit_node"),
+        (None, None) => {}
+    }
+    if let Some(bootstrap) = &settings.bootstrap {
+        ini.set("bootstrap", "add-node", std::slice::from_ref(bootstrap));
+    }
+    Ok(ini)
+}
+
+/// The complete lokinet.ini and the hand-edited keys the settings replaced
+pub fn render(
+    settings: &LokinetSettings,
+    hand_edited: Option<&str>,
+) -> Result<(String, Vec<String>)> {
+    let typed = from_settings(settings)?;
+    let mut ini = defaults();
+    let mut replaced = Vec::new();
+    if let Some(text) = hand_edited {
+        let file = Ini::parse(text).map_err(|e| anyhow!("{}: {}", settings.config_file, e))?;
+        replaced = file.clone().merge(&typed);
+        ini.merge(&file);
+    }
+    ini.merge(&typed);
+    let text = format!(
+        "# Generated by Chimera from [lokinet] and {}; edits are overwritten\n{}",
+        settings.config_file,
+        ini.render()
+    );
+    Ok((text, replaced))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AdapterMode;
+
+    fn settings() -> LokinetSettings {
+        LokinetSettings {
+            enabled: true,
+            binary_path: String::new(),
+            mode: AdapterMode::Managed,
+            dns_port: 1053,
+            exit_node: None,
+            exit_auth: None,
+            upstream_dns: vec!["9.9.9.10".to_string()],
+            bootstrap: None,
+            config_file: "chimera.lokinet.ini".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_from_settings() {
+        let mut s = settings();
+        s.dns_port = 5353;
+        s.upstream_dns = vec!["1.1.1.1".to_string(), "9.9.9.9".to_string()];
+        s.exit_node = Some("exit.loki".to_string());
+        s.exit_auth = Some("s3cret".to_string());
+        s.bootstrap = Some("data/lokinet/bootstrap.signed".to_string());
+        let (text, replaced) = render(&s, None).unwrap();
+        let ini = Ini::parse(&text).unwrap();
+
+        assert_eq!(ini.get("dns", "bind"), ["127.0.0.1:5353"]);
+        assert_eq!(ini.get("dns", "upstream"), ["1.1.1.1", "9.9.9.9"]);
+        assert_eq!(ini.get("network", "exit-node"), ["exit.loki"]);
+        assert_eq!(ini.get("network", "exit-auth"), ["exit.loki:s3cret"]);
+        assert_eq!(
+            ini.get("bootstrap", "add-node"),
+            ["data/lokinet/bootstrap.signed"]
+        );
+        assert_eq!(ini.get("network", "ifname"), ["chimera0"]);
+        assert!(replaced.is_empty());
+    }
+
+    #[test]
+    fn test_precedence() {
+        let hand_edited = "\
+# local tweaks
+[network]
+ifname=lokitun0
+exit-node=other.loki
+
+[dns]
+bind=127.0.0.1:53
+upstream=8.8.8.8
+upstream=8.8.4.4
+
+[logging]
+level=debug
+";
+        let mut s = settings();
+        s.exit_node = Some("exit.loki".to_string());
+        let (text, replaced) = render(&s, Some(hand_edited)).unwrap();
+        let ini = Ini::parse(&text).unwrap();
+
+        // The file beats Chimera's defaults and adds its own options
+        assert_eq!(ini.get("api", "bind"), ["127.0.0.1:1190"]);
+        assert_eq!(ini.get("logging", "level"), ["debug"]);
+        // Typed settings beat the file, replacing every value of a key
+        assert_eq!(ini.get("dns", "bind"), ["127.0.0.1:1053"]);
+        assert_eq!(ini.get("dns", "upstream"), ["9.9.9.10"]);
+        assert_eq!(ini.get("network", "exit-node"), ["exit.loki"]);
+        // The interface is Chimera's, so routing names the real one
+        assert_eq!(ini.get("network", "ifname"), [IFNAME]);
+        assert_eq!(
+            replaced,
+            [
+                "dns.bind",
+                "dns.upstream",
+                "network.ifname",
+                "network.exit-node"
+            ]
+        );
+
+        // Repeating Chimera's own value is no override
+        let (_, replaced) = render(&settings(), Some("[network]\nifname=chimera0\n")).unwrap();
+        assert!(replaced.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let mut s = settings();
+        s.exit_auth = Some("token".to_string());
+        assert!(render(&s, None).is_err());
+        s.exit_node = Some("exit.example.com".to_string());
+        assert!(render(&s, None).is_err());
+        assert!(render(&settings(), Some("bind=127.0.0.1:53\n")).is_err());
+        assert!(render(&settings(), Some("[dns]\nno value\n")).is_err());
+    }
+}
@@ -1,28 +1,40 @@
-use super::ProtocolAdapter;
+use super::{Endpoint, ProtocolAdapter};
+use crate::config::PtSettings;
+use crate::pt_manager::{encode_socks_args, ManagedTransport};
 use async_trait::async_trait;
 use log::info;
-use std::process::{Child, Command, Stdio};
-use std::sync::Arc;
-use tokio::sync::Mutex;
 
 /// Meek Pluggable Transport Adapter
 /// Domain fronting for Tor to bypass censorship
+///
+/// Runs the meek client as a PT 1.0 managed proxy. meek_lite takes the
+/// reflector URL and front domain per connection, as SOCKS arguments; see
+/// `socks_args`.
 pub struct MeekAdapter {
     enabled: bool,
-    binary_path: String,
     front_domain: String,
-    process: Arc<Mutex<Option<Child>>>,
+    transport: ManagedTransport,
 }
 
 impl MeekAdapter {
     pub fn new(enabled: bool, binary_path: String, front_domain: String) -> Self {
         Self {
             enabled,
-            binary_path,
             front_domain,
-            process: Arc::new(Mutex::new(None)),
+            transport: ManagedTransport::new(PtSettings {
+                binary_path,
+                transports: vec!["meek_lite".to_string()],
+                state_dir: Some("data/meek".to_string()),
+                upstream_proxy: None,
+            }),
         }
     }
+
+    /// SOCKS5 username/password for a connection through the transport's
+    /// endpoint to the meek reflector at `url`, fronted by `front_domain`
+    pub fn socks_args(&self, url: &str) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+        encode_socks_args(&[("url", url), ("front", &self.front_domain)])
+    }
 }
 
 #[async_trait]
@@ -34,16 +46,7 @@ impl ProtocolAdapter for MeekAdapter {
         }
 
         info!("Starting Meek transport...");
-        let child = Command::new(&self.binary_path)
-            .env("TOR_PT_MANAGED_TRANSPORT_VER", "1")
-            .env("TOR_PT_STATE_LOCATION", "data/meek")
-            .env("TOR_PT_CLIENT_TRANSPORTS", "meek_lite")
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()?;
-
-        let mut proc_lock = self.process.lock().await;
-        *proc_lock = Some(child);
+        self.transport.launch().await?;
 
         info!(
             "Meek transport started with front domain: {}",
@@ -53,22 +56,22 @@ impl ProtocolAdapter for MeekAdapter {
     }
 
     async fn stop(&self) -> anyhow::Result<()> {
-        let mut proc_lock = self.process.lock().await;
-        if let Some(ref mut child) = *proc_lock {
-            child.kill()?;
-            *proc_lock = None;
+        if self.transport.is_running().await {
+            self.transport.shutdown().await?;
             info!("Meek stopped.");
         }
         Ok(())
     }
 
     async fn is_healthy(&self) -> bool {
-        let proc_lock = self.process.lock().await;
-        proc_lock.is_some()
+        self.transport.is_running().await
     }
 
-    fn get_proxy_addr(&self) -> String {
-        // Meek is a transport, not direct proxy
-        String::new()
+    fn endpoint(&self) -> Endpoint {
+        // SOCKS endpoint announced by the transport (none until started)
+        self.transport
+            .endpoint("meek_lite")
+            .map(Endpoint::Transport)
+            .unwrap_or(Endpoint::None)
     }
 }
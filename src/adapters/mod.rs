@@ -1,4 +1,6 @@
+pub mod capability;
 pub mod cjdns;
+pub mod endpoint;
 pub mod freenet;
 pub mod gnunet;
 pub mod i2p;
@@ -6,6 +8,7 @@ pub mod ipfs;
 pub mod lantern;
 pub mod lokinet;
 pub mod meek;
+pub mod mode;
 pub mod mysterium;
 pub mod nym;
 pub mod obfs4;
@@ -26,6 +29,12 @@ pub mod zeronet;
 use anyhow::Result;
 use async_trait::async_trait;
 
+pub use capability::{
+    Capability, ContentStore, Message, Messaging, StreamTransport, TransportStream, TunnelInterface,
+};
+pub use endpoint::{Endpoint, EndpointKind, HostPort, IpPrefix};
+pub use mode::{Daemon, Probe};
+
 #[async_trait]
 pub trait ProtocolAdapter {
     /// Start the protocol daemon/client
@@ -34,9 +43,42 @@ pub trait ProtocolAdapter {
     /// Stop the protocol daemon/client
     async fn stop(&self) -> Result<()>;
 
-    /// Get the SOCKS5 proxy address for this protocol
-    fn get_proxy_addr(&self) -> String;
+    /// Where the network can be reached from this machine
+    fn endpoint(&self) -> Endpoint;
 
     /// Check if the protocol is healthy/connected
     async fn is_healthy(&self) -> bool;
+
+    /// `Some(self)` when the adapter implements `StreamTransport`
+    fn as_stream_transport(&self) -> Option<&dyn StreamTransport> {
+        None
+    }
+
+    /// `Some(self)` when the adapter implements `ContentStore`
+    fn as_content_store(&self) -> Option<&dyn ContentStore> {
+        None
+    }
+
+    /// `Some(self)` when the adapter implements `Messaging`
+    fn as_messaging(&self) -> Option<&dyn Messaging> {
+        None
+    }
+
+    /// `Some(self)` when the adapter implements `TunnelInterface`
+    fn as_tunnel_interface(&self) -> Option<&dyn TunnelInterface> {
+        None
+    }
+
+    /// Capabilities the adapter implements
+    fn capabilities(&self) -> Vec<Capability> {
+        Capability::ALL
+            .into_iter()
+            .filter(|capability| match capability {
+                Capability::StreamTransport => self.as_stream_transport().is_some(),
+                Capability::ContentStore => self.as_content_store().is_some(),
+                Capability::Messaging => self.as_messaging().is_some(),
+                Capability::TunnelInterface => self.as_tunnel_interface().is_some(),
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,223 @@
+//! Managed vs. external daemons.
+//!
+//! `mode = "managed"` spawns the adapter's daemon, `"external"` attaches to
+//! one already answering at the configured address (system tor, i2pd, ...)
+//! and `"auto"` probes that address first and spawns only when nothing
+//! answers. An attached daemon is never stopped by Chimera.
+
+use super::IpPrefix;
+use crate::config::AdapterMode;
+use anyhow::{bail, Result};
+use log::info;
+use std::fmt;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What answers when a network's daemon is up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Probe {
+    /// SOCKS5 listener completing a no-auth handshake
+    Socks(SocketAddr),
+    /// Any TCP listener
+    Tcp(SocketAddr),
+    /// DNS resolver answering a query, whatever the answer
+    Dns(SocketAddr),
+    /// Network interface, by name
+    Interface(String),
+    /// One of the host's IPv6 addresses lies in the prefix
+    Prefix(IpPrefix),
+    /// A process runs under this executable name (Linux only)
+    Process(String),
+}
+
+impl Probe {
+    pub async fn answers(&self) -> bool {
+        let probe = async {
+            match self {
+                Probe::Socks(addr) => crate::socks5::probe_handshake(*addr).await.is_ok(),
+                Probe::Tcp(addr) => TcpStream::connect(addr).await.is_ok(),
+                Probe::Dns(addr) => super::lokinet::dns::ping(*addr).await,
+                Probe::Interface(name) => Path::new("/sys/class/net").join(name).exists(),
+                Probe::Prefix(prefix) => host_ipv6()
+                    .into_iter()
+                    .any(|(ip, _)| prefix.contains(ip.into())),
+                Probe::Process(name) => process_running(name),
+            }
+        };
+        tokio::time::timeout(PROBE_TIMEOUT, probe)
+            .await
+            .unwrap_or(false)
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Probe::Socks(addr) => write!(f, "socks5 {}", addr),
+            Probe::Tcp(addr) => write!(f, "tcp {}", addr),
+            Probe::Dns(addr) => write!(f, "dns {}", addr),
+            Probe::Interface(name) => write!(f, "interface {}", name),
+            Probe::Prefix(prefix) => write!(f, "an address in {}", prefix),
+            Probe::Process(name) => write!(f, "process {}", name),
+        }
+    }
+}
+
+/// IPv6 addresses of this host and their interfaces (Linux only)
+fn host_ipv6() -> Vec<(Ipv6Addr, String)> {
+    std::fs::read_to_string("/proc/net/if_inet6")
+        .map(|table| parse_if_inet6(&table))
+        .unwrap_or_default()
+}
+
+/// `/proc/net/if_inet6`: 32 hex digits, four fields, interface name
+fn parse_if_inet6(table: &str) -> Vec<(Ipv6Addr, String)> {
+    table
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let ip = u128::from_str_radix(fields.first()?, 16).ok()?;
+            Some((Ipv6Addr::from(ip), fields.get(5)?.to_string()))
+        })
+        .collect()
+}
+
+/// Whether a process named `name` runs, by `/proc/<pid>/comm` (which keeps
+/// the first 15 bytes of the name)
+fn process_running(name: &str) -> bool {
+    let comm = &name.as_bytes()[..name.len().min(15)];
+    let Ok(procs) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+    procs.flatten().any(|entry| {
+        std::fs::read(entry.path().join("comm"))
+            .is_ok_and(|c| c.strip_suffix(b"\n").unwrap_or(&c) == comm)
+    })
+}
+
+/// An adapter's configured mode and whether `start` attached to a running
+/// daemon instead of spawning one
+#[derive(Debug)]
+pub struct Daemon {
+    mode: AdapterMode,
+    attached: AtomicBool,
+}
+
+impl Daemon {
+    pub fn new(mode: AdapterMode) -> Self {
+        Self {
+            mode,
+            attached: AtomicBool::new(false),
+        }
+    }
+
+    pub fn mode(&self) -> AdapterMode {
+        self.mode
+    }
+
+    /// Whether `start` should spawn `name`'s daemon. Attaches instead when
+    /// the mode allows it and `probe` answers; fails in external mode when
+    /// nothing does.
+    pub async fn should_spawn(&self, name: &str, probe: &Probe) -> Result<bool> {
+        let attach = match self.mode {
+            AdapterMode::Managed => false,
+            AdapterMode::External => {
+                if !probe.answers().await {
+                    bail!("{} mode is external but nothing answers on {}", name, probe);
+                }
+                true
+            }
+            AdapterMode::Auto => probe.answers().await,
+        };
+        if attach {
+            info!("{}: attached to the running daemon ({})", name, probe);
+        }
+        self.attached.store(attach, Ordering::Relaxed);
+        Ok(!attach)
+    }
+
+    /// Running daemon belongs to someone else
+    pub fn is_attached(&self) -> bool {
+        self.attached.load(Ordering::Relaxed)
+    }
+
+    /// Forget the attached daemon; it keeps running
+    pub fn detach(&self) -> bool {
+        self.attached.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Default for Daemon {
+    fn default() -> Self {
+        Self::new(AdapterMode::Managed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{socks_proxy, SUCCEEDED};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_parse_if_inet6() {
+        let table = "\
+00000000000000000000000000000001 01 80 10 80       lo
+0200abcd00000000000000000000beef 05 07 00 80     ygg0
+";
+        let addrs = parse_if_inet6(table);
+        assert_eq!(addrs.len(), 2);
+        assert_eq!(
+            addrs[1],
+            ("200:abcd::beef".parse().unwrap(), "ygg0".to_string())
+        );
+        let ygg = IpPrefix::new("200::".parse().unwrap(), 7);
+        assert!(addrs.iter().any(|(ip, _)| ygg.contains((*ip).into())));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_process_probe() {
+        let comm = std::fs::read_to_string("/proc/self/comm").unwrap();
+        assert!(Probe::Process(comm.trim_end().to_string()).answers().await);
+        assert!(
+            !Probe::Process("chimera-no-such-process".to_string())
+                .answers()
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modes() {
+        // Nothing listens on a port we just released
+        let released = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = Probe::Socks(released.local_addr().unwrap());
+        drop(released);
+
+        let managed = Daemon::new(AdapterMode::Managed);
+        assert!(managed.should_spawn("Test", &closed).await.unwrap());
+        assert!(!managed.is_attached());
+
+        let auto = Daemon::new(AdapterMode::Auto);
+        assert!(auto.should_spawn("Test", &closed).await.unwrap());
+
+        let external = Daemon::new(AdapterMode::External);
+        let err = external.should_spawn("Test", &closed).await.unwrap_err();
+        assert!(err.to_string().contains("nothing answers"));
+
+        let (addr, _connects) = socks_proxy(SUCCEEDED).await;
+        let running = Probe::Socks(addr);
+
+        assert!(!auto.should_spawn("Test", &running).await.unwrap());
+        assert!(auto.is_attached());
+        assert!(!external.should_spawn("Test", &running).await.unwrap());
+        assert!(external.detach());
+        assert!(!external.is_attached());
+        assert!(managed.should_spawn("Test", &running).await.unwrap());
+    }
+}
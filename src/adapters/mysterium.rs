@@ -1,6 +1,8 @@
-use super::ProtocolAdapter;
+use super::{Daemon, Endpoint, Probe, ProtocolAdapter, StreamTransport, TransportStream};
+use crate::config::AdapterMode;
 use async_trait::async_trait;
 use log::info;
+use std::net::SocketAddr;
 use std::process::{Child, Command, Stdio};
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -12,6 +14,7 @@ pub struct MysteriumAdapter {
     binary_path: String,
     socks_port: u16,
     process: Arc<Mutex<Option<Child>>>,
+    daemon: Daemon,
 }
 
 impl MysteriumAdapter {
@@ -21,8 +24,18 @@ impl MysteriumAdapter {
             binary_path,
             socks_port,
             process: Arc::new(Mutex::new(None)),
+            daemon: Daemon::default(),
         }
     }
+
+    pub fn with_mode(mut self, mode: AdapterMode) -> Self {
+        self.daemon = Daemon::new(mode);
+        self
+    }
+
+    fn probe(&self) -> Probe {
+        Probe::Socks(SocketAddr::from(([127, 0, 0, 1], self.socks_port)))
+    }
 }
 
 #[async_trait]
@@ -33,6 +46,10 @@ impl ProtocolAdapter for MysteriumAdapter {
             return Ok(());
         }
 
+        if !self.daemon.should_spawn("Mysterium", &self.probe()).await? {
+            return Ok(());
+        }
+
         info!("Starting Mysterium Network...");
         let child = Command::new(&self.binary_path)
             .arg("service")
@@ -52,6 +69,7 @@ impl ProtocolAdapter for MysteriumAdapter {
     }
 
     async fn stop(&self) -> anyhow::Result<()> {
+        self.daemon.detach();
         let mut proc_lock = self.process.lock().await;
         if let Some(ref mut child) = *proc_lock {
             child.kill()?;
@@ -62,11 +80,28 @@ impl ProtocolAdapter for MysteriumAdapter {
     }
 
     async fn is_healthy(&self) -> bool {
+        if self.daemon.is_attached() {
+            return self.probe().answers().await;
+        }
         let proc_lock = self.process.lock().await;
         proc_lock.is_some()
     }
 
-    fn get_proxy_addr(&self) -> String {
-        format!("127.0.0.1:{}", self.socks_port)
+    fn endpoint(&self) -> Endpoint {
+        Endpoint::Socks5(SocketAddr::from(([127, 0, 0, 1], self.socks_port)))
+    }
+
+    fn as_stream_transport(&self) -> Option<&dyn StreamTransport> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl StreamTransport for MysteriumAdapter {
+    async fn open(&self, host: &str, port: u16) -> anyhow::Result<TransportStream> {
+        let proxy = SocketAddr::from(([127, 0, 0, 1], self.socks_port));
+        Ok(crate::socks5::connect_through(proxy, host, port)
+            .await?
+            .into())
     }
 }
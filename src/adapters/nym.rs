@@ -1,20 +1,49 @@
-use super::ProtocolAdapter;
+pub mod messaging;
+pub mod requesters;
+
+use super::{Endpoint, Message, Messaging, ProtocolAdapter, StreamTransport, TransportStream};
 use crate::config::NymSettings;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
-use log::{error, info, warn};
+use futures::stream::{BoxStream, StreamExt};
+use log::{debug, error, info, warn};
+use messaging::{Destination, NymMessaging, MESSAGING_DIR};
 use nym_sdk::mixnet;
+use std::collections::HashSet;
 use std::fs;
+use std::net::SocketAddr;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
+/// On-disk client storage (identity keys, gateway registration, reply
+/// SURBs); the Nym address stays the same across restarts
+pub const CLIENT_DIR: &str = "data/nym/client";
+
+/// A relaying probe through the mixnet may legitimately take a while
+const PROBE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Consecutive failed probes before switching network requester
+const PROBE_FAILURES_BEFORE_FAILOVER: u32 = 2;
+
 pub struct NymAdapter {
     settings: NymSettings,
     // We store the client wrapped in an Option.
     // If the type is not exactly SmsMixnetClient, we might need to adjust.
     // Based on SDK patterns, it is likely exported or public.
     client: Arc<Mutex<Option<mixnet::Socks5MixnetClient>>>,
+    /// Raw-message client, connected on first use
+    messaging: Mutex<Option<Arc<NymMessaging>>>,
+    /// Network requester the SOCKS5 client exits through
+    provider: Mutex<Option<String>>,
+    /// Requesters that stopped relaying, skipped until all have failed
+    failed_providers: Mutex<HashSet<String>>,
+    /// Outcome of the last relaying probe
+    relaying: AtomicBool,
+    /// Where the SDK's SOCKS5 client actually listens
+    socks_addr: Mutex<Option<SocketAddr>>,
 }
 
 impl NymAdapter {
@@ -22,8 +51,169 @@ impl NymAdapter {
         Self {
             settings,
             client: Arc::new(Mutex::new(None)),
+            messaging: Mutex::new(None),
+            provider: Mutex::new(None),
+            failed_providers: Mutex::new(HashSet::new()),
+            relaying: AtomicBool::new(false),
+            socks_addr: Mutex::new(None),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// Listen address reported by the running SOCKS5 client
+    pub async fn socks_addr(&self) -> Option<SocketAddr> {
+        *self.socks_addr.lock().await
+    }
+
+    /// Network requester currently in use
+    pub async fn provider(&self) -> Option<String> {
+        self.provider.lock().await.clone()
+    }
+
+    /// The pinned `upstream_provider` first, then the best directory entry
+    /// that has not failed. Once every candidate has failed they are all
+    /// tried again.
+    async fn select_provider(&self) -> Option<String> {
+        let pinned = self.settings.upstream_provider.clone();
+        let directory = match requesters::load_directory(&self.settings).await {
+            Ok(list) => list,
+            Err(e) => {
+                if pinned.is_none() {
+                    warn!("{}", e);
+                }
+                Vec::new()
+            }
+        };
+
+        let mut failed = self.failed_providers.lock().await;
+        for attempt in 0..2 {
+            if attempt == 1 {
+                if failed.is_empty() {
+                    break;
+                }
+                info!("Every known Nym network requester has failed; retrying them");
+                failed.clear();
+            }
+            if let Some(p) = pinned.as_ref().filter(|p| !failed.contains(*p)) {
+                return Some(p.clone());
+            }
+            if let Some(best) = requesters::rank(&directory, &self.settings, &failed).first() {
+                info!(
+                    "Selected Nym network requester {} (performance {:.0}%)",
+                    best.address,
+                    best.score() * 100.0
+                );
+                return Some(best.address.clone());
+            }
+        }
+        None
+    }
+
+    /// Probe the SOCKS5 client every `probe_interval_secs` and switch to
+    /// another network requester when it stops relaying
+    pub async fn run_failover(self: Arc<Self>) {
+        let Some((host, port)) = self
+            .settings
+            .probe_target
+            .rsplit_once(':')
+            .and_then(|(h, p)| Some((h.to_string(), p.parse::<u16>().ok()?)))
+        else {
+            warn!(
+                "Invalid nym.probe_target {:?}; requester failover disabled",
+                self.settings.probe_target
+            );
+            return;
+        };
+        let proxy = SocketAddr::from(([127, 0, 0, 1], self.settings.socks_port));
+        let interval = Duration::from_secs(self.settings.probe_interval_secs.max(10));
+        let mut failures = 0;
+
+        loop {
+            tokio::time::sleep(interval).await;
+            if self.client.lock().await.is_none() {
+                continue;
+            }
+            let probe = tokio::time::timeout(
+                PROBE_TIMEOUT,
+                crate::socks5::probe_connect(proxy, &host, port),
+            )
+            .await;
+            if matches!(probe, Ok(Ok(0x00))) {
+                failures = 0;
+                self.relaying.store(true, Ordering::Relaxed);
+                continue;
+            }
+            failures += 1;
+            debug!("Nym relaying probe failed ({} in a row)", failures);
+            if failures < PROBE_FAILURES_BEFORE_FAILOVER {
+                continue;
+            }
+            failures = 0;
+            self.relaying.store(false, Ordering::Relaxed);
+            if let Some(provider) = self.provider().await {
+                warn!(
+                    "Nym network requester {} stopped relaying; failing over",
+                    provider
+                );
+                self.failed_providers.lock().await.insert(provider);
+            }
+            let restarted = async {
+                self.stop().await?;
+                self.start().await
+            };
+            if let Err(e) = restarted.await {
+                error!("Nym failover failed: {}", e);
+            }
         }
     }
+
+    /// Native mixnet messaging (send, subscribe, SURB replies). Its address
+    /// is separate from the SOCKS5 client and survives New Identity.
+    pub async fn messaging(&self) -> Result<Arc<NymMessaging>> {
+        if !self.settings.enabled {
+            bail!("Nym is disabled");
+        }
+        let mut messaging = self.messaging.lock().await;
+        if let Some(client) = messaging.as_ref() {
+            return Ok(client.clone());
+        }
+        let client = Arc::new(NymMessaging::connect(Path::new(MESSAGING_DIR)).await?);
+        *messaging = Some(client.clone());
+        Ok(client)
+    }
+
+    /// Tear down the mixnet client, discard its stored keys and connect again
+    /// with a fresh identity (new Nym address)
+    pub async fn reconnect(&self) -> Result<()> {
+        info!("Rebuilding Nym client with a fresh identity...");
+        self.stop().await?;
+        if Path::new(CLIENT_DIR).exists() {
+            fs::remove_dir_all(CLIENT_DIR)?;
+        }
+        self.start().await
+    }
+}
+
+/// Listen address from the SDK's `socks5_url()`. The SDK is told to bind
+/// `nym.socks_port`; this guards against it listening anywhere else.
+fn check_socks5_url(url: &str, port: u16) -> Result<SocketAddr> {
+    let addr: SocketAddr = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .trim_end_matches('/')
+        .parse()
+        .map_err(|_| anyhow!("cannot parse Nym SOCKS5 address {:?}", url))?;
+    if addr.port() != port || !(addr.ip().is_loopback() || addr.ip().is_unspecified()) {
+        bail!(
+            "Nym SOCKS5 client listens on {} but nym.socks_port is {}",
+            addr,
+            port
+        );
+    }
+    Ok(addr)
 }
 
 #[async_trait]
@@ -33,11 +223,11 @@ impl ProtocolAdapter for NymAdapter {
             return Ok(());
         }
 
-        let provider = match &self.settings.upstream_provider {
+        let provider = match self.select_provider().await {
             Some(p) => p,
             None => {
-                error!("Nym is enabled but no 'upstream_provider' is configured. SOCKS5 client cannot start.");
-                warn!("Please set 'nym.upstream_provider' in your config to a valid Nym Network Requester address.");
+                error!("Nym is enabled but no network requester is available. SOCKS5 client cannot start.");
+                warn!("Set 'nym.directory_url' to a requester directory or pin 'nym.upstream_provider'.");
                 // We return Ok to not crash the Main server, but Nym won't work.
                 return Ok(());
             }
@@ -45,17 +235,20 @@ impl ProtocolAdapter for NymAdapter {
 
         info!("Initializing Native Nym Client with provider: {}", provider);
 
-        let data_dir = Path::new("data/nym");
+        let data_dir = Path::new(CLIENT_DIR);
         if !data_dir.exists() {
             fs::create_dir_all(data_dir)?;
         }
 
-        // Configure SOCKS5
-        let socks5_config = mixnet::Socks5::new(provider.clone());
+        // Configure SOCKS5 on the port the SOCKS5 server routes `.nym` traffic to
+        let mut socks5_config = mixnet::Socks5::new(provider.clone());
+        socks5_config.bind_address = SocketAddr::from(([127, 0, 0, 1], self.settings.socks_port));
 
-        // Use new_ephemeral for now to avoid storage complexity issues in this phase
-        let client_builder =
-            mixnet::MixnetClientBuilder::new_ephemeral().socks5_config(socks5_config);
+        // Keys are generated on first start and reused afterwards
+        let storage_paths = mixnet::StoragePaths::new_from_dir(data_dir)?;
+        let client_builder = mixnet::MixnetClientBuilder::new_with_default_storage(storage_paths)
+            .await?
+            .socks5_config(socks5_config);
 
         let client_instance = client_builder.build()?;
 
@@ -63,10 +256,20 @@ impl ProtocolAdapter for NymAdapter {
         let connected_client = client_instance.connect_to_mixnet_via_socks5().await?;
 
         let url = connected_client.socks5_url();
+        let socks_addr = match check_socks5_url(&url, self.settings.socks_port) {
+            Ok(addr) => addr,
+            Err(e) => {
+                connected_client.disconnect().await;
+                return Err(e);
+            }
+        };
         info!("Nym SOCKS5 Client listening at: {}", url);
 
         let mut client_lock = self.client.lock().await;
         *client_lock = Some(connected_client);
+        *self.provider.lock().await = Some(provider);
+        *self.socks_addr.lock().await = Some(socks_addr);
+        self.relaying.store(true, Ordering::Relaxed);
 
         info!("Nym Client started successfully.");
         Ok(())
@@ -78,15 +281,80 @@ impl ProtocolAdapter for NymAdapter {
             info!("Disconnecting Nym Client...");
             client.disconnect().await;
         }
+        *self.provider.lock().await = None;
+        *self.socks_addr.lock().await = None;
         Ok(())
     }
 
-    fn get_proxy_addr(&self) -> String {
-        format!("127.0.0.1:{}", self.settings.socks_port)
+    fn endpoint(&self) -> Endpoint {
+        Endpoint::Socks5(SocketAddr::from(([127, 0, 0, 1], self.settings.socks_port)))
+    }
+
+    fn as_stream_transport(&self) -> Option<&dyn StreamTransport> {
+        Some(self)
     }
 
+    fn as_messaging(&self) -> Option<&dyn Messaging> {
+        Some(self)
+    }
+
+    /// Connected, and the last probe (if any) got through a requester
     async fn is_healthy(&self) -> bool {
         let client_lock = self.client.lock().await;
-        client_lock.is_some()
+        client_lock.is_some() && self.relaying.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl StreamTransport for NymAdapter {
+    async fn open(&self, host: &str, port: u16) -> Result<TransportStream> {
+        let proxy = self
+            .socks_addr()
+            .await
+            .ok_or_else(|| anyhow!("Nym has no SOCKS5 listener"))?;
+        Ok(crate::socks5::connect_through(proxy, host, port)
+            .await?
+            .into())
+    }
+}
+
+/// Native messaging; `reply_to` is the sender's SURB tag in base58
+#[async_trait]
+impl Messaging for NymAdapter {
+    async fn address(&self) -> Result<String> {
+        Ok(self.messaging().await?.address().to_string())
+    }
+
+    /// `to` is a Nym address (`...@<gateway>`) or a reply tag
+    async fn send(&self, to: &str, data: &[u8]) -> Result<()> {
+        let destination: Destination = to.parse()?;
+        let client = self.messaging().await?;
+        match destination {
+            Destination::Address(recipient) => client.send(&recipient, data).await,
+            Destination::Reply(tag) => client.reply(tag, data).await,
+        }
+    }
+
+    async fn receive(&self) -> Result<BoxStream<'static, Message>> {
+        let messages = self.messaging().await?.subscribe().map(|msg| Message {
+            data: msg.data,
+            reply_to: msg.reply_tag.map(|tag| tag.to_base58_string()),
+        });
+        Ok(messages.boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socks5_url_must_match_port() {
+        let addr = check_socks5_url("socks5h://127.0.0.1:1080", 1080).unwrap();
+        assert_eq!(addr.port(), 1080);
+        assert!(check_socks5_url("127.0.0.1:1080", 1080).is_ok());
+        assert!(check_socks5_url("socks5h://127.0.0.1:1080", 1081).is_err());
+        assert!(check_socks5_url("socks5h://192.0.2.1:1080", 1080).is_err());
+        assert!(check_socks5_url("not a url", 1080).is_err());
     }
 }
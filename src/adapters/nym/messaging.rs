@@ -0,0 +1,205 @@
+//! Native mixnet messaging through the Nym SDK.
+//!
+//! Uses its own `MixnetClient` (the SOCKS5 client cannot carry raw
+//! messages) with on-disk keys, so our recipient address is stable. Outgoing
+//! messages carry reply SURBs: the recipient can answer without learning our
+//! address, and we answer anonymous senders the same way.
+
+use anyhow::{anyhow, Result};
+use futures::{Stream, StreamExt};
+use log::{debug, info};
+use nym_sdk::mixnet::{
+    self, IncludedSurbs, MixnetClientSender, MixnetMessageSender, ReconstructedMessage,
+};
+use std::path::Path;
+use tokio::sync::{broadcast, oneshot, Mutex};
+
+pub use nym_sdk::mixnet::{AnonymousSenderTag, Recipient};
+
+/// Storage of the messaging client, next to the SOCKS5 client's
+pub const MESSAGING_DIR: &str = "data/nym/messaging";
+
+/// Reply SURBs attached to every outgoing message
+const REPLY_SURBS: u32 = 10;
+
+/// Messages buffered per subscriber before the slowest one starts losing them
+const SUBSCRIBER_BUFFER: usize = 256;
+
+/// A message received from the mixnet
+#[derive(Debug, Clone)]
+pub struct IncomingMessage {
+    pub data: Vec<u8>,
+    /// Present when the sender attached SURBs; pass to [`NymMessaging::reply`]
+    pub reply_tag: Option<AnonymousSenderTag>,
+}
+
+impl From<ReconstructedMessage> for IncomingMessage {
+    fn from(msg: ReconstructedMessage) -> Self {
+        Self {
+            data: msg.message,
+            reply_tag: msg.sender_tag,
+        }
+    }
+}
+
+pub struct NymMessaging {
+    address: Recipient,
+    sender: MixnetClientSender,
+    incoming: broadcast::Sender<IncomingMessage>,
+    shutdown: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl NymMessaging {
+    /// Connect a messaging client whose keys live in `storage_dir`
+    pub async fn connect(storage_dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(storage_dir)?;
+        let storage_paths = mixnet::StoragePaths::new_from_dir(storage_dir)?;
+        let mut client = mixnet::MixnetClientBuilder::new_with_default_storage(storage_paths)
+            .await?
+            .build()?
+            .connect_to_mixnet()
+            .await?;
+
+        let address = *client.nym_address();
+        let sender = client.split_sender();
+        let (incoming, _) = broadcast::channel(SUBSCRIBER_BUFFER);
+        let (shutdown, mut shutdown_rx) = oneshot::channel();
+
+        // The client itself is the stream of incoming messages; it stays in
+        // this task until shutdown
+        let tx = incoming.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    msg = client.next() => match msg {
+                        // No subscribers is fine; the message is dropped
+                        Some(msg) => {
+                            let _ = tx.send(msg.into());
+                        }
+                        None => break,
+                    },
+                    _ = &mut shutdown_rx => break,
+                }
+            }
+            client.disconnect().await;
+            debug!("Nym messaging client disconnected");
+        });
+
+        info!("Nym messaging address: {}", address);
+        Ok(Self {
+            address,
+            sender,
+            incoming,
+            shutdown: Mutex::new(Some(shutdown)),
+        })
+    }
+
+    /// Our recipient address, to hand to peers
+    pub fn address(&self) -> &Recipient {
+        &self.address
+    }
+
+    /// Send bytes to `recipient` with reply SURBs attached; our address is
+    /// not revealed
+    pub async fn send(&self, recipient: &Recipient, data: &[u8]) -> Result<()> {
+        self.sender
+            .send_message(*recipient, data, IncludedSurbs::new(REPLY_SURBS))
+            .await?;
+        Ok(())
+    }
+
+    /// Answer an anonymous sender through the SURBs it attached
+    pub async fn reply(&self, tag: AnonymousSenderTag, data: &[u8]) -> Result<()> {
+        self.sender.send_reply(tag, data).await?;
+        Ok(())
+    }
+
+    /// Messages received from now on. Each subscriber sees every message;
+    /// one that falls too far behind skips the oldest.
+    pub fn subscribe(&self) -> impl Stream<Item = IncomingMessage> {
+        futures::stream::unfold(self.incoming.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => return Some((msg, rx)),
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        debug!("Nym subscriber skipped {} messages", n)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    pub async fn disconnect(&self) {
+        if let Some(shutdown) = self.shutdown.lock().await.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// Parse a Nym recipient address ("<client>.<encryption>@<gateway>")
+pub fn parse_recipient(address: &str) -> Result<Recipient> {
+    address
+        .trim()
+        .parse()
+        .map_err(|e| anyhow!("invalid Nym address {:?}: {:?}", address, e))
+}
+
+/// Where a message goes: a Nym address, or an anonymous sender answered
+/// through its reply SURBs
+#[derive(Debug)]
+pub enum Destination {
+    Address(Recipient),
+    Reply(AnonymousSenderTag),
+}
+
+impl std::str::FromStr for Destination {
+    type Err = anyhow::Error;
+
+    /// Nym addresses contain `@`; anything else is a base58 reply tag
+    fn from_str(to: &str) -> Result<Self> {
+        if to.contains('@') {
+            return Ok(Destination::Address(parse_recipient(to)?));
+        }
+        AnonymousSenderTag::try_from_base58_string(to.trim())
+            .map(Destination::Reply)
+            .map_err(|e| anyhow!("invalid Nym reply tag {:?}: {}", to, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ed25519 base point as client and gateway identity, X25519 base point
+    // as encryption key
+    const ADDRESS: &str = "6x5SYnLroiN7WYq8NQYU9KHcH4YjpBbwpUfVu3EB7ieH.\
+        c8fpTXm3XTRgE5maYQ24Li4L65wMYvAFomzXknxVEx7@\
+        6x5SYnLroiN7WYq8NQYU9KHcH4YjpBbwpUfVu3EB7ieH";
+    // Tag of bytes 1..=16
+    const REPLY_TAG: &str = "8DfbjXLth7APvt3qQPgtf";
+
+    #[test]
+    fn test_parse_recipient() {
+        let recipient = parse_recipient(&format!(" {}\n", ADDRESS)).unwrap();
+        assert_eq!(recipient.to_string(), ADDRESS);
+        assert!(parse_recipient("").is_err());
+        assert!(parse_recipient("not-a-nym-address").is_err());
+        assert!(parse_recipient("6x5SYnLroiN7WYq8NQYU9KHcH4YjpBbwpUfVu3EB7ieH@gateway").is_err());
+    }
+
+    #[test]
+    fn test_destination_dispatch() {
+        match ADDRESS.parse::<Destination>().unwrap() {
+            Destination::Address(recipient) => assert_eq!(recipient.to_string(), ADDRESS),
+            other => panic!("expected an address, got {:?}", other),
+        }
+        match REPLY_TAG.parse::<Destination>().unwrap() {
+            Destination::Reply(tag) => assert_eq!(tag.to_base58_string(), REPLY_TAG),
+            other => panic!("expected a reply tag, got {:?}", other),
+        }
+        // With an @ it must be an address; without, a valid tag
+        assert!(format!("{}@", REPLY_TAG).parse::<Destination>().is_err());
+        assert!("0OIl".parse::<Destination>().is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//! Directory of Nym network requesters (the exits the SOCKS5 client relays
+//! through) and automatic selection.
+//!
+//! The directory is a JSON list, either bare or under a `requesters` key:
+//!
+//! ```json
+//! [{ "address": "<client>.<enc>@<gateway>", "performance": 0.97,
+//!    "exit_policy": true, "allowed_ports": [80, 443] }]
+//! ```
+//!
+//! It is read from `nym.directory_url` (http(s) URL or file path) and cached
+//! in `nym.directory_cache`, which is used alone when the source is
+//! unreachable.
+
+use crate::config::NymSettings;
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Requester {
+    pub address: String,
+    /// Measured performance, 0.0-1.0 (percentages are accepted too)
+    #[serde(default)]
+    pub performance: f64,
+    /// Runs Nym's exit policy (open proxy) rather than a host allow list
+    #[serde(default)]
+    pub exit_policy: bool,
+    /// Ports the exit policy allows; empty when not advertised
+    #[serde(default)]
+    pub allowed_ports: Vec<u16>,
+}
+
+impl Requester {
+    /// Performance normalised to 0.0-1.0
+    pub fn score(&self) -> f64 {
+        if self.performance > 1.0 {
+            self.performance / 100.0
+        } else {
+            self.performance
+        }
+    }
+
+    fn eligible(&self, settings: &NymSettings) -> bool {
+        let ports_ok = self.allowed_ports.is_empty()
+            || settings
+                .required_ports
+                .iter()
+                .all(|p| self.allowed_ports.contains(p));
+        (self.exit_policy || !settings.require_exit_policy)
+            && self.score() >= settings.min_performance
+            && ports_ok
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Directory {
+    List(Vec<Requester>),
+    Wrapped { requesters: Vec<Requester> },
+}
+
+pub fn parse_directory(json: &str) -> Result<Vec<Requester>> {
+    Ok(match serde_json::from_str(json)? {
+        Directory::List(list) => list,
+        Directory::Wrapped { requesters } => requesters,
+    })
+}
+
+/// Eligible requesters, best first, skipping `exclude`
+pub fn rank(
+    requesters: &[Requester],
+    settings: &NymSettings,
+    exclude: &HashSet<String>,
+) -> Vec<Requester> {
+    let mut ranked: Vec<Requester> = requesters
+        .iter()
+        .filter(|r| r.eligible(settings) && !exclude.contains(&r.address))
+        .cloned()
+        .collect();
+    ranked.sort_by(|a, b| b.score().partial_cmp(&a.score()).unwrap_or(Ordering::Equal));
+    ranked
+}
+
+async fn fetch(source: &str) -> Result<String> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(client
+            .get(source)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    } else {
+        Ok(tokio::fs::read_to_string(source).await?)
+    }
+}
+
+/// Load the directory from its source, refreshing the cache, or from the
+/// cache alone when the source is unavailable
+pub async fn load_directory(settings: &NymSettings) -> Result<Vec<Requester>> {
+    if let Some(source) = &settings.directory_url {
+        match fetch(source).await.and_then(|json| {
+            let list = parse_directory(&json)?;
+            Ok((json, list))
+        }) {
+            Ok((json, list)) => {
+                if let Some(dir) = std::path::Path::new(&settings.directory_cache).parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(&settings.directory_cache, json)?;
+                info!(
+                    "Loaded {} Nym network requesters from {}",
+                    list.len(),
+                    source
+                );
+                return Ok(list);
+            }
+            Err(e) => warn!(
+                "Nym requester directory {} unavailable ({}); using cache",
+                source, e
+            ),
+        }
+    }
+    let json = std::fs::read_to_string(&settings.directory_cache).with_context(|| {
+        format!(
+            "no Nym requester directory: set nym.directory_url or provide {}",
+            settings.directory_cache
+        )
+    })?;
+    parse_directory(&json).map_err(|e| anyhow!("bad {}: {}", settings.directory_cache, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> NymSettings {
+        NymSettings {
+            enabled: true,
+            binary_path: String::new(),
+            socks_port: 1080,
+            upstream_provider: None,
+            directory_url: None,
+            directory_cache: String::new(),
+            min_performance: 0.8,
+            require_exit_policy: true,
+            required_ports: vec![443],
+            probe_target: "nymtech.net:443".to_string(),
+            probe_interval_secs: 60,
+        }
+    }
+
+    const DIRECTORY: &str = r#"{ "requesters": [
+        { "address": "slow@gw", "performance": 0.5, "exit_policy": true },
+        { "address": "good@gw", "performance": 95, "exit_policy": true },
+        { "address": "best@gw", "performance": 0.99, "exit_policy": true, "allowed_ports": [80, 443] },
+        { "address": "allowlist@gw", "performance": 1.0 },
+        { "address": "web-only@gw", "performance": 0.99, "exit_policy": true, "allowed_ports": [80] }
+    ] }"#;
+
+    #[test]
+    fn test_rank_filters_and_orders() {
+        let list = parse_directory(DIRECTORY).unwrap();
+        let ranked: Vec<String> = rank(&list, &settings(), &HashSet::new())
+            .into_iter()
+            .map(|r| r.address)
+            .collect();
+        assert_eq!(ranked, ["best@gw", "good@gw"]);
+
+        let failed = HashSet::from(["best@gw".to_string()]);
+        assert_eq!(rank(&list, &settings(), &failed)[0].address, "good@gw");
+
+        let mut lax = settings();
+        lax.require_exit_policy = false;
+        assert_eq!(
+            rank(&list, &lax, &HashSet::new())[0].address,
+            "allowlist@gw"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bare_list_and_cache_fallback() {
+        let cache = std::env::temp_dir().join(format!("chimera-nym-{}.json", std::process::id()));
+        std::fs::write(&cache, r#"[{ "address": "a@gw", "performance": 0.9 }]"#).unwrap();
+        let mut s = settings();
+        s.directory_url = Some("/nonexistent/requesters.json".to_string());
+        s.directory_cache = cache.display().to_string();
+        let list = load_directory(&s).await.unwrap();
+        assert_eq!(list[0].address, "a@gw");
+        std::fs::remove_file(cache).unwrap();
+    }
+}
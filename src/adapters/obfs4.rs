@@ -1,26 +1,29 @@
-use super::ProtocolAdapter;
+use super::{Endpoint, ProtocolAdapter};
+use crate::config::PtSettings;
+use crate::pt_manager::ManagedTransport;
 use async_trait::async_trait;
 use log::info;
-use std::process::{Child, Command, Stdio};
-use std::sync::Arc;
-use tokio::sync::Mutex;
 
 /// Obfs4 Pluggable Transport Adapter
 /// Obfuscation layer for Tor to bypass censorship
+///
+/// Runs obfs4proxy/lyrebird as a PT 1.0 managed proxy. The transport picks
+/// its own SOCKS port and reports it through `CMETHOD`.
 pub struct Obfs4Adapter {
     enabled: bool,
-    binary_path: String,
-    listen_port: u16,
-    process: Arc<Mutex<Option<Child>>>,
+    transport: ManagedTransport,
 }
 
 impl Obfs4Adapter {
-    pub fn new(enabled: bool, binary_path: String, listen_port: u16) -> Self {
+    pub fn new(enabled: bool, binary_path: String) -> Self {
         Self {
             enabled,
-            binary_path,
-            listen_port,
-            process: Arc::new(Mutex::new(None)),
+            transport: ManagedTransport::new(PtSettings {
+                binary_path,
+                transports: vec!["obfs4".to_string()],
+                state_dir: Some("data/obfs4".to_string()),
+                upstream_proxy: None,
+            }),
         }
     }
 }
@@ -34,38 +37,29 @@ impl ProtocolAdapter for Obfs4Adapter {
         }
 
         info!("Starting Obfs4 transport...");
-        let child = Command::new(&self.binary_path)
-            .env("TOR_PT_MANAGED_TRANSPORT_VER", "1")
-            .env("TOR_PT_STATE_LOCATION", "data/obfs4")
-            .env("TOR_PT_SERVER_TRANSPORTS", "obfs4")
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()?;
-
-        let mut proc_lock = self.process.lock().await;
-        *proc_lock = Some(child);
+        self.transport.launch().await?;
 
         info!("Obfs4 transport started.");
         Ok(())
     }
 
     async fn stop(&self) -> anyhow::Result<()> {
-        let mut proc_lock = self.process.lock().await;
-        if let Some(ref mut child) = *proc_lock {
-            child.kill()?;
-            *proc_lock = None;
+        if self.transport.is_running().await {
+            self.transport.shutdown().await?;
             info!("Obfs4 stopped.");
         }
         Ok(())
     }
 
     async fn is_healthy(&self) -> bool {
-        let proc_lock = self.process.lock().await;
-        proc_lock.is_some()
+        self.transport.is_running().await
     }
 
-    fn get_proxy_addr(&self) -> String {
-        // Obfs4 is a transport, not direct proxy
-        String::new()
+    fn endpoint(&self) -> Endpoint {
+        // SOCKS endpoint announced by the transport (none until started)
+        self.transport
+            .endpoint("obfs4")
+            .map(Endpoint::Transport)
+            .unwrap_or(Endpoint::None)
     }
 }
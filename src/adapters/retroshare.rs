@@ -1,4 +1,4 @@
-use super::ProtocolAdapter;
+use super::{endpoint, Endpoint, ProtocolAdapter};
 use crate::config::RetroShareSettings;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -36,7 +36,7 @@ impl ProtocolAdapter for RetroShareAdapter {
 
         let client = reqwest::Client::new();
         // Just check the root or a known endpoint
-        let url = format!("{}", self.settings.api_url);
+        let url = self.settings.api_url.clone();
 
         match client.get(&url).send().await {
             Ok(resp) if resp.status().is_success() => {
@@ -70,12 +70,11 @@ impl ProtocolAdapter for RetroShareAdapter {
         Ok(())
     }
 
-    fn get_proxy_addr(&self) -> String {
-        // RetroShare doesn't typically provide a SOCKS5 proxy for general traffic.
-        // It provides services usually accessible via the node.
-        // We return the API URL or a placeholder if no proxy exists.
-        // For now, let's return the API host/port.
-        self.settings.api_url.clone()
+    fn endpoint(&self) -> Endpoint {
+        // RetroShare has no proxy for general traffic, only its JSON API
+        endpoint::url_addr(&self.settings.api_url)
+            .map(Endpoint::Api)
+            .unwrap_or(Endpoint::None)
     }
 
     async fn is_healthy(&self) -> bool {
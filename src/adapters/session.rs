@@ -1,6 +1,8 @@
-use super::ProtocolAdapter;
+use super::{Daemon, Endpoint, Probe, ProtocolAdapter};
+use crate::config::AdapterMode;
 use async_trait::async_trait;
 use log::info;
+use std::path::Path;
 use std::process::{Child, Command, Stdio};
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -11,6 +13,7 @@ pub struct SessionAdapter {
     enabled: bool,
     binary_path: String,
     process: Arc<Mutex<Option<Child>>>,
+    daemon: Daemon,
 }
 
 impl SessionAdapter {
@@ -19,8 +22,24 @@ impl SessionAdapter {
             enabled,
             binary_path,
             process: Arc::new(Mutex::new(None)),
+            daemon: Daemon::default(),
         }
     }
+
+    pub fn with_mode(mut self, mode: AdapterMode) -> Self {
+        self.daemon = Daemon::new(mode);
+        self
+    }
+
+    /// Session listens nowhere; a running client is found by its process name
+    fn probe(&self) -> Probe {
+        let name = Path::new(&self.binary_path)
+            .file_name()
+            .map_or(self.binary_path.clone(), |n| {
+                n.to_string_lossy().into_owned()
+            });
+        Probe::Process(name)
+    }
 }
 
 #[async_trait]
@@ -31,6 +50,10 @@ impl ProtocolAdapter for SessionAdapter {
             return Ok(());
         }
 
+        if !self.daemon.should_spawn("Session", &self.probe()).await? {
+            return Ok(());
+        }
+
         info!("Starting Session (Oxen)...");
         let child = Command::new(&self.binary_path)
             .stdout(Stdio::null())
@@ -45,6 +68,7 @@ impl ProtocolAdapter for SessionAdapter {
     }
 
     async fn stop(&self) -> anyhow::Result<()> {
+        self.daemon.detach();
         let mut proc_lock = self.process.lock().await;
         if let Some(ref mut child) = *proc_lock {
             child.kill()?;
@@ -55,12 +79,15 @@ impl ProtocolAdapter for SessionAdapter {
     }
 
     async fn is_healthy(&self) -> bool {
+        if self.daemon.is_attached() {
+            return self.probe().answers().await;
+        }
         let proc_lock = self.process.lock().await;
         proc_lock.is_some()
     }
 
-    fn get_proxy_addr(&self) -> String {
+    fn endpoint(&self) -> Endpoint {
         // Session uses onion routing, not a SOCKS proxy
-        String::new()
+        Endpoint::None
     }
 }
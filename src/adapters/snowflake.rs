@@ -1,8 +1,11 @@
-use super::ProtocolAdapter;
+use super::{Endpoint, ProtocolAdapter};
+use crate::contribute::SnowflakeStats;
+use crate::stats::Stats;
 use async_trait::async_trait;
 use log::info;
-use std::process::{Child, Command, Stdio};
+use std::process::Stdio;
 use std::sync::Arc;
+use tokio::process::{Child, Command};
 use tokio::sync::Mutex;
 
 /// Snowflake Pluggable Transport Adapter
@@ -11,6 +14,9 @@ pub struct SnowflakeAdapter {
     enabled: bool,
     binary_path: String,
     broker_url: String,
+    /// Extra proxy options, e.g. `-capacity` from `[contribute]`
+    extra_args: Vec<String>,
+    stats: Option<Arc<Stats>>,
     process: Arc<Mutex<Option<Child>>>,
 }
 
@@ -20,9 +26,22 @@ impl SnowflakeAdapter {
             enabled,
             binary_path,
             broker_url,
+            extra_args: Vec::new(),
+            stats: None,
             process: Arc::new(Mutex::new(None)),
         }
     }
+
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.extra_args = args;
+        self
+    }
+
+    /// Publish totals from the proxy's periodic summaries as `networks.snowflake`
+    pub fn with_stats(mut self, stats: Arc<Stats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
 }
 
 #[async_trait]
@@ -34,13 +53,32 @@ impl ProtocolAdapter for SnowflakeAdapter {
         }
 
         info!("Starting Snowflake proxy...");
-        let child = Command::new(&self.binary_path)
+        let mut child = Command::new(&self.binary_path)
             .arg("-url")
             .arg(&self.broker_url)
+            .args(&self.extra_args)
             .stdout(Stdio::null())
-            .stderr(Stdio::null())
+            .stderr(Stdio::piped())
             .spawn()?;
 
+        // The proxy logs its summaries to stderr
+        if let Some(stderr) = child.stderr.take() {
+            let stats = self.stats.clone();
+            tokio::spawn(async move {
+                use tokio::io::{AsyncBufReadExt, BufReader};
+                let mut totals = SnowflakeStats::default();
+                let mut reader = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = reader.next_line().await {
+                    info!("[Snowflake] {}", line);
+                    if let Some(stats) = &stats {
+                        if totals.add_summary(&line) {
+                            stats.record_network("snowflake", &totals);
+                        }
+                    }
+                }
+            });
+        }
+
         let mut proc_lock = self.process.lock().await;
         *proc_lock = Some(child);
 
@@ -50,9 +88,8 @@ impl ProtocolAdapter for SnowflakeAdapter {
 
     async fn stop(&self) -> anyhow::Result<()> {
         let mut proc_lock = self.process.lock().await;
-        if let Some(ref mut child) = *proc_lock {
-            child.kill()?;
-            *proc_lock = None;
+        if let Some(mut child) = proc_lock.take() {
+            child.kill().await?;
             info!("Snowflake stopped.");
         }
         Ok(())
@@ -63,8 +100,8 @@ impl ProtocolAdapter for SnowflakeAdapter {
         proc_lock.is_some()
     }
 
-    fn get_proxy_addr(&self) -> String {
+    fn endpoint(&self) -> Endpoint {
         // Snowflake is a transport, not a direct proxy
-        String::new()
+        Endpoint::None
     }
 }
@@ -1,9 +1,18 @@
-use super::ProtocolAdapter;
-use crate::config::TorSettings;
-use anyhow::Result;
+pub mod control;
+pub mod onion_auth;
+pub mod pool;
+
+use super::{Daemon, Endpoint, Probe, ProtocolAdapter};
+use crate::config::{ContributeSettings, TorSettings};
+use crate::contribute;
+use crate::pt_manager::ClientMethod;
+use anyhow::{bail, Result};
 use async_trait::async_trait;
+use control::TorControl;
 use log::{info, warn};
-use std::path::Path;
+use onion_auth::{OnionAuthKey, OnionAuthStore};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
 use tokio::process::{Child, Command};
@@ -11,15 +20,127 @@ use tokio::sync::Mutex;
 
 pub struct TorAdapter {
     settings: TorSettings,
+    data_dir: PathBuf,
     process: Arc<Mutex<Option<Child>>>,
+    daemon: Daemon,
+    transport_plugins: Arc<Mutex<Vec<ClientMethod>>>,
+    /// Bridge/relay options from `[contribute]`
+    relay_args: Vec<String>,
+    /// Upstream set by the chain mode; overrides `tor.upstream_proxy`
+    chain_upstream: Arc<Mutex<Option<String>>>,
 }
 
 impl TorAdapter {
     pub fn new(settings: TorSettings) -> Self {
+        Self::instance(settings, 0)
+    }
+
+    /// Instance `index` of a pool: ports are offset by `2 * index` and each
+    /// instance past the first gets its own `data/tor-<index>` directory
+    pub fn instance(mut settings: TorSettings, index: usize) -> Self {
+        let offset = (2 * index) as u16;
+        settings.socks_port += offset;
+        settings.control_port += offset;
+        let data_dir = match index {
+            0 => PathBuf::from("data/tor"),
+            n => PathBuf::from(format!("data/tor-{}", n)),
+        };
         Self {
+            daemon: Daemon::new(settings.mode),
             settings,
+            data_dir,
             process: Arc::new(Mutex::new(None)),
+            transport_plugins: Arc::new(Mutex::new(Vec::new())),
+            relay_args: Vec::new(),
+            chain_upstream: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// Using a tor Chimera didn't start (`mode = "external"` or `"auto"`)
+    pub fn is_attached(&self) -> bool {
+        self.daemon.is_attached()
+    }
+
+    async fn is_running(&self) -> bool {
+        self.daemon.is_attached() || self.process.lock().await.is_some()
+    }
+
+    /// Run as a bridge or non-exit relay as configured in `[contribute]`
+    pub fn with_contribution(mut self, contribute: &ContributeSettings) -> Self {
+        self.relay_args = contribute::tor_args(contribute);
+        self
+    }
+
+    /// Pluggable transport endpoints Tor should use for its bridges.
+    /// Must be called before `start`.
+    pub async fn set_transport_plugins(&self, methods: Vec<ClientMethod>) {
+        *self.transport_plugins.lock().await = methods;
+    }
+
+    /// SOCKS5 proxy ("host:port") Tor must reach the network through, as
+    /// wired by the chain mode. Must be called before `start`.
+    pub async fn set_upstream_proxy(&self, upstream: String) {
+        *self.chain_upstream.lock().await = Some(upstream);
+    }
+
+    /// Local SOCKS listener of this instance
+    pub fn socks_addr(&self) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], self.settings.socks_port))
+    }
+
+    /// Open an authenticated control-port connection
+    pub async fn control(&self) -> Result<TorControl> {
+        let addr = SocketAddr::from(([127, 0, 0, 1], self.settings.control_port));
+        let cookie = self.data_dir.join("control_auth_cookie");
+        TorControl::connect(addr, Some(&cookie)).await
+    }
+
+    /// Bootstrap progress in percent, from the control port
+    pub async fn bootstrap_progress(&self) -> Result<u8> {
+        self.control().await?.bootstrap_progress().await
+    }
+
+    /// Ask Tor for fresh circuits (NEWNYM)
+    pub async fn signal_newnym(&self) -> Result<()> {
+        let mut control = self.control().await?;
+        control.signal_newnym().await?;
+        info!("Tor switched to clean circuits (NEWNYM).");
+        Ok(())
+    }
+
+    /// Client authorization keys Tor loads from its ClientOnionAuthDir
+    pub fn onion_auth(&self) -> OnionAuthStore {
+        OnionAuthStore::new(&self.settings.client_auth_dir)
+    }
+
+    /// Store a client authorization key and, if Tor is running, apply it
+    /// immediately through the control port
+    pub async fn add_client_auth(&self, key: &OnionAuthKey) -> Result<()> {
+        self.onion_auth().import(key)?;
+        if self.is_running().await {
+            let mut control = self.control().await?;
+            control
+                .onion_client_auth_add(&key.onion, &key.control_key_blob())
+                .await?;
         }
+        info!("Client authorization stored for {}.onion", key.onion);
+        Ok(())
+    }
+
+    /// Delete a client authorization key, also dropping it from running Tor
+    pub async fn remove_client_auth(&self, onion: &str) -> Result<bool> {
+        let onion = onion_auth::normalize_onion(onion)?;
+        let removed = self.onion_auth().remove(&onion)?;
+        if self.is_running().await {
+            let mut control = self.control().await?;
+            // Tor answers 251 when it had no such credential; that is fine
+            let _ = control.onion_client_auth_remove(&onion).await;
+        }
+        Ok(removed)
     }
 }
 
@@ -30,6 +151,27 @@ impl ProtocolAdapter for TorAdapter {
             return Ok(());
         }
 
+        let probe = Probe::Socks(self.socks_addr());
+        if !self.daemon.should_spawn("Tor", &probe).await? {
+            // Its traffic would leave directly instead of through the chain
+            if let Some(upstream) = self.chain_upstream.lock().await.as_ref() {
+                self.daemon.detach();
+                bail!(
+                    "chain_mode needs Tor to go through {}, but a running tor on {} cannot be \
+                     redirected; set tor.mode = \"managed\"",
+                    upstream,
+                    probe
+                );
+            }
+            if !self.settings.bridges.is_empty()
+                || !self.relay_args.is_empty()
+                || self.settings.upstream_proxy.is_some()
+            {
+                warn!("Tor is external; configure bridges, relaying and upstream proxies on it");
+            }
+            return Ok(());
+        }
+
         if !Path::new(&self.settings.binary_path).exists() {
             warn!(
                 "Tor binary not found at {}. Skipping Tor start.",
@@ -40,21 +182,35 @@ impl ProtocolAdapter for TorAdapter {
 
         info!("Starting Tor...");
         let mut cmd = Command::new(&self.settings.binary_path);
+        // ExtendedErrors makes Tor report onion-service failures as 0xF0-0xF7
         cmd.arg("--SocksPort")
-            .arg(self.settings.socks_port.to_string())
+            .arg(format!("{} ExtendedErrors", self.settings.socks_port))
             .arg("--ControlPort")
             .arg(self.settings.control_port.to_string())
+            .arg("--CookieAuthentication")
+            .arg("1")
             .arg("--DataDirectory")
-            .arg("data/tor");
+            .arg(&self.data_dir);
 
-        let data_dir = Path::new("data/tor");
-        if !data_dir.exists() {
-            info!("Creating Tor data directory: {:?}", data_dir);
-            std::fs::create_dir_all(data_dir)?;
+        if !self.data_dir.exists() {
+            info!("Creating Tor data directory: {:?}", self.data_dir);
+            std::fs::create_dir_all(&self.data_dir)?;
         }
 
-        // Phase 4: Protocol Chaining - Use upstream proxy if configured
-        if let Some(ref upstream) = self.settings.upstream_proxy {
+        // v3 client authorization keys managed by Chimera
+        let onion_auth = self.onion_auth();
+        onion_auth.ensure_dir()?;
+        cmd.arg("--ClientOnionAuthDir").arg(onion_auth.dir());
+
+        // Phase 4: Protocol Chaining - the chain mode's hop wins over a
+        // hand-configured upstream proxy
+        let upstream = self
+            .chain_upstream
+            .lock()
+            .await
+            .clone()
+            .or_else(|| self.settings.upstream_proxy.clone());
+        if let Some(ref upstream) = upstream {
             info!(
                 "Configuring Tor to chain through upstream proxy: {}",
                 upstream
@@ -62,28 +218,60 @@ impl ProtocolAdapter for TorAdapter {
             cmd.arg("--Socks5Proxy").arg(upstream);
         }
 
+        if !self.relay_args.is_empty() {
+            contribute::check_tor(&self.settings, upstream.as_deref())?;
+            info!("Tor will relay traffic for the network (contribution mode)");
+            cmd.args(&self.relay_args);
+        }
+
+        // Bridges fronted by managed pluggable transports
+        if !self.settings.bridges.is_empty() {
+            let plugins = self.transport_plugins.lock().await;
+            cmd.arg("--UseBridges").arg("1");
+            for bridge in &self.settings.bridges {
+                // Vanilla bridges start with an address rather than a transport name
+                let transport = bridge.split_whitespace().next().unwrap_or("");
+                let is_vanilla = transport.contains(':');
+                if !is_vanilla && !plugins.iter().any(|m| m.transport == transport) {
+                    warn!(
+                        "No pluggable transport provides '{}'; bridge may be unusable: {}",
+                        transport, bridge
+                    );
+                }
+                cmd.arg("--Bridge").arg(bridge);
+            }
+            for plugin in plugins.iter() {
+                info!(
+                    "Tor using pluggable transport: {}",
+                    plugin.tor_plugin_line()
+                );
+                cmd.arg("--ClientTransportPlugin")
+                    .arg(plugin.tor_plugin_line());
+            }
+        }
+
         cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
 
         let mut child = cmd.spawn()?;
 
         // Capture stdout/stderr for logging
         if let Some(stdout) = child.stdout.take() {
-             tokio::spawn(async move {
-                 use tokio::io::{AsyncBufReadExt, BufReader};
-                 let mut reader = BufReader::new(stdout).lines();
-                 while let Ok(Some(line)) = reader.next_line().await {
-                     info!("[Tor] {}", line);
-                 }
-             });
+            tokio::spawn(async move {
+                use tokio::io::{AsyncBufReadExt, BufReader};
+                let mut reader = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = reader.next_line().await {
+                    info!("[Tor] {}", line);
+                }
+            });
         }
         if let Some(stderr) = child.stderr.take() {
-             tokio::spawn(async move {
-                 use tokio::io::{AsyncBufReadExt, BufReader};
-                 let mut reader = BufReader::new(stderr).lines();
-                 while let Ok(Some(line)) = reader.next_line().await {
-                     log::warn!("[Tor] {}", line);
-                 }
-             });
+            tokio::spawn(async move {
+                use tokio::io::{AsyncBufReadExt, BufReader};
+                let mut reader = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = reader.next_line().await {
+                    log::warn!("[Tor] {}", line);
+                }
+            });
         }
 
         let mut proc_lock = self.process.lock().await;
@@ -94,6 +282,7 @@ impl ProtocolAdapter for TorAdapter {
     }
 
     async fn stop(&self) -> Result<()> {
+        self.daemon.detach();
         let mut proc_lock = self.process.lock().await;
         if let Some(mut child) = proc_lock.take() {
             info!("Stopping Tor...");
@@ -102,11 +291,14 @@ impl ProtocolAdapter for TorAdapter {
         Ok(())
     }
 
-    fn get_proxy_addr(&self) -> String {
-        format!("127.0.0.1:{}", self.settings.socks_port)
+    fn endpoint(&self) -> Endpoint {
+        Endpoint::Socks5(self.socks_addr())
     }
 
     async fn is_healthy(&self) -> bool {
+        if self.daemon.is_attached() {
+            return Probe::Socks(self.socks_addr()).answers().await;
+        }
         let proc_lock = self.process.lock().await;
         proc_lock.is_some()
     }
@@ -0,0 +1,153 @@
+//! Minimal Tor control-port client (control-spec.txt).
+//!
+//! Authenticates with the cookie Tor writes into its data directory, or with
+//! null authentication when no cookie exists.
+
+use anyhow::{anyhow, Result};
+use std::net::SocketAddr;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// An authenticated control connection
+pub struct TorControl {
+    stream: BufReader<TcpStream>,
+}
+
+impl TorControl {
+    /// Connect and authenticate. `cookie_path` is Tor's `control_auth_cookie`.
+    pub async fn connect(addr: SocketAddr, cookie_path: Option<&Path>) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        let mut control = Self {
+            stream: BufReader::new(stream),
+        };
+
+        let auth = match cookie_path.filter(|p| p.exists()) {
+            Some(path) => {
+                let cookie = tokio::fs::read(path).await?;
+                let hex: String = cookie.iter().map(|b| format!("{:02X}", b)).collect();
+                format!("AUTHENTICATE {}", hex)
+            }
+            None => "AUTHENTICATE".to_string(),
+        };
+        control.command(&auth).await?;
+        Ok(control)
+    }
+
+    /// Send a command and return the reply lines (status code stripped).
+    /// Fails if Tor answers with anything other than 250.
+    pub async fn command(&mut self, command: &str) -> Result<Vec<String>> {
+        self.stream
+            .get_mut()
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .await?;
+
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line().await?;
+            if line.len() < 4 {
+                return Err(anyhow!("Malformed control reply: {:?}", line));
+            }
+            let (code, sep, text) = (&line[..3], &line[3..4], &line[4..]);
+            if code != "250" {
+                return Err(anyhow!("Tor control error {}: {}", code, text));
+            }
+            match sep {
+                " " => {
+                    lines.push(text.to_string());
+                    return Ok(lines);
+                }
+                "+" => {
+                    // Data reply: "250+key=" followed by lines up to "."
+                    let mut data = text.to_string();
+                    loop {
+                        let data_line = self.read_line().await?;
+                        if data_line == "." {
+                            break;
+                        }
+                        data.push('\n');
+                        data.push_str(data_line.strip_prefix('.').unwrap_or(&data_line));
+                    }
+                    lines.push(data);
+                }
+                _ => lines.push(text.to_string()),
+            }
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            return Err(anyhow!("Tor closed the control connection"));
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    /// GETINFO a single key and return its value
+    pub async fn get_info(&mut self, key: &str) -> Result<String> {
+        let lines = self.command(&format!("GETINFO {}", key)).await?;
+        let prefix = format!("{}=", key);
+        lines
+            .iter()
+            .find_map(|l| l.strip_prefix(&prefix))
+            .map(|v| v.to_string())
+            .ok_or_else(|| anyhow!("GETINFO {} returned no value", key))
+    }
+
+    /// Bootstrap progress in percent (100 = circuits can be built)
+    pub async fn bootstrap_progress(&mut self) -> Result<u8> {
+        let phase = self.get_info("status/bootstrap-phase").await?;
+        parse_bootstrap_progress(&phase)
+            .ok_or_else(|| anyhow!("Unexpected bootstrap phase {:?}", phase))
+    }
+
+    /// Switch to clean circuits for new streams
+    pub async fn signal_newnym(&mut self) -> Result<()> {
+        self.command("SIGNAL NEWNYM").await?;
+        Ok(())
+    }
+
+    /// Register v3 client authorization for an onion service (`key_blob` is
+    /// `x25519:<base64 private key>`). Not persisted by Tor.
+    pub async fn onion_client_auth_add(&mut self, onion: &str, key_blob: &str) -> Result<()> {
+        self.command(&format!("ONION_CLIENT_AUTH_ADD {} {}", onion, key_blob))
+            .await?;
+        Ok(())
+    }
+
+    /// Forget client authorization for an onion service
+    pub async fn onion_client_auth_remove(&mut self, onion: &str) -> Result<()> {
+        self.command(&format!("ONION_CLIENT_AUTH_REMOVE {}", onion))
+            .await?;
+        Ok(())
+    }
+}
+
+/// PROGRESS from a bootstrap status event, e.g.
+/// `NOTICE BOOTSTRAP PROGRESS=100 TAG=done SUMMARY="Done"`
+pub fn parse_bootstrap_progress(phase: &str) -> Option<u8> {
+    phase
+        .split_whitespace()
+        .find_map(|word| word.strip_prefix("PROGRESS="))
+        .and_then(|p| p.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bootstrap_progress() {
+        assert_eq!(
+            parse_bootstrap_progress(r#"NOTICE BOOTSTRAP PROGRESS=100 TAG=done SUMMARY="Done""#),
+            Some(100)
+        );
+        assert_eq!(
+            parse_bootstrap_progress(
+                r#"NOTICE BOOTSTRAP PROGRESS=14 TAG=handshake SUMMARY="Handshaking with a relay""#
+            ),
+            Some(14)
+        );
+        assert_eq!(parse_bootstrap_progress("NOTICE BOOTSTRAP"), None);
+    }
+}
@@ -0,0 +1,265 @@
+//! v3 onion service client authorization keys.
+//!
+//! Keys live in Tor's `ClientOnionAuthDir` as one `<onion>.auth_private` file
+//! per service, in the format Tor reads at startup:
+//!
+//! ```text
+//! <56-char onion address without .onion>:descriptor:x25519:<base32 private key>
+//! ```
+//!
+//! Keys added while Tor runs are also pushed with `ONION_CLIENT_AUTH_ADD` so
+//! they apply without a restart.
+
+use anyhow::{anyhow, bail, Context, Result};
+use data_encoding::{BASE32_NOPAD, BASE64};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const AUTH_SUFFIX: &str = ".auth_private";
+
+/// Client authorization credentials for one onion service
+#[derive(Clone, PartialEq, Eq)]
+pub struct OnionAuthKey {
+    /// Onion address without the `.onion` suffix, lowercase
+    pub onion: String,
+    private_key: [u8; 32],
+}
+
+// Keep the private key out of logs
+impl fmt::Debug for OnionAuthKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnionAuthKey")
+            .field("onion", &self.onion)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OnionAuthKey {
+    /// Parse one `.auth_private` line
+    pub fn parse(line: &str) -> Result<Self> {
+        let parts: Vec<&str> = line.trim().split(':').collect();
+        let [onion, "descriptor", "x25519", key] = parts.as_slice() else {
+            bail!("expected <onion>:descriptor:x25519:<base32 key>");
+        };
+
+        let onion = normalize_onion(onion)?;
+        let bytes = BASE32_NOPAD
+            .decode(key.to_ascii_uppercase().as_bytes())
+            .map_err(|e| anyhow!("invalid base32 key: {}", e))?;
+        let private_key: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("x25519 private key must be 32 bytes"))?;
+
+        Ok(Self { onion, private_key })
+    }
+
+    /// Render as an `.auth_private` line
+    pub fn to_auth_private(&self) -> String {
+        format!(
+            "{}:descriptor:x25519:{}",
+            self.onion,
+            BASE32_NOPAD.encode(&self.private_key)
+        )
+    }
+
+    /// Key in the form `ONION_CLIENT_AUTH_ADD` expects (`x25519:<base64>`)
+    pub fn control_key_blob(&self) -> String {
+        format!("x25519:{}", BASE64.encode(&self.private_key))
+    }
+}
+
+/// Accepts `abc...xyz` or `abc...xyz.onion` and checks it is a v3 address
+pub fn normalize_onion(addr: &str) -> Result<String> {
+    let onion = addr.trim().to_ascii_lowercase();
+    let onion = onion.strip_suffix(".onion").unwrap_or(&onion).to_string();
+
+    // v3: base32(pubkey[32] | checksum[2] | version[1])
+    let decoded = BASE32_NOPAD
+        .decode(onion.to_ascii_uppercase().as_bytes())
+        .ok()
+        .filter(|b| b.len() == 35 && b[34] == 0x03);
+    if onion.len() != 56 || decoded.is_none() {
+        bail!("'{}' is not a v3 onion address", addr);
+    }
+    Ok(onion)
+}
+
+/// Chimera-managed `ClientOnionAuthDir`
+#[derive(Debug, Clone)]
+pub struct OnionAuthStore {
+    dir: PathBuf,
+}
+
+impl OnionAuthStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Create the directory, readable only by the current user
+    pub fn ensure_dir(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("creating {}", self.dir.display()))?;
+        restrict_permissions(&self.dir, 0o700)
+    }
+
+    fn key_path(&self, onion: &str) -> PathBuf {
+        self.dir.join(format!("{}{}", onion, AUTH_SUFFIX))
+    }
+
+    /// Store a key, replacing any existing key for the same onion
+    pub fn import(&self, key: &OnionAuthKey) -> Result<()> {
+        self.ensure_dir()?;
+        let path = self.key_path(&key.onion);
+        fs::write(&path, format!("{}\n", key.to_auth_private()))
+            .with_context(|| format!("writing {}", path.display()))?;
+        restrict_permissions(&path, 0o600)
+    }
+
+    /// Import every key from an existing `.auth_private` file
+    pub fn import_file(&self, path: &Path) -> Result<Vec<OnionAuthKey>> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let keys = contents
+            .lines()
+            .filter(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+            .map(OnionAuthKey::parse)
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("parsing {}", path.display()))?;
+        for key in &keys {
+            self.import(key)?;
+        }
+        Ok(keys)
+    }
+
+    /// Key stored for an onion address, if any
+    pub fn get(&self, onion: &str) -> Result<Option<OnionAuthKey>> {
+        let onion = normalize_onion(onion)?;
+        let path = self.key_path(&onion);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let line = fs::read_to_string(&path)?;
+        OnionAuthKey::parse(&line).map(Some)
+    }
+
+    /// `.auth_private` line for an onion, for moving it to another client
+    pub fn export(&self, onion: &str) -> Result<String> {
+        self.get(onion)?
+            .map(|k| k.to_auth_private())
+            .ok_or_else(|| anyhow!("no client authorization key for {}", onion))
+    }
+
+    /// Every stored key, sorted by onion address
+    pub fn keys(&self) -> Result<Vec<OnionAuthKey>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if !path.to_string_lossy().ends_with(AUTH_SUFFIX) {
+                continue;
+            }
+            match fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|l| OnionAuthKey::parse(&l))
+            {
+                Ok(key) => keys.push(key),
+                Err(e) => log::warn!("Skipping {}: {}", path.display(), e),
+            }
+        }
+        keys.sort_by(|a, b| a.onion.cmp(&b.onion));
+        Ok(keys)
+    }
+
+    /// Onion addresses that have credentials
+    pub fn list(&self) -> Result<Vec<String>> {
+        Ok(self.keys()?.into_iter().map(|k| k.onion).collect())
+    }
+
+    /// Delete the key for an onion; returns whether one existed
+    pub fn remove(&self, onion: &str) -> Result<bool> {
+        let path = self.key_path(&normalize_onion(onion)?);
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(&path)?;
+        Ok(true)
+    }
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("restricting permissions on {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Address and key shapes from Tor's client authorization documentation
+    const ONION: &str = "25njqamcweflpvkl73j4szahhihoc4xt3ktcgjnpaingr5yhkenl5sid";
+    const KEY: &str = "3FVW6TKGMN3YIL4YCXKVWGNWJ3GTI4ZF6FHQUBR4EKNVZP2XBJQQ";
+
+    #[test]
+    fn test_parse_roundtrip() {
+        let line = format!("{}:descriptor:x25519:{}", ONION, KEY);
+        let key = OnionAuthKey::parse(&line).unwrap();
+        assert_eq!(key.onion, ONION);
+        assert_eq!(key.to_auth_private(), line);
+        assert!(key.control_key_blob().starts_with("x25519:"));
+
+        // The .onion suffix and lowercase keys are accepted too
+        let relaxed = format!("{}.onion:descriptor:x25519:{}", ONION, KEY.to_lowercase());
+        assert_eq!(OnionAuthKey::parse(&relaxed).unwrap(), key);
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed() {
+        assert!(OnionAuthKey::parse("nonsense").is_err());
+        assert!(OnionAuthKey::parse(&format!("{}:descriptor:ed25519:{}", ONION, KEY)).is_err());
+        assert!(OnionAuthKey::parse(&format!("abc:descriptor:x25519:{}", KEY)).is_err());
+        assert!(OnionAuthKey::parse(&format!("{}:descriptor:x25519:AAAA", ONION)).is_err());
+    }
+
+    #[test]
+    fn test_store_import_list_export_remove() {
+        let dir = std::env::temp_dir().join(format!("chimera-onion-auth-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let store = OnionAuthStore::new(&dir);
+        assert!(store.list().unwrap().is_empty());
+
+        let key = OnionAuthKey::parse(&format!("{}:descriptor:x25519:{}", ONION, KEY)).unwrap();
+        store.import(&key).unwrap();
+        assert_eq!(store.list().unwrap(), vec![ONION.to_string()]);
+        assert_eq!(
+            store.export(&format!("{}.onion", ONION)).unwrap(),
+            key.to_auth_private()
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&dir), 0o700);
+            assert_eq!(mode(&store.key_path(ONION)), 0o600);
+        }
+
+        assert!(store.remove(ONION).unwrap());
+        assert!(!store.remove(ONION).unwrap());
+        assert!(store.export(ONION).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
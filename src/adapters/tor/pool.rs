@@ -0,0 +1,402 @@
+//! Pool of tor processes for workloads that outgrow a single instance.
+//!
+//! Each instance has its own SOCKS/control ports and data directory (see
+//! [`TorAdapter::instance`]) and its own health flag. Streams are spread
+//! across the healthy instances by least connections or by consistent
+//! (rendezvous) hashing of the destination host.
+
+use super::onion_auth::{OnionAuthKey, OnionAuthStore};
+use super::TorAdapter;
+use crate::adapters::{Endpoint, ProtocolAdapter, StreamTransport, TransportStream};
+use crate::config::{ContributeSettings, TorBalance, TorSettings};
+use crate::pt_manager::ClientMethod;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{error, info, warn};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+struct TorInstance {
+    adapter: Arc<TorAdapter>,
+    healthy: AtomicBool,
+    active: Arc<AtomicUsize>,
+}
+
+/// Point-in-time view of one pool member
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceStatus {
+    pub index: usize,
+    pub socks_addr: SocketAddr,
+    pub healthy: bool,
+    pub active_connections: usize,
+}
+
+/// An instance picked for one connection; counts towards its load until dropped
+#[derive(Debug)]
+pub struct PoolLease {
+    index: usize,
+    addr: SocketAddr,
+    active: Arc<AtomicUsize>,
+}
+
+impl PoolLease {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for PoolLease {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub struct TorPool {
+    instances: Vec<TorInstance>,
+    strategy: TorBalance,
+}
+
+impl fmt::Debug for TorPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TorPool")
+            .field("instances", &self.instances.len())
+            .field("strategy", &self.strategy)
+            .finish()
+    }
+}
+
+impl TorPool {
+    pub fn new(settings: TorSettings) -> Self {
+        Self::with_contribution(settings, &ContributeSettings::default())
+    }
+
+    /// Pool whose primary instance also relays as configured in `[contribute]`
+    pub fn with_contribution(settings: TorSettings, contribute: &ContributeSettings) -> Self {
+        let count = settings.instances.max(1);
+        let strategy = settings.balance;
+        let instances = (0..count)
+            .map(|i| TorInstance {
+                adapter: Arc::new(match i {
+                    0 => TorAdapter::instance(settings.clone(), 0).with_contribution(contribute),
+                    _ => TorAdapter::instance(settings.clone(), i),
+                }),
+                healthy: AtomicBool::new(false),
+                active: Arc::new(AtomicUsize::new(0)),
+            })
+            .collect();
+        Self {
+            instances,
+            strategy,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn strategy(&self) -> TorBalance {
+        self.strategy
+    }
+
+    /// The first instance, which keeps the configured ports and `data/tor`
+    pub fn primary(&self) -> Arc<TorAdapter> {
+        self.instances[0].adapter.clone()
+    }
+
+    pub fn status(&self) -> Vec<InstanceStatus> {
+        self.instances
+            .iter()
+            .enumerate()
+            .map(|(index, inst)| InstanceStatus {
+                index,
+                socks_addr: inst.adapter.socks_addr(),
+                healthy: inst.healthy.load(Ordering::Relaxed),
+                active_connections: inst.active.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Re-check every instance and record its health
+    pub async fn refresh_health(&self) {
+        for (index, inst) in self.instances.iter().enumerate() {
+            let healthy = inst.adapter.is_healthy().await;
+            if inst.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+                if healthy {
+                    info!("Tor instance {} is now healthy", index);
+                } else {
+                    warn!("Tor instance {} is now unhealthy", index);
+                }
+            }
+        }
+    }
+
+    /// Highest bootstrap progress any instance reports, if one answers
+    pub async fn bootstrap_progress(&self) -> Option<u8> {
+        let mut best = None;
+        for inst in &self.instances {
+            if let Ok(progress) = inst.adapter.bootstrap_progress().await {
+                best = best.max(Some(progress));
+            }
+        }
+        best
+    }
+
+    /// Healthy instance for a stream to `target_host`, per the strategy
+    pub fn pick(&self, target_host: &str) -> Option<usize> {
+        let healthy = self
+            .instances
+            .iter()
+            .enumerate()
+            .filter(|(_, inst)| inst.healthy.load(Ordering::Relaxed));
+
+        match self.strategy {
+            TorBalance::LeastConnections => healthy
+                .min_by_key(|(_, inst)| inst.active.load(Ordering::Relaxed))
+                .map(|(i, _)| i),
+            // Rendezvous hashing: only destinations of a failed instance move
+            TorBalance::ConsistentHash => {
+                let host = target_host.to_ascii_lowercase();
+                healthy
+                    .max_by_key(|(i, _)| {
+                        let mut hasher = DefaultHasher::new();
+                        (&host, *i).hash(&mut hasher);
+                        hasher.finish()
+                    })
+                    .map(|(i, _)| i)
+            }
+        }
+    }
+
+    /// Pick an instance and count the connection against it
+    pub fn acquire(&self, target_host: &str) -> Option<PoolLease> {
+        let index = self.pick(target_host)?;
+        let inst = &self.instances[index];
+        inst.active.fetch_add(1, Ordering::Relaxed);
+        Some(PoolLease {
+            index,
+            addr: inst.adapter.socks_addr(),
+            active: inst.active.clone(),
+        })
+    }
+
+    pub async fn set_transport_plugins(&self, methods: Vec<ClientMethod>) {
+        for inst in &self.instances {
+            inst.adapter.set_transport_plugins(methods.clone()).await;
+        }
+    }
+
+    /// Chain every instance through an upstream SOCKS5 proxy
+    pub async fn set_upstream_proxy(&self, upstream: String) {
+        for inst in &self.instances {
+            inst.adapter.set_upstream_proxy(upstream.clone()).await;
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.instances[0].adapter.is_enabled()
+    }
+
+    /// NEWNYM on every instance
+    pub async fn signal_newnym(&self) -> Result<()> {
+        let mut failed = Vec::new();
+        for (index, inst) in self.instances.iter().enumerate() {
+            if let Err(e) = inst.adapter.signal_newnym().await {
+                failed.push(format!("instance {}: {}", index, e));
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("NEWNYM failed on {}", failed.join(", ")))
+        }
+    }
+
+    /// Key store shared by all instances
+    pub fn onion_auth(&self) -> OnionAuthStore {
+        self.instances[0].adapter.onion_auth()
+    }
+
+    pub async fn add_client_auth(&self, key: &OnionAuthKey) -> Result<()> {
+        for inst in &self.instances {
+            inst.adapter.add_client_auth(key).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn remove_client_auth(&self, onion: &str) -> Result<bool> {
+        let mut removed = false;
+        for inst in &self.instances {
+            removed |= inst.adapter.remove_client_auth(onion).await?;
+        }
+        Ok(removed)
+    }
+}
+
+#[async_trait]
+impl ProtocolAdapter for TorPool {
+    async fn start(&self) -> Result<()> {
+        if self.instances.len() > 1 {
+            let ports: HashSet<u16> = self
+                .instances
+                .iter()
+                .flat_map(|i| {
+                    [
+                        i.adapter.settings.socks_port,
+                        i.adapter.settings.control_port,
+                    ]
+                })
+                .collect();
+            if ports.len() != 2 * self.instances.len() {
+                return Err(anyhow!(
+                    "Tor instance ports overlap; tor.socks_port and tor.control_port must differ by an odd number"
+                ));
+            }
+            info!("Starting {} Tor instances...", self.instances.len());
+        }
+
+        let mut started = 0;
+        for (index, inst) in self.instances.iter().enumerate() {
+            match inst.adapter.start().await {
+                Ok(()) => started += 1,
+                Err(e) => error!("Failed to start Tor instance {}: {}", index, e),
+            }
+        }
+        if started == 0 {
+            return Err(anyhow!("no Tor instance could be started"));
+        }
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        for inst in &self.instances {
+            inst.adapter.stop().await?;
+            inst.healthy.store(false, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn endpoint(&self) -> Endpoint {
+        self.instances[0].adapter.endpoint()
+    }
+
+    fn as_stream_transport(&self) -> Option<&dyn StreamTransport> {
+        Some(self)
+    }
+
+    async fn is_healthy(&self) -> bool {
+        self.refresh_health().await;
+        self.instances
+            .iter()
+            .any(|i| i.healthy.load(Ordering::Relaxed))
+    }
+}
+
+/// Streams go to the instance the balancing strategy picks for the host and
+/// count against it until they are dropped
+#[async_trait]
+impl StreamTransport for TorPool {
+    async fn open(&self, host: &str, port: u16) -> Result<TransportStream> {
+        // Falls back to the first instance while none is known healthy
+        let lease = self.acquire(host);
+        let proxy = lease
+            .as_ref()
+            .map(PoolLease::addr)
+            .unwrap_or_else(|| self.instances[0].adapter.socks_addr());
+        let stream = crate::socks5::connect_through(proxy, host, port).await?;
+        Ok(TransportStream::from(stream).with_guard(lease))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+    use crate::test_support::{socks_proxy, SUCCEEDED};
+
+    fn pool(instances: usize, balance: TorBalance) -> TorPool {
+        let mut settings = Settings::defaults().unwrap().tor;
+        settings.instances = instances;
+        settings.balance = balance;
+        TorPool::new(settings)
+    }
+
+    fn set_healthy(pool: &TorPool, healthy: &[bool]) {
+        for (inst, h) in pool.instances.iter().zip(healthy) {
+            inst.healthy.store(*h, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_instances_get_distinct_ports() {
+        let pool = pool(3, TorBalance::LeastConnections);
+        let ports: Vec<u16> = pool.status().iter().map(|s| s.socks_addr.port()).collect();
+        assert_eq!(ports, vec![9052, 9054, 9056]);
+        assert_eq!(
+            pool.instances[2].adapter.data_dir,
+            std::path::Path::new("data/tor-2")
+        );
+    }
+
+    #[test]
+    fn test_least_connections_skips_unhealthy() {
+        let pool = pool(3, TorBalance::LeastConnections);
+        assert!(pool.acquire("example.com").is_none());
+
+        set_healthy(&pool, &[true, false, true]);
+        let a = pool.acquire("example.com").unwrap();
+        let b = pool.acquire("example.com").unwrap();
+        assert_eq!((a.index(), b.index()), (0, 2));
+
+        // Releasing a lease frees capacity on that instance again
+        drop(a);
+        assert_eq!(pool.acquire("example.com").unwrap().index(), 0);
+        assert_eq!(pool.status()[2].active_connections, 1);
+    }
+
+    #[tokio::test]
+    async fn test_stream_transport_counts_connections() {
+        let (proxy, _connects) = socks_proxy(SUCCEEDED).await;
+        let mut settings = Settings::defaults().unwrap().tor;
+        settings.socks_port = proxy.port();
+        let pool = TorPool::new(settings);
+        set_healthy(&pool, &[true]);
+
+        let stream = pool.open("example.onion", 80).await.unwrap();
+        assert_eq!(pool.status()[0].active_connections, 1);
+        drop(stream);
+        assert_eq!(pool.status()[0].active_connections, 0);
+    }
+
+    #[test]
+    fn test_consistent_hash_is_sticky() {
+        let pool = pool(4, TorBalance::ConsistentHash);
+        set_healthy(&pool, &[true, true, true, true]);
+
+        let hosts: Vec<String> = (0..32).map(|i| format!("site{}.onion", i)).collect();
+        let before: Vec<usize> = hosts.iter().map(|h| pool.pick(h).unwrap()).collect();
+        assert_eq!(pool.pick("SITE0.onion"), Some(before[0]));
+
+        // Only destinations of the failed instance move
+        set_healthy(&pool, &[true, false, true, true]);
+        for (host, old) in hosts.iter().zip(&before) {
+            let new = pool.pick(host).unwrap();
+            assert_ne!(new, 1);
+            if *old != 1 {
+                assert_eq!(new, *old);
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use super::ProtocolAdapter;
+use super::{endpoint, Endpoint, ProtocolAdapter};
 use crate::config::TriblerSettings;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -73,11 +73,11 @@ impl ProtocolAdapter for TriblerAdapter {
         Ok(())
     }
 
-    fn get_proxy_addr(&self) -> String {
-        // Tribler typically doesn't offer a SOCKS proxy for external apps
-        // It manages its own anonymous downloads.
-        // We'll return the API URL.
-        self.settings.api_url.clone()
+    fn endpoint(&self) -> Endpoint {
+        // Tribler has no proxy for other applications, only its REST API
+        endpoint::url_addr(&self.settings.api_url)
+            .map(Endpoint::Api)
+            .unwrap_or(Endpoint::None)
     }
 
     async fn is_healthy(&self) -> bool {
@@ -1,6 +1,8 @@
-use super::ProtocolAdapter;
+use super::{Daemon, Endpoint, IpPrefix, Probe, ProtocolAdapter, TunnelInterface};
+use crate::config::AdapterMode;
 use async_trait::async_trait;
-use log::info;
+use log::{info, warn};
+use std::fs;
 use std::process::{Child, Command, Stdio};
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -13,6 +15,7 @@ pub struct WireGuardAdapter {
     interface_name: String,
     config_path: Option<String>,
     process: Arc<Mutex<Option<Child>>>,
+    daemon: Daemon,
 }
 
 impl WireGuardAdapter {
@@ -28,8 +31,22 @@ impl WireGuardAdapter {
             interface_name,
             config_path,
             process: Arc::new(Mutex::new(None)),
+            daemon: Daemon::default(),
         }
     }
+
+    pub fn with_mode(mut self, mode: AdapterMode) -> Self {
+        self.daemon = Daemon::new(mode);
+        self
+    }
+
+    fn probe(&self) -> Probe {
+        Probe::Interface(self.interface_name.clone())
+    }
+
+    fn config(&self) -> &str {
+        self.config_path.as_deref().unwrap_or("wg0.conf")
+    }
 }
 
 #[async_trait]
@@ -40,8 +57,12 @@ impl ProtocolAdapter for WireGuardAdapter {
             return Ok(());
         }
 
+        if !self.daemon.should_spawn("WireGuard", &self.probe()).await? {
+            return Ok(());
+        }
+
         info!("Starting WireGuard...");
-        let config = self.config_path.as_deref().unwrap_or("wg0.conf");
+        let config = self.config();
 
         let child = Command::new(&self.binary_path)
             .arg("up")
@@ -58,7 +79,11 @@ impl ProtocolAdapter for WireGuardAdapter {
     }
 
     async fn stop(&self) -> anyhow::Result<()> {
-        let config = self.config_path.as_deref().unwrap_or("wg0.conf");
+        // Someone else's interface stays up
+        if self.daemon.detach() {
+            return Ok(());
+        }
+        let config = self.config();
 
         // WireGuard uses wg-quick down to stop
         let _ = Command::new(&self.binary_path)
@@ -73,12 +98,73 @@ impl ProtocolAdapter for WireGuardAdapter {
     }
 
     async fn is_healthy(&self) -> bool {
+        if self.daemon.is_attached() {
+            return self.probe().answers().await;
+        }
         let proc_lock = self.process.lock().await;
         proc_lock.is_some()
     }
 
-    fn get_proxy_addr(&self) -> String {
-        // WireGuard creates a VPN tunnel, not a SOCKS proxy
-        String::new()
+    fn endpoint(&self) -> Endpoint {
+        // WireGuard creates a VPN tunnel; its routes follow AllowedIPs
+        Endpoint::Tun {
+            interface: self.interface_name.clone(),
+            prefixes: self.prefixes(),
+            dns: None,
+        }
+    }
+
+    fn as_tunnel_interface(&self) -> Option<&dyn TunnelInterface> {
+        Some(self)
+    }
+}
+
+impl TunnelInterface for WireGuardAdapter {
+    fn interfaces(&self) -> Vec<String> {
+        vec![self.interface_name.clone()]
+    }
+
+    /// `AllowedIPs` of the config's peers. Default routes are left out: a
+    /// full tunnel already carries everything, and IP literals keep their
+    /// usual network.
+    fn prefixes(&self) -> Vec<IpPrefix> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        let text = match fs::read_to_string(self.config()) {
+            Ok(text) => text,
+            Err(e) => {
+                warn!("Cannot read WireGuard config {}: {}", self.config(), e);
+                return Vec::new();
+            }
+        };
+        allowed_ips(&text)
+            .into_iter()
+            .filter(|prefix| prefix.len > 0)
+            .collect()
+    }
+}
+
+/// Every `AllowedIPs` entry in a wg-quick config
+fn allowed_ips(config: &str) -> Vec<IpPrefix> {
+    config
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(key, _)| key.trim().eq_ignore_ascii_case("AllowedIPs"))
+        .flat_map(|(_, value)| value.split(','))
+        .filter_map(|prefix| prefix.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_ips() {
+        let config = "[Interface]\nAddress = 10.8.0.2/32\n\n[Peer]\n\
+                      AllowedIPs = 10.8.0.0/24, fd00::/64\nAllowedIPs=0.0.0.0/0\n";
+        let prefixes: Vec<String> = allowed_ips(config).iter().map(|p| p.to_string()).collect();
+        assert_eq!(prefixes, ["10.8.0.0/24", "fd00::/64", "0.0.0.0/0"]);
     }
 }
@@ -1,10 +1,21 @@
-use super::ProtocolAdapter;
+use super::{
+    Daemon, Endpoint, IpPrefix, Probe, ProtocolAdapter, StreamTransport, TransportStream,
+    TunnelInterface,
+};
+use crate::config::AdapterMode;
+use anyhow::{anyhow, bail};
 use async_trait::async_trait;
 use log::info;
+use std::net::{IpAddr, Ipv6Addr};
 use std::process::{Child, Command, Stdio};
 use std::sync::Arc;
+use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 
+/// Node addresses (`200::/8`) and routed subnets (`300::/8`)
+const MESH_PREFIX: IpPrefix =
+    IpPrefix::new(IpAddr::V6(Ipv6Addr::new(0x200, 0, 0, 0, 0, 0, 0, 0)), 7);
+
 /// Yggdrasil Mesh Network Adapter
 /// Provides end-to-end encrypted IPv6 overlay network
 pub struct YggdrasilAdapter {
@@ -12,6 +23,7 @@ pub struct YggdrasilAdapter {
     binary_path: String,
     config_path: Option<String>,
     process: Arc<Mutex<Option<Child>>>,
+    daemon: Daemon,
 }
 
 impl YggdrasilAdapter {
@@ -21,8 +33,18 @@ impl YggdrasilAdapter {
             binary_path,
             config_path,
             process: Arc::new(Mutex::new(None)),
+            daemon: Daemon::default(),
         }
     }
+
+    pub fn with_mode(mut self, mode: AdapterMode) -> Self {
+        self.daemon = Daemon::new(mode);
+        self
+    }
+
+    fn probe(&self) -> Probe {
+        Probe::Prefix(MESH_PREFIX)
+    }
 }
 
 #[async_trait]
@@ -33,6 +55,10 @@ impl ProtocolAdapter for YggdrasilAdapter {
             return Ok(());
         }
 
+        if !self.daemon.should_spawn("Yggdrasil", &self.probe()).await? {
+            return Ok(());
+        }
+
         info!("Starting Yggdrasil...");
         let mut cmd = Command::new(&self.binary_path);
         cmd.arg("-useconffile");
@@ -53,6 +79,7 @@ impl ProtocolAdapter for YggdrasilAdapter {
     }
 
     async fn stop(&self) -> anyhow::Result<()> {
+        self.daemon.detach();
         let mut proc_lock = self.process.lock().await;
         if let Some(ref mut child) = *proc_lock {
             child.kill()?;
@@ -63,12 +90,52 @@ impl ProtocolAdapter for YggdrasilAdapter {
     }
 
     async fn is_healthy(&self) -> bool {
+        if self.daemon.is_attached() {
+            return self.probe().answers().await;
+        }
         let proc_lock = self.process.lock().await;
         proc_lock.is_some()
     }
 
-    fn get_proxy_addr(&self) -> String {
+    fn endpoint(&self) -> Endpoint {
         // Yggdrasil doesn't use a SOCKS proxy; it creates a TUN interface
-        String::new()
+        Endpoint::Tun {
+            interface: self.interfaces().join(","),
+            prefixes: self.prefixes(),
+            dns: None,
+        }
+    }
+
+    fn as_stream_transport(&self) -> Option<&dyn StreamTransport> {
+        Some(self)
+    }
+
+    fn as_tunnel_interface(&self) -> Option<&dyn TunnelInterface> {
+        Some(self)
+    }
+}
+
+/// Direct connections to mesh addresses
+#[async_trait]
+impl StreamTransport for YggdrasilAdapter {
+    async fn open(&self, host: &str, port: u16) -> anyhow::Result<TransportStream> {
+        let ip: IpAddr = host
+            .parse()
+            .map_err(|_| anyhow!("{} is not a Yggdrasil address", host))?;
+        if !MESH_PREFIX.contains(ip) {
+            bail!("{} is outside {}", ip, MESH_PREFIX);
+        }
+        Ok(TcpStream::connect((ip, port)).await?.into())
+    }
+}
+
+impl TunnelInterface for YggdrasilAdapter {
+    /// Named by Yggdrasil itself (`IfName = "auto"`)
+    fn interfaces(&self) -> Vec<String> {
+        vec!["auto".to_string()]
+    }
+
+    fn prefixes(&self) -> Vec<IpPrefix> {
+        vec![MESH_PREFIX]
     }
 }
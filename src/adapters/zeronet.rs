@@ -1,4 +1,4 @@
-use super::ProtocolAdapter;
+use super::{endpoint, Endpoint, ProtocolAdapter};
 use crate::config::ZeroNetSettings;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -69,9 +69,10 @@ impl ProtocolAdapter for ZeroNetAdapter {
         Ok(())
     }
 
-    fn get_proxy_addr(&self) -> String {
-        // ZeroNet uses HTTP proxy
-        self.settings.proxy_url.clone()
+    fn endpoint(&self) -> Endpoint {
+        endpoint::url_addr(&self.settings.proxy_url)
+            .map(Endpoint::HttpGateway)
+            .unwrap_or(Endpoint::None)
     }
 
     async fn is_healthy(&self) -> bool {
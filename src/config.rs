@@ -1,5 +1,7 @@
-use config::{Config, ConfigError, File};
-use serde::Deserialize;
+use config::builder::DefaultState;
+use config::{Config, ConfigBuilder, ConfigError, File};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
 
 /// Protocol chaining mode for multi-hop routing
 #[derive(Debug, Deserialize, Clone, Default, PartialEq)]
@@ -8,9 +10,73 @@ pub enum ChainMode {
     #[default]
     None,
     TorOverNym,
+    /// Refused at startup: the Nym SDK can't send its gateway connection
+    /// through a proxy
     NymOverTor,
 }
 
+/// How SOCKS streams are separated onto distinct Tor circuits
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IsolationMode {
+    /// All streams share circuits (plain no-auth handshake to Tor)
+    #[default]
+    None,
+    /// One circuit group per SOCKS username presented by the client
+    SocksUsername,
+    /// One circuit group per client IP address
+    ClientAddress,
+    /// One circuit group per destination host
+    DestinationDomain,
+    /// One circuit group per Chimera listener
+    Listener,
+}
+
+/// How connections are spread across a pool of Tor instances
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TorBalance {
+    /// Healthy instance with the fewest open connections
+    #[default]
+    LeastConnections,
+    /// Same destination always maps to the same healthy instance
+    ConsistentHash,
+}
+
+/// Whether Chimera runs a network's daemon or uses one already running
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdapterMode {
+    /// Spawn the daemon from `binary_path`
+    #[default]
+    Managed,
+    /// Attach to a daemon answering at the configured ports; never spawn
+    External,
+    /// Attach when something answers, otherwise spawn
+    Auto,
+}
+
+/// A port number, or `"auto"` (read as 0) for a free port picked at
+/// startup by `ports::allocate`
+fn port_or_auto<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Port(u16),
+        Text(String),
+    }
+    match Raw::deserialize(deserializer)? {
+        Raw::Port(port) => Ok(port),
+        Raw::Text(text) if text.eq_ignore_ascii_case("auto") => Ok(0),
+        Raw::Text(text) => text.trim().parse().map_err(|_| {
+            serde::de::Error::custom(format!(
+                "invalid port {:?}: expected a number or \"auto\"",
+                text
+            ))
+        }),
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub server: ServerSettings,
@@ -25,6 +91,74 @@ pub struct Settings {
     pub retroshare: RetroShareSettings,
     pub gnunet: GnunetSettings,
     pub tribler: TriblerSettings,
+    pub v2ray: ProxyClientSettings,
+    pub trojan: ProxyClientSettings,
+    pub outline: ProxyClientSettings,
+    pub psiphon: ProxyClientSettings,
+    pub mysterium: ProxyClientSettings,
+    pub lantern: ProxyClientSettings,
+    pub sentinel: ProxyClientSettings,
+    pub wireguard: WireGuardSettings,
+    pub yggdrasil: MeshSettings,
+    pub cjdns: MeshSettings,
+    pub session: SessionSettings,
+    pub obfs4: Obfs4Settings,
+    pub meek: MeekSettings,
+    pub identity: IdentitySettings,
+    /// Managed pluggable transports (obfs4proxy, lyrebird, meek, ...)
+    #[serde(default)]
+    pub pluggable_transports: Vec<PtSettings>,
+    /// Opt-in relaying for other users of the networks
+    #[serde(default)]
+    pub contribute: ContributeSettings,
+    /// Chains built by Chimera with nested SOCKS5 CONNECTs, nearest hop
+    /// first: a SOCKS network ("tor", "nym", ...) or "socks5://host:port"
+    #[serde(default)]
+    pub chains: HashMap<String, Vec<String>>,
+    /// Destinations sent through a named chain; the first match wins
+    #[serde(default)]
+    pub routes: Vec<RouteRule>,
+    /// Readiness timeouts for dependency-ordered startup
+    #[serde(default)]
+    pub startup: StartupSettings,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct StartupSettings {
+    /// Seconds an adapter may take to start and pass its readiness check
+    pub timeout_secs: u64,
+    /// Per-network overrides, e.g. `tor = 300`
+    pub timeouts: HashMap<String, u64>,
+}
+
+impl Default for StartupSettings {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 90,
+            timeouts: HashMap::new(),
+        }
+    }
+}
+
+impl StartupSettings {
+    /// Startup timeout of a network, by its lower-case name
+    pub fn timeout_for(&self, network: &str) -> std::time::Duration {
+        std::time::Duration::from_secs(
+            self.timeouts
+                .get(network)
+                .copied()
+                .unwrap_or(self.timeout_secs),
+        )
+    }
+}
+
+/// Send matching destinations through one of `chains`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct RouteRule {
+    /// Exact host, "*.example.com" (the domain and its subdomains) or "*"
+    pub host: String,
+    pub chain: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -32,48 +166,307 @@ pub struct ServerSettings {
     pub host: String,
     pub port: u16,
     pub log_level: String,
+    /// Local control port (loopback only) used by the CLI and other tools
+    pub control_port: u16,
+    /// Fresh random cookie written here (owner-only) at every start; clients
+    /// authenticate by sending it
+    pub control_cookie_file: String,
+    /// Also accept this password (`AUTHENTICATE "<password>"`)
+    pub control_password: Option<String>,
+}
+
+/// "New identity" policy
+#[derive(Debug, Deserialize, Clone)]
+pub struct IdentitySettings {
+    /// Request a new identity every N seconds (0 disables the timer)
+    pub rotate_interval_secs: u64,
+    /// Also close existing connections when the identity changes
+    pub close_connections: bool,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct TorSettings {
     pub enabled: bool,
     pub binary_path: String,
+    pub mode: AdapterMode,
+    #[serde(deserialize_with = "port_or_auto")]
     pub socks_port: u16,
+    #[serde(deserialize_with = "port_or_auto")]
     pub control_port: u16,
     /// Optional upstream SOCKS5 proxy (for chaining, e.g., Nym)
     pub upstream_proxy: Option<String>,
     /// Fallback protocol if Tor is unhealthy
     pub fallback_protocol: Option<String>,
+    /// Bridge lines (e.g. "obfs4 1.2.3.4:443 FINGERPRINT cert=... iat-mode=0").
+    /// When set, Tor only connects through these bridges using the managed
+    /// pluggable transports.
+    pub bridges: Vec<String>,
+    /// Stream isolation mode, implemented through SOCKS5 username/password
+    /// pairs which Tor isolates on by default (IsolateSOCKSAuth)
+    pub isolation: IsolationMode,
+    /// ClientOnionAuthDir holding v3 client authorization keys
+    pub client_auth_dir: String,
+    /// Number of tor processes to run. Instance `n` uses `socks_port + 2n`,
+    /// `control_port + 2n` and its own data directory.
+    pub instances: usize,
+    /// Load-balancing strategy when `instances` > 1
+    pub balance: TorBalance,
+}
+
+/// A pluggable transport binary launched as a PT 1.0 managed proxy
+#[derive(Debug, Deserialize, Clone)]
+pub struct PtSettings {
+    pub binary_path: String,
+    /// Client transports requested via TOR_PT_CLIENT_TRANSPORTS (e.g. ["obfs4"])
+    pub transports: Vec<String>,
+    /// State directory (TOR_PT_STATE_LOCATION). Defaults to `data/pt/<binary name>`.
+    #[serde(default)]
+    pub state_dir: Option<String>,
+    /// Upstream proxy URI passed via TOR_PT_PROXY (e.g. "socks5://127.0.0.1:1080")
+    #[serde(default)]
+    pub upstream_proxy: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct I2pSettings {
     pub enabled: bool,
     pub binary_path: String,
+    pub mode: AdapterMode,
+    #[serde(deserialize_with = "port_or_auto")]
     pub socks_port: u16,
+    #[serde(deserialize_with = "port_or_auto")]
     pub http_proxy_port: u16,
+    /// SAM v3 bridge (TCP) for native streams and datagrams
+    #[serde(deserialize_with = "port_or_auto")]
+    pub sam_port: u16,
+    /// SAM UDP port datagrams are sent through
+    #[serde(deserialize_with = "port_or_auto")]
+    pub sam_udp_port: u16,
+    /// I2PControl JSON-RPC endpoint (HTTPS, loopback only)
+    #[serde(deserialize_with = "port_or_auto")]
+    pub i2pcontrol_port: u16,
+    /// Empty: a random password for each i2pd Chimera starts. Set it to
+    /// reach an external router's I2PControl.
+    pub i2pcontrol_password: String,
+    /// Local services published on I2P
+    #[serde(default)]
+    pub server_tunnels: Vec<I2pServerTunnel>,
+    #[serde(default)]
+    pub addressbook: I2pAddressBookSettings,
+}
+
+/// Chimera-side I2P name resolution (`[i2p.addressbook]`)
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct I2pAddressBookSettings {
+    /// hosts.txt subscription URLs, fetched through i2pd's HTTP proxy
+    pub subscriptions: Vec<String>,
+    /// Jump service URL prefix the name is appended to,
+    /// e.g. "http://stats.i2p/cgi-bin/jump.cgi?a="
+    pub jump_service: Option<String>,
+    /// Local names: "name.i2p" = "<b32 address or base64 destination>"
+    pub petnames: HashMap<String, String>,
+    pub refresh_interval_secs: u64,
+}
+
+impl Default for I2pAddressBookSettings {
+    fn default() -> Self {
+        Self {
+            subscriptions: Vec::new(),
+            jump_service: None,
+            petnames: HashMap::new(),
+            refresh_interval_secs: 12 * 60 * 60,
+        }
+    }
+}
+
+/// i2pd tunnel flavour for a published service
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum I2pTunnelKind {
+    /// Plain TCP service
+    #[default]
+    Server,
+    /// Eepsite: i2pd rewrites the Host header for the local web server
+    Http,
+}
+
+/// One `[[i2p.server_tunnels]]` entry, rendered into i2pd's tunnels.conf
+#[derive(Debug, Deserialize, Clone)]
+pub struct I2pServerTunnel {
+    pub name: String,
+    /// Local service, e.g. "127.0.0.1:8080"
+    pub target: String,
+    #[serde(default)]
+    pub kind: I2pTunnelKind,
+    #[serde(default = "default_i2p_tunnel_length")]
+    pub inbound_length: u8,
+    #[serde(default = "default_i2p_tunnel_length")]
+    pub outbound_length: u8,
+    /// Destination keys, relative to the I2P data directory. Defaults to
+    /// `keys/<name>.dat`; i2pd creates the file on first start.
+    #[serde(default)]
+    pub key_file: Option<String>,
+}
+
+fn default_i2p_tunnel_length() -> u8 {
+    3
+}
+
+/// Resources Chimera gives back to the networks (`[contribute]`). Nothing is
+/// relayed unless `enabled` is set.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ContributeSettings {
+    pub enabled: bool,
+    /// Average relayed bandwidth in KB/s (0 = unlimited)
+    pub bandwidth_kbps: u32,
+    /// Short-term burst in KB/s where the daemon supports one (0 = same as
+    /// `bandwidth_kbps`)
+    pub burst_kbps: u32,
+    /// Concurrent relayed tunnels/clients where the daemon supports a limit
+    /// (0 = daemon default)
+    pub max_connections: u32,
+    pub tor: TorContributeSettings,
+    pub i2p: I2pContributeSettings,
+    pub snowflake: SnowflakeContributeSettings,
+}
+
+/// What the primary Tor instance relays
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TorRelayRole {
+    #[default]
+    None,
+    /// Unlisted bridge for censored users
+    Bridge,
+    /// Public non-exit relay
+    Relay,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TorContributeSettings {
+    pub role: TorRelayRole,
+    /// ORPort; must be reachable from the internet
+    pub or_port: u16,
+    pub nickname: String,
+    pub contact_info: Option<String>,
+    /// Server pluggable transport for bridges, e.g. "obfs4 bin/lyrebird"
+    pub bridge_transport: Option<String>,
+    /// Public port of the bridge transport
+    pub bridge_transport_port: u16,
+}
+
+impl Default for TorContributeSettings {
+    fn default() -> Self {
+        Self {
+            role: TorRelayRole::None,
+            or_port: 9001,
+            nickname: "ChimeraNode".to_string(),
+            contact_info: None,
+            bridge_transport: None,
+            bridge_transport_port: 9443,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct I2pContributeSettings {
+    /// Accept participating (transit) tunnels
+    pub transit: bool,
+    pub floodfill: bool,
+    /// Share of the bandwidth limit available to transit traffic, in percent
+    pub share_percent: u8,
+}
+
+impl Default for I2pContributeSettings {
+    fn default() -> Self {
+        Self {
+            transit: true,
+            floodfill: false,
+            share_percent: 80,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SnowflakeContributeSettings {
+    pub enabled: bool,
+    pub binary_path: String,
+    pub broker_url: String,
+}
+
+impl Default for SnowflakeContributeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            binary_path: if cfg!(target_os = "windows") {
+                "bin/snowflake-proxy.exe".to_string()
+            } else {
+                "bin/snowflake-proxy".to_string()
+            },
+            broker_url: "https://snowflake-broker.torproject.net/".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct LokinetSettings {
     pub enabled: bool,
     pub binary_path: String,
+    pub mode: AdapterMode,
+    /// Lokinet's DNS resolver listens on 127.0.0.1:<dns_port>
+    #[serde(deserialize_with = "port_or_auto")]
     pub dns_port: u16,
-    pub socks_port: u16,
+    /// Exit node for clearnet traffic ("<address>.loki")
+    pub exit_node: Option<String>,
+    /// Auth token the exit node requires
+    pub exit_auth: Option<String>,
+    /// Resolvers for non-Lokinet names
+    pub upstream_dns: Vec<String>,
+    /// Bootstrap RC file (`bootstrap.signed`); Lokinet's default when unset
+    pub bootstrap: Option<String>,
+    /// Hand-edited lokinet.ini merged under the generated keys
+    pub config_file: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct NymSettings {
     pub enabled: bool,
     pub binary_path: String,
+    #[serde(deserialize_with = "port_or_auto")]
     pub socks_port: u16,
+    /// Pinned network requester; tried before any directory entry
     pub upstream_provider: Option<String>,
+    /// Network requester directory (http(s) URL or JSON file)
+    pub directory_url: Option<String>,
+    /// Last directory fetched, used when the source is unreachable
+    pub directory_cache: String,
+    /// Minimum measured performance (0.0-1.0) of an automatic pick
+    pub min_performance: f64,
+    /// Only pick requesters running Nym's exit policy (open proxy)
+    pub require_exit_policy: bool,
+    /// Ports the requester's exit policy must allow, if it advertises ports
+    pub required_ports: Vec<u16>,
+    /// host:port reached through the SOCKS5 client to check it still relays
+    pub probe_target: String,
+    pub probe_interval_secs: u64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct IpfsSettings {
     pub enabled: bool,
+    /// `external` (default) uses a running Kubo node; `managed` and `auto`
+    /// may run `ipfs daemon` from `binary_path` on its own repository
+    pub mode: AdapterMode,
+    pub binary_path: String,
+    /// IPFS_PATH of the managed node
+    pub repo_path: String,
     pub api_url: String,
+    #[serde(deserialize_with = "port_or_auto")]
     pub gateway_port: u16,
 }
 
@@ -81,6 +474,7 @@ pub struct IpfsSettings {
 pub struct ZeroNetSettings {
     pub enabled: bool,
     pub proxy_url: String,
+    /// Unused; the proxy listens on the port in `proxy_url`
     pub port: u16,
 }
 
@@ -103,6 +497,10 @@ pub struct RetroShareSettings {
 #[derive(Debug, Deserialize, Clone)]
 pub struct GnunetSettings {
     pub enabled: bool,
+    pub mode: AdapterMode,
+    /// gnunet-arm, which starts and stops GNUnet's services
+    pub binary_path: String,
+    /// gnunet-gns-proxy's SOCKS port, as set in gnunet.conf
     pub socks_port: u16,
 }
 
@@ -113,44 +511,141 @@ pub struct TriblerSettings {
     pub api_key: Option<String>,
 }
 
+/// A proxy client exposing a local SOCKS5 port (V2Ray, Trojan, Outline,
+/// Psiphon, Mysterium, Lantern, Sentinel)
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProxyClientSettings {
+    pub enabled: bool,
+    pub binary_path: String,
+    pub mode: AdapterMode,
+    /// Client configuration file; Mysterium, Lantern and Sentinel take none
+    pub config_path: Option<String>,
+    /// `"auto"` only for Outline, Psiphon and Lantern, which take the port
+    /// on their command line
+    #[serde(deserialize_with = "port_or_auto")]
+    pub socks_port: u16,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WireGuardSettings {
+    pub enabled: bool,
+    /// wg-quick or a compatible tool taking `up|down <config>`
+    pub binary_path: String,
+    pub mode: AdapterMode,
+    pub interface: String,
+    pub config_path: Option<String>,
+}
+
+/// Yggdrasil and cjdns mesh routers
+#[derive(Debug, Deserialize, Clone)]
+pub struct MeshSettings {
+    pub enabled: bool,
+    pub binary_path: String,
+    pub mode: AdapterMode,
+    pub config_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SessionSettings {
+    pub enabled: bool,
+    /// "external"/"auto" look for a running process of `binary_path`'s name
+    pub mode: AdapterMode,
+    pub binary_path: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Obfs4Settings {
+    pub enabled: bool,
+    /// obfs4proxy or lyrebird
+    pub binary_path: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MeekSettings {
+    pub enabled: bool,
+    pub binary_path: String,
+    pub front_domain: String,
+}
+
 impl Settings {
     pub fn new() -> Result<Self, ConfigError> {
+        Self::builder()?
+            // Merge with config file
+            .add_source(File::with_name("chimera").required(false))
+            // Merge with environment variables (e.g. CHIMERA_SERVER_PORT=9090)
+            .add_source(config::Environment::with_prefix("CHIMERA").separator("__"))
+            .build()?
+            .try_deserialize()
+    }
+
+    /// Built-in defaults only, ignoring `chimera.toml` and `CHIMERA__*`
+    /// (for tests, which must not depend on the local setup)
+    pub fn defaults() -> Result<Self, ConfigError> {
+        Self::builder()?.build()?.try_deserialize()
+    }
+
+    /// Builder holding every default, before any source is merged
+    fn builder() -> Result<ConfigBuilder<DefaultState>, ConfigError> {
         let s = Config::builder()
             // Start with default values
             .set_default("server.host", "127.0.0.1")?
             .set_default("server.port", 9050)?
             .set_default("server.log_level", "info")?
+            .set_default("server.control_port", 9060)?
+            .set_default("server.control_cookie_file", "data/control_auth_cookie")?
+            .set_default("server.control_password", None::<String>)?
+            .set_default("identity.rotate_interval_secs", 0)?
+            .set_default("identity.close_connections", false)?
             // Phase 4: Protocol Chaining
             .set_default("chain_mode", "none")?
-            .set_default("tor.enabled", true)?;
+            .set_default("tor.enabled", true)?
+            .set_default("tor.mode", "managed")?;
 
         #[cfg(target_os = "windows")]
         let s = s.set_default("tor.binary_path", "bin/tor.exe")?;
         #[cfg(not(target_os = "windows"))]
         let s = s.set_default("tor.binary_path", "bin/tor")?;
 
-        let s = s.set_default("tor.socks_port", 9052)?
+        let s = s
+            .set_default("tor.socks_port", 9052)?
             .set_default("tor.control_port", 9051)?
             .set_default("tor.upstream_proxy", None::<String>)?
             .set_default("tor.fallback_protocol", None::<String>)?
-            .set_default("i2p.enabled", true)?;
+            .set_default("tor.bridges", Vec::<String>::new())?
+            .set_default("tor.isolation", "none")?
+            .set_default("tor.client_auth_dir", "data/tor/onion_auth")?
+            .set_default("tor.instances", 1)?
+            .set_default("tor.balance", "least_connections")?
+            .set_default("i2p.enabled", true)?
+            .set_default("i2p.mode", "managed")?;
 
         #[cfg(target_os = "windows")]
         let s = s.set_default("i2p.binary_path", "bin/i2pd.exe")?;
         #[cfg(not(target_os = "windows"))]
         let s = s.set_default("i2p.binary_path", "bin/i2pd")?;
 
-        let s = s.set_default("i2p.socks_port", 4447)?
+        let s = s
+            .set_default("i2p.socks_port", 4447)?
             .set_default("i2p.http_proxy_port", 4444)?
-            .set_default("lokinet.enabled", false)?;
+            .set_default("i2p.sam_port", 7656)?
+            .set_default("i2p.sam_udp_port", 7655)?
+            .set_default("i2p.i2pcontrol_port", 7650)?
+            .set_default("i2p.i2pcontrol_password", "")?
+            .set_default("lokinet.enabled", false)?
+            .set_default("lokinet.mode", "managed")?;
 
         #[cfg(target_os = "windows")]
         let s = s.set_default("lokinet.binary_path", "bin/lokinet.exe")?;
         #[cfg(not(target_os = "windows"))]
         let s = s.set_default("lokinet.binary_path", "bin/lokinet")?;
 
-        let s = s.set_default("lokinet.dns_port", 1053)?
-            .set_default("lokinet.socks_port", 1090)?
+        let s = s
+            .set_default("lokinet.dns_port", 1053)?
+            .set_default("lokinet.exit_node", None::<String>)?
+            .set_default("lokinet.exit_auth", None::<String>)?
+            .set_default("lokinet.upstream_dns", vec!["9.9.9.10"])?
+            .set_default("lokinet.bootstrap", None::<String>)?
+            .set_default("lokinet.config_file", "chimera.lokinet.ini")?
             .set_default("nym.enabled", false)?;
 
         #[cfg(target_os = "windows")]
@@ -158,10 +653,21 @@ impl Settings {
         #[cfg(not(target_os = "windows"))]
         let s = s.set_default("nym.binary_path", "bin/nym-socks5-client")?;
 
-        let s = s.set_default("nym.socks_port", 1080)?
+        let s = s
+            .set_default("nym.socks_port", 1080)?
             .set_default("nym.upstream_provider", None::<String>)?
+            .set_default("nym.directory_url", None::<String>)?
+            .set_default("nym.directory_cache", "data/nym/requesters.json")?
+            .set_default("nym.min_performance", 0.8)?
+            .set_default("nym.require_exit_policy", true)?
+            .set_default("nym.required_ports", vec![80, 443])?
+            .set_default("nym.probe_target", "nymtech.net:443")?
+            .set_default("nym.probe_interval_secs", 120)?
             // Phase 2.5: Decentralized Web
             .set_default("ipfs.enabled", false)?
+            .set_default("ipfs.mode", "external")?
+            .set_default("ipfs.binary_path", "ipfs")?
+            .set_default("ipfs.repo_path", "data/ipfs")?
             .set_default("ipfs.api_url", "http://127.0.0.1:5001")?
             .set_default("ipfs.gateway_port", 8080)?
             .set_default("zeronet.enabled", false)?
@@ -177,16 +683,96 @@ impl Settings {
             .set_default("retroshare.user", None::<String>)?
             .set_default("retroshare.password", None::<String>)?
             .set_default("gnunet.enabled", false)?
+            .set_default("gnunet.mode", "managed")?
+            .set_default("gnunet.binary_path", "gnunet-arm")?
             .set_default("gnunet.socks_port", 2080)? // Placeholder default
             .set_default("tribler.enabled", false)?
             .set_default("tribler.api_url", "http://127.0.0.1:8085")?
             .set_default("tribler.api_key", None::<String>)?
-            // Merge with config file
-            .add_source(File::with_name("chimera").required(false))
-            // Merge with environment variables (e.g. CHIMERA_SERVER_PORT=9090)
-            .add_source(config::Environment::with_prefix("CHIMERA").separator("__"))
-            .build()?;
+            // Proxy clients
+            .set_default("v2ray.enabled", false)?
+            .set_default("v2ray.mode", "managed")?
+            .set_default("v2ray.binary_path", "bin/v2ray")?
+            .set_default("v2ray.config_path", None::<String>)?
+            .set_default("v2ray.socks_port", 10808)?
+            .set_default("trojan.enabled", false)?
+            .set_default("trojan.mode", "managed")?
+            .set_default("trojan.binary_path", "bin/trojan-go")?
+            .set_default("trojan.config_path", None::<String>)?
+            .set_default("trojan.socks_port", 10809)?
+            .set_default("outline.enabled", false)?
+            .set_default("outline.mode", "managed")?
+            .set_default("outline.binary_path", "bin/outline-cli")?
+            .set_default("outline.config_path", None::<String>)?
+            .set_default("outline.socks_port", 10810)?
+            .set_default("psiphon.enabled", false)?
+            .set_default("psiphon.mode", "managed")?
+            .set_default("psiphon.binary_path", "bin/psiphon-tunnel-core")?
+            .set_default("psiphon.config_path", None::<String>)?
+            .set_default("psiphon.socks_port", 10811)?
+            .set_default("mysterium.enabled", false)?
+            .set_default("mysterium.mode", "managed")?
+            .set_default("mysterium.binary_path", "bin/myst")?
+            .set_default("mysterium.config_path", None::<String>)?
+            .set_default("mysterium.socks_port", 10812)?
+            .set_default("lantern.enabled", false)?
+            .set_default("lantern.mode", "managed")?
+            .set_default("lantern.binary_path", "bin/lantern")?
+            .set_default("lantern.config_path", None::<String>)?
+            .set_default("lantern.socks_port", 10813)?
+            .set_default("sentinel.enabled", false)?
+            .set_default("sentinel.mode", "managed")?
+            .set_default("sentinel.binary_path", "bin/sentinelcli")?
+            .set_default("sentinel.config_path", None::<String>)?
+            .set_default("sentinel.socks_port", 10814)?
+            // VPN and mesh interfaces
+            .set_default("wireguard.enabled", false)?
+            .set_default("wireguard.mode", "managed")?
+            .set_default("wireguard.binary_path", "wg-quick")?
+            .set_default("wireguard.interface", "wg0")?
+            .set_default("wireguard.config_path", None::<String>)?
+            .set_default("yggdrasil.enabled", false)?
+            .set_default("yggdrasil.mode", "managed")?
+            .set_default("yggdrasil.binary_path", "bin/yggdrasil")?
+            .set_default("yggdrasil.config_path", None::<String>)?
+            .set_default("cjdns.enabled", false)?
+            .set_default("cjdns.mode", "managed")?
+            .set_default("cjdns.binary_path", "bin/cjdroute")?
+            .set_default("cjdns.config_path", None::<String>)?
+            // Messaging and transports
+            .set_default("session.enabled", false)?
+            .set_default("session.mode", "managed")?
+            .set_default("session.binary_path", "bin/session")?
+            .set_default("obfs4.enabled", false)?
+            .set_default("obfs4.binary_path", "bin/lyrebird")?
+            .set_default("meek.enabled", false)?
+            .set_default("meek.binary_path", "bin/meek-client")?
+            .set_default("meek.front_domain", "ajax.aspnetcdn.com")?;
+
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::FileFormat;
+
+    fn parse(toml: &str) -> Settings {
+        Settings::builder()
+            .unwrap()
+            .add_source(File::from_str(toml, FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
 
-        s.try_deserialize()
+    #[test]
+    fn test_auto_ports() {
+        let s = parse("[nym]\nsocks_port = \"auto\"\n[i2p]\nsam_port = \"AUTO\"\n");
+        assert_eq!(s.nym.socks_port, 0);
+        assert_eq!(s.i2p.sam_port, 0);
+        assert_eq!(parse("[nym]\nsocks_port = 1090\n").nym.socks_port, 1090);
     }
 }
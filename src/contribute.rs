@@ -0,0 +1,283 @@
+//! Contribution mode: translate `[contribute]` into each daemon's native
+//! relay options and collect per-network contribution statistics.
+//!
+//! | network   | role                    | bandwidth cap                     | connection cap           |
+//! |-----------|-------------------------|-----------------------------------|--------------------------|
+//! | Tor       | bridge / non-exit relay | RelayBandwidthRate/Burst          | none in Tor              |
+//! | I2P       | transit, floodfill      | `bandwidth` (KB/s) and `share`    | `limits.transittunnels`  |
+//! | Snowflake | WebRTC proxy            | none in the proxy                 | `-capacity`              |
+
+use crate::adapters::tor::control::TorControl;
+use crate::adapters::tor::TorAdapter;
+use crate::config::{ContributeSettings, TorRelayRole, TorSettings};
+use crate::stats::Stats;
+use anyhow::{bail, Result};
+use log::debug;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Extra tor command-line options for the primary instance
+pub fn tor_args(c: &ContributeSettings) -> Vec<String> {
+    let tor = &c.tor;
+    if !c.enabled || tor.role == TorRelayRole::None {
+        return Vec::new();
+    }
+    let mut args = vec![
+        "--ORPort".to_string(),
+        tor.or_port.to_string(),
+        "--Nickname".to_string(),
+        tor.nickname.clone(),
+        // Never an exit: relayed traffic stays inside the Tor network
+        "--ExitRelay".to_string(),
+        "0".to_string(),
+        "--ExitPolicy".to_string(),
+        "reject *:*".to_string(),
+    ];
+    if let Some(contact) = &tor.contact_info {
+        args.extend(["--ContactInfo".to_string(), contact.clone()]);
+    }
+    if tor.role == TorRelayRole::Bridge {
+        args.extend(["--BridgeRelay".to_string(), "1".to_string()]);
+        if let Some(transport) = &tor.bridge_transport {
+            let (name, binary) = transport.split_once(' ').unwrap_or((transport, ""));
+            args.extend([
+                "--ServerTransportPlugin".to_string(),
+                format!("{} exec {}", name, binary.trim()),
+                "--ServerTransportListenAddr".to_string(),
+                format!("{} 0.0.0.0:{}", name, tor.bridge_transport_port),
+                "--ExtORPort".to_string(),
+                "auto".to_string(),
+            ]);
+        }
+    }
+    if c.bandwidth_kbps > 0 {
+        let burst = c.burst_kbps.max(c.bandwidth_kbps);
+        args.extend([
+            "--RelayBandwidthRate".to_string(),
+            format!("{} KBytes", c.bandwidth_kbps),
+            "--RelayBandwidthBurst".to_string(),
+            format!("{} KBytes", burst),
+        ]);
+    }
+    args
+}
+
+/// Tor refuses to relay while it reaches the network through bridges or an
+/// upstream proxy
+pub fn check_tor(settings: &TorSettings, upstream: Option<&str>) -> Result<()> {
+    if !settings.bridges.is_empty() {
+        bail!("a Tor relay or bridge cannot use tor.bridges");
+    }
+    if upstream.is_some() {
+        bail!("a Tor relay or bridge cannot run behind tor.upstream_proxy (chain_mode)");
+    }
+    Ok(())
+}
+
+/// Extra i2pd command-line options
+pub fn i2pd_args(c: &ContributeSettings) -> Vec<String> {
+    if !c.enabled {
+        return Vec::new();
+    }
+    let i2p = &c.i2p;
+    let mut args = vec![
+        format!("--notransit={}", !i2p.transit),
+        format!("--floodfill={}", i2p.floodfill),
+        format!("--share={}", i2p.share_percent.min(100)),
+    ];
+    if c.bandwidth_kbps > 0 {
+        args.push(format!("--bandwidth={}", c.bandwidth_kbps));
+    }
+    if c.max_connections > 0 {
+        args.push(format!("--limits.transittunnels={}", c.max_connections));
+    }
+    args
+}
+
+/// Extra snowflake proxy options
+pub fn snowflake_args(c: &ContributeSettings) -> Vec<String> {
+    if c.max_connections > 0 {
+        vec!["-capacity".to_string(), c.max_connections.to_string()]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Relay statistics of the primary Tor instance
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TorRelayStats {
+    pub role: String,
+    pub or_port: u16,
+    pub fingerprint: Option<String>,
+    /// Tor's self-test found the ORPort reachable
+    pub reachable: bool,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+pub async fn tor_relay_stats(
+    control: &mut TorControl,
+    c: &ContributeSettings,
+) -> Result<TorRelayStats> {
+    let number = |v: String| v.trim().parse::<u64>().unwrap_or(0);
+    Ok(TorRelayStats {
+        role: format!("{:?}", c.tor.role).to_lowercase(),
+        or_port: c.tor.or_port,
+        // Not known until Tor has generated its relay keys
+        fingerprint: control.get_info("fingerprint").await.ok(),
+        reachable: control
+            .get_info("status/reachability-succeeded/or")
+            .await
+            .is_ok_and(|v| v == "1"),
+        bytes_read: number(control.get_info("traffic/read").await?),
+        bytes_written: number(control.get_info("traffic/written").await?),
+    })
+}
+
+/// Publish Tor relay statistics as `networks.tor` every `interval`
+pub async fn run_tor_relay_stats(
+    adapter: Arc<TorAdapter>,
+    settings: ContributeSettings,
+    stats: Arc<Stats>,
+    interval: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let result = match adapter.control().await {
+            Ok(mut control) => tor_relay_stats(&mut control, &settings).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(relay) => stats.record_network("tor", &relay),
+            Err(e) => debug!("Tor relay statistics unavailable: {}", e),
+        }
+    }
+}
+
+/// Totals from the snowflake proxy's periodic summaries
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SnowflakeStats {
+    pub connections: u64,
+    pub bytes_down: u64,
+    pub bytes_up: u64,
+}
+
+impl SnowflakeStats {
+    /// Add one summary line ("In the last 1h0m0s, there were 3 completed
+    /// successful connections. Traffic Relayed ↓ 120 KB (...), ↑ 15 KB (...).");
+    /// returns false for other log lines
+    pub fn add_summary(&mut self, line: &str) -> bool {
+        let Some(rest) = line.split_once("there were ").map(|(_, r)| r) else {
+            return false;
+        };
+        let Some(connections) = rest
+            .split_whitespace()
+            .next()
+            .and_then(|n| n.parse::<u64>().ok())
+        else {
+            return false;
+        };
+        self.connections += connections;
+        self.bytes_down += traffic_after(rest, '↓');
+        self.bytes_up += traffic_after(rest, '↑');
+        true
+    }
+}
+
+/// "<number> <unit>" following `arrow`, in bytes
+fn traffic_after(text: &str, arrow: char) -> u64 {
+    let Some((_, rest)) = text.split_once(arrow) else {
+        return 0;
+    };
+    let mut words = rest.split_whitespace();
+    let amount: f64 = words.next().and_then(|n| n.parse().ok()).unwrap_or(0.0);
+    let unit = words.next().unwrap_or("B").trim_end_matches([',', '.']);
+    let scale: u64 = match unit {
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        "TB" => 1 << 40,
+        _ => 1,
+    };
+    (amount * scale as f64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled() -> ContributeSettings {
+        ContributeSettings {
+            enabled: true,
+            bandwidth_kbps: 500,
+            max_connections: 40,
+            ..Default::default()
+        }
+    }
+
+    fn value_of<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+        args.iter()
+            .position(|a| a == flag)
+            .map(|i| args[i + 1].as_str())
+    }
+
+    #[test]
+    fn test_nothing_without_opt_in() {
+        let mut c = enabled();
+        c.enabled = false;
+        c.tor.role = TorRelayRole::Relay;
+        assert!(tor_args(&c).is_empty());
+        assert!(i2pd_args(&c).is_empty());
+        assert!(tor_args(&enabled()).is_empty());
+    }
+
+    #[test]
+    fn test_tor_bridge_args() {
+        let mut c = enabled();
+        c.tor.role = TorRelayRole::Bridge;
+        c.tor.bridge_transport = Some("obfs4 bin/lyrebird".to_string());
+        let args = tor_args(&c);
+        assert_eq!(value_of(&args, "--BridgeRelay"), Some("1"));
+        assert_eq!(value_of(&args, "--ExitPolicy"), Some("reject *:*"));
+        assert_eq!(
+            value_of(&args, "--ServerTransportPlugin"),
+            Some("obfs4 exec bin/lyrebird")
+        );
+        assert_eq!(
+            value_of(&args, "--ServerTransportListenAddr"),
+            Some("obfs4 0.0.0.0:9443")
+        );
+        assert_eq!(value_of(&args, "--RelayBandwidthRate"), Some("500 KBytes"));
+        // Burst may not be below the rate
+        assert_eq!(value_of(&args, "--RelayBandwidthBurst"), Some("500 KBytes"));
+    }
+
+    #[test]
+    fn test_i2pd_and_snowflake_caps() {
+        let mut c = enabled();
+        c.i2p.floodfill = true;
+        let args = i2pd_args(&c);
+        assert!(args.contains(&"--notransit=false".to_string()));
+        assert!(args.contains(&"--floodfill=true".to_string()));
+        assert!(args.contains(&"--bandwidth=500".to_string()));
+        assert!(args.contains(&"--limits.transittunnels=40".to_string()));
+        assert_eq!(snowflake_args(&c), ["-capacity", "40"]);
+    }
+
+    #[test]
+    fn test_snowflake_summaries() {
+        let mut stats = SnowflakeStats::default();
+        assert!(stats.add_summary(
+            "2024/05/01 10:00:00 In the last 1h0m0s, there were 3 completed successful connections. \
+             Traffic Relayed ↓ 120 KB (0.03 KB/s), ↑ 2 MB (0.57 KB/s)."
+        ));
+        assert!(stats.add_summary(
+            "In the last 1h0m0s, there were 1 connections. Traffic Relayed ↑ 512 B, ↓ 1 KB."
+        ));
+        assert!(!stats.add_summary("Proxy starting"));
+        assert_eq!(stats.connections, 4);
+        assert_eq!(stats.bytes_down, 121 * 1024);
+        assert_eq!(stats.bytes_up, 2 * 1024 * 1024 + 512);
+    }
+}
@@ -0,0 +1,396 @@
+//! Local control surface.
+//!
+//! A small line-based protocol in the style of Tor's control port, bound to
+//! loopback only. Replies use `250` for success, `510` for unknown commands
+//! and `550` for failures; multi-line replies use `250-` continuation lines.
+//!
+//! As with Tor, the first line must be `AUTHENTICATE <hex cookie>` (the
+//! cookie is written to `server.control_cookie_file` at startup) or
+//! `AUTHENTICATE "<password>"` when `server.control_password` is set. Any
+//! other first line, such as a browser's cross-protocol HTTP request,
+//! closes the connection.
+//!
+//! Commands:
+//! - `NEWIDENTITY [CLOSE]`
+//! - `STATUS`
+//! - `STATS` (JSON counters, including failure reasons)
+//! - `ONIONAUTH LIST | ADD <auth_private line> | REMOVE <onion>`
+//! - `EXPLAIN <host>` (how a destination would be routed)
+//! - `I2PTUNNELS` (published server tunnels and their `.b32.i2p` addresses)
+//! - `I2PROUTER RESTART | RESEED` (graceful restart or reseed via I2PControl)
+//! - `QUIT`
+
+use crate::adapters::i2p::i2pcontrol::RouterCommand;
+use crate::adapters::tor::onion_auth::OnionAuthKey;
+use crate::config::ServerSettings;
+use crate::identity::{new_identity, NewIdentityOptions};
+use crate::process_manager::ProcessManager;
+use crate::socks5::Socks5Server;
+use anyhow::{anyhow, Context, Result};
+use data_encoding::{HEXLOWER, HEXLOWER_PERMISSIVE};
+use log::{debug, info};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+const COOKIE_LEN: usize = 32;
+
+/// HTTP methods a browser may send in a cross-protocol request
+const HTTP_METHODS: [&str; 8] = [
+    "GET", "POST", "PUT", "HEAD", "OPTIONS", "DELETE", "PATCH", "CONNECT",
+];
+
+/// Cookie or password a client presents before any other command
+#[derive(Clone)]
+pub struct ControlAuth {
+    cookie: Vec<u8>,
+    password: Option<String>,
+}
+
+impl ControlAuth {
+    /// Fresh random cookie; `password` is accepted as well
+    pub fn new(password: Option<String>) -> Result<Self> {
+        let mut cookie = vec![0u8; COOKIE_LEN];
+        getrandom::getrandom(&mut cookie)
+            .map_err(|e| anyhow!("no randomness for the control cookie: {}", e))?;
+        Ok(Self { cookie, password })
+    }
+
+    /// Node side: a fresh cookie, written to `control_cookie_file` readable
+    /// only by the current user
+    pub fn create(settings: &ServerSettings) -> Result<Self> {
+        let auth = Self::new(settings.control_password.clone())?;
+        let path = Path::new(&settings.control_cookie_file);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, &auth.cookie).with_context(|| format!("writing {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(auth)
+    }
+
+    /// Client side: the configured password, or else the running node's
+    /// cookie
+    pub fn load(settings: &ServerSettings) -> Result<Self> {
+        if let Some(password) = &settings.control_password {
+            return Ok(Self {
+                cookie: Vec::new(),
+                password: Some(password.clone()),
+            });
+        }
+        let cookie = fs::read(&settings.control_cookie_file).with_context(|| {
+            format!(
+                "reading {} (is the node running?)",
+                settings.control_cookie_file
+            )
+        })?;
+        Ok(Self {
+            cookie,
+            password: None,
+        })
+    }
+
+    /// Argument to send with `AUTHENTICATE`
+    fn credential(&self) -> String {
+        match &self.password {
+            Some(password) => format!(
+                "\"{}\"",
+                password.replace('\\', "\\\\").replace('"', "\\\"")
+            ),
+            None => HEXLOWER.encode(&self.cookie),
+        }
+    }
+
+    /// Whether `AUTHENTICATE <arg>` proves the client may use the port
+    fn accepts(&self, arg: &str) -> bool {
+        let arg = arg.trim();
+        if let Some(quoted) = arg.strip_prefix('"').and_then(|a| a.strip_suffix('"')) {
+            let mut given = String::new();
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                given.extend(if c == '\\' { chars.next() } else { Some(c) });
+            }
+            return self
+                .password
+                .as_ref()
+                .is_some_and(|p| constant_time_eq(p.as_bytes(), given.as_bytes()));
+        }
+        !self.cookie.is_empty()
+            && HEXLOWER_PERMISSIVE
+                .decode(arg.as_bytes())
+                .is_ok_and(|cookie| constant_time_eq(&cookie, &self.cookie))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn looks_like_http(line: &str) -> bool {
+    let method = line.split_whitespace().next().unwrap_or("");
+    HTTP_METHODS.contains(&method) || line.contains(" HTTP/")
+}
+
+#[derive(Clone)]
+pub struct ControlServer {
+    port: u16,
+    process_manager: Arc<ProcessManager>,
+    socks5: Socks5Server,
+    auth: ControlAuth,
+}
+
+impl ControlServer {
+    pub fn new(
+        port: u16,
+        process_manager: Arc<ProcessManager>,
+        socks5: Socks5Server,
+        auth: ControlAuth,
+    ) -> Self {
+        Self {
+            port,
+            process_manager,
+            socks5,
+            auth,
+        }
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        let addr = SocketAddr::from(([127, 0, 0, 1], self.port));
+        let listener = TcpListener::bind(addr).await?;
+        info!("🎛️ Control port listening on {}", listener.local_addr()?);
+        self.serve(listener).await
+    }
+
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        loop {
+            let (socket, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_client(socket).await {
+                    debug!("Control connection error: {}", e);
+                }
+            });
+        }
+    }
+
+    async fn handle_client(&self, socket: TcpStream) -> Result<()> {
+        let (rx, mut tx) = socket.into_split();
+        let mut lines = BufReader::new(rx).lines();
+
+        let Some(first) = lines.next_line().await? else {
+            return Ok(());
+        };
+        match first.trim().split_once(' ') {
+            Some((command, arg))
+                if command.eq_ignore_ascii_case("AUTHENTICATE") && self.auth.accepts(arg) =>
+            {
+                tx.write_all(b"250 OK\r\n").await?;
+            }
+            _ if looks_like_http(&first) => {
+                debug!("Closing control connection that looks like HTTP");
+                return Ok(());
+            }
+            _ => {
+                tx.write_all(b"515 Authentication failed\r\n").await?;
+                return Ok(());
+            }
+        }
+
+        while let Some(line) = lines.next_line().await? {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.eq_ignore_ascii_case("QUIT") {
+                tx.write_all(b"250 closing connection\r\n").await?;
+                break;
+            }
+            for reply in self.dispatch(line).await {
+                tx.write_all(format!("{}\r\n", reply).as_bytes()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute one command and return its reply lines
+    pub async fn dispatch(&self, line: &str) -> Vec<String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("").to_ascii_uppercase();
+        let raw_args: Vec<&str> = words.collect();
+        let args: Vec<String> = raw_args.iter().map(|w| w.to_ascii_uppercase()).collect();
+
+        match command.as_str() {
+            "NEWIDENTITY" => {
+                let options = NewIdentityOptions {
+                    close_connections: args.iter().any(|a| a == "CLOSE"),
+                };
+                let report = new_identity(&self.process_manager, &self.socks5, options).await;
+                if report.failed.is_empty() {
+                    vec![format!("250-{}", report), "250 OK".to_string()]
+                } else {
+                    vec![format!("550 New identity incomplete: {}", report)]
+                }
+            }
+            "STATUS" => {
+                let health = self.process_manager.health_state.read().await;
+                let mut entries: Vec<String> = health
+                    .iter()
+                    .map(|(p, up)| {
+                        format!(
+                            "250-{}={}",
+                            format!("{:?}", p).to_lowercase(),
+                            if *up { "up" } else { "down" }
+                        )
+                    })
+                    .collect();
+                entries.sort();
+                let tor = self.process_manager.tor();
+                if tor.len() > 1 {
+                    for inst in tor.status() {
+                        entries.push(format!(
+                            "250-tor.{}={} socks={} connections={}",
+                            inst.index,
+                            if inst.healthy { "up" } else { "down" },
+                            inst.socks_addr,
+                            inst.active_connections
+                        ));
+                    }
+                }
+                if let Some(provider) = self.process_manager.nym().provider().await {
+                    entries.push(format!("250-nym.provider={}", provider));
+                }
+                for (protocol, _, readiness) in self.process_manager.readiness().entries {
+                    entries.push(format!(
+                        "250-startup.{}={}",
+                        format!("{:?}", protocol).to_lowercase(),
+                        readiness
+                    ));
+                }
+                // Actual addresses, including ports allocated with "auto"
+                for entry in self.process_manager.registry().enabled() {
+                    entries.push(format!(
+                        "250-endpoint.{}={}",
+                        format!("{:?}", entry.info.protocol).to_lowercase(),
+                        entry.endpoint()
+                    ));
+                }
+                entries.push(format!(
+                    "250-connections={}",
+                    self.socks5.active_connections()
+                ));
+                entries.push("250 OK".to_string());
+                entries
+            }
+            "ONIONAUTH" => self.onion_auth(&raw_args).await,
+            "EXPLAIN" => match raw_args.first() {
+                Some(host) => {
+                    let plan = self.socks5.plan_route(host).await;
+                    let mut lines = vec![format!("250-{}", plan)];
+                    lines.extend(plan.steps.iter().map(|s| format!("250-  {}", s)));
+                    lines.push("250 OK".to_string());
+                    lines
+                }
+                None => vec!["512 Usage: EXPLAIN <host>".to_string()],
+            },
+            "I2PTUNNELS" => {
+                let mut lines: Vec<String> = self
+                    .process_manager
+                    .i2p()
+                    .server_tunnel_addresses()
+                    .into_iter()
+                    .map(|(name, addr)| {
+                        format!("250-{}={}", name, addr.as_deref().unwrap_or("pending"))
+                    })
+                    .collect();
+                lines.push("250 OK".to_string());
+                lines
+            }
+            "I2PROUTER" => {
+                let command = match args.first().map(String::as_str) {
+                    Some("RESTART") => RouterCommand::RestartGraceful,
+                    Some("RESEED") => RouterCommand::Reseed,
+                    _ => return vec!["512 Usage: I2PROUTER RESTART | RESEED".to_string()],
+                };
+                match self.process_manager.i2p().router_command(command).await {
+                    Ok(()) => vec!["250 OK".to_string()],
+                    Err(e) => vec![format!("550 {}", e)],
+                }
+            }
+            "STATS" => match serde_json::to_string(&self.socks5.stats().snapshot()) {
+                Ok(json) => vec![format!("250-{}", json), "250 OK".to_string()],
+                Err(e) => vec![format!("550 {}", e)],
+            },
+            _ => vec![format!("510 Unrecognized command \"{}\"", command)],
+        }
+    }
+
+    /// `ONIONAUTH` subcommands; keys and onion addresses keep their case
+    async fn onion_auth(&self, args: &[&str]) -> Vec<String> {
+        let tor = self.process_manager.tor();
+        let result = match args {
+            [sub] if sub.eq_ignore_ascii_case("LIST") => tor.onion_auth().list().map(|onions| {
+                onions
+                    .into_iter()
+                    .map(|o| format!("250-{}.onion", o))
+                    .collect::<Vec<_>>()
+            }),
+            [sub, line] if sub.eq_ignore_ascii_case("ADD") => match OnionAuthKey::parse(line) {
+                Ok(key) => tor
+                    .add_client_auth(&key)
+                    .await
+                    .map(|_| vec![format!("250-added={}.onion", key.onion)]),
+                Err(e) => Err(e),
+            },
+            [sub, onion] if sub.eq_ignore_ascii_case("REMOVE") => tor
+                .remove_client_auth(onion)
+                .await
+                .map(|removed| vec![format!("250-removed={}", removed)]),
+            _ => return vec!["512 Usage: ONIONAUTH LIST | ADD <key> | REMOVE <onion>".to_string()],
+        };
+
+        match result {
+            Ok(mut lines) => {
+                lines.push("250 OK".to_string());
+                lines
+            }
+            Err(e) => vec![format!("550 {}", e)],
+        }
+    }
+}
+
+/// Authenticate to a running node's control port, send one command and
+/// return the reply lines. Fails if the final line is not a `250`.
+pub async fn send_command(port: u16, auth: &ControlAuth, command: &str) -> Result<Vec<String>> {
+    let stream = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).await?;
+    let (rx, mut tx) = stream.into_split();
+    tx.write_all(format!("AUTHENTICATE {}\r\n{}\r\n", auth.credential(), command).as_bytes())
+        .await?;
+
+    let mut lines = BufReader::new(rx).lines();
+    match lines.next_line().await? {
+        Some(line) if line.starts_with("250") => {}
+        Some(line) => return Err(anyhow!("Control authentication failed: {}", line)),
+        None => return Err(anyhow!("Control port closed without a reply")),
+    }
+    let mut reply = Vec::new();
+    while let Some(line) = lines.next_line().await? {
+        let done = line.as_bytes().get(3) == Some(&b' ');
+        reply.push(line);
+        if done {
+            break;
+        }
+    }
+
+    match reply.last() {
+        Some(last) if last.starts_with("250") => Ok(reply),
+        Some(last) => Err(anyhow!("Control command failed: {}", last)),
+        None => Err(anyhow!("Control port closed without a reply")),
+    }
+}
@@ -18,15 +18,28 @@ pub enum Protocol {
     GnuNet,
     RetroShare,
     Tribler,
+    // Proxy clients with a local SOCKS5 port
+    V2Ray,
+    Trojan,
+    Outline,
+    Psiphon,
+    Mysterium,
+    Lantern,
+    Sentinel,
+    // VPN and mesh interfaces
+    WireGuard,
+    Yggdrasil,
+    Cjdns,
+    // Messaging
+    Session,
+    // Transports
+    Snowflake,
+    Obfs4,
+    Meek,
 }
 
-/// Shared health state map
-pub type HealthState = Arc<RwLock<HashMap<Protocol, bool>>>;
-
-/// Creates a new health state with all protocols marked unhealthy initially
-pub fn new_health_state() -> HealthState {
-    let mut map = HashMap::new();
-    for protocol in [
+impl Protocol {
+    pub const ALL: [Protocol; 24] = [
         Protocol::Tor,
         Protocol::I2p,
         Protocol::Nym,
@@ -37,14 +50,69 @@ pub fn new_health_state() -> HealthState {
         Protocol::GnuNet,
         Protocol::RetroShare,
         Protocol::Tribler,
-    ] {
-        map.insert(protocol, false);
+        Protocol::V2Ray,
+        Protocol::Trojan,
+        Protocol::Outline,
+        Protocol::Psiphon,
+        Protocol::Mysterium,
+        Protocol::Lantern,
+        Protocol::Sentinel,
+        Protocol::WireGuard,
+        Protocol::Yggdrasil,
+        Protocol::Cjdns,
+        Protocol::Session,
+        Protocol::Snowflake,
+        Protocol::Obfs4,
+        Protocol::Meek,
+    ];
+}
+
+impl std::str::FromStr for Protocol {
+    type Err = anyhow::Error;
+
+    /// Lower-case network name as used in the config ("tor", "gnunet", ...)
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "tor" => Protocol::Tor,
+            "i2p" => Protocol::I2p,
+            "nym" => Protocol::Nym,
+            "lokinet" => Protocol::Lokinet,
+            "ipfs" => Protocol::Ipfs,
+            "zeronet" => Protocol::ZeroNet,
+            "freenet" => Protocol::Freenet,
+            "gnunet" => Protocol::GnuNet,
+            "retroshare" => Protocol::RetroShare,
+            "tribler" => Protocol::Tribler,
+            "v2ray" => Protocol::V2Ray,
+            "trojan" => Protocol::Trojan,
+            "outline" => Protocol::Outline,
+            "psiphon" => Protocol::Psiphon,
+            "mysterium" => Protocol::Mysterium,
+            "lantern" => Protocol::Lantern,
+            "sentinel" => Protocol::Sentinel,
+            "wireguard" => Protocol::WireGuard,
+            "yggdrasil" => Protocol::Yggdrasil,
+            "cjdns" => Protocol::Cjdns,
+            "session" => Protocol::Session,
+            "snowflake" => Protocol::Snowflake,
+            "obfs4" => Protocol::Obfs4,
+            "meek" => Protocol::Meek,
+            _ => anyhow::bail!("unknown network {:?}", name),
+        })
     }
+}
+
+/// Shared health state map
+pub type HealthState = Arc<RwLock<HashMap<Protocol, bool>>>;
+
+/// Creates a new health state with all protocols marked unhealthy initially
+pub fn new_health_state() -> HealthState {
+    let map = Protocol::ALL.iter().map(|&p| (p, false)).collect();
     Arc::new(RwLock::new(map))
 }
 
 /// Background health monitor that periodically checks adapter health
-pub async fn run_health_monitor<T: ProtocolAdapter + Send + Sync + 'static>(
+pub async fn run_health_monitor<T: ProtocolAdapter + Send + Sync + ?Sized + 'static>(
     protocol: Protocol,
     adapter: Arc<T>,
     state: HealthState,
@@ -67,3 +135,18 @@ pub async fn run_health_monitor<T: ProtocolAdapter + Send + Sync + 'static>(
         tokio::time::sleep(interval).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_round_trip() {
+        // Config sections, chain hops and STATUS all use the lower-case name
+        for protocol in Protocol::ALL {
+            let name = format!("{:?}", protocol).to_lowercase();
+            assert_eq!(name.parse::<Protocol>().unwrap(), protocol);
+        }
+        assert!("wireguard0".parse::<Protocol>().is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! "New identity" across all networks.
+//!
+//! Mirrors Tor Browser's New Identity button: Tor gets NEWNYM, the Nym client
+//! discards its stored keys and reconnects with a fresh identity,
+//! stream-isolation tokens are rotated and, optionally, existing connections
+//! are closed.
+
+use crate::config::IdentitySettings;
+use crate::health_monitor::Protocol;
+use crate::process_manager::ProcessManager;
+use crate::socks5::Socks5Server;
+use log::{info, warn};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NewIdentityOptions {
+    /// Tear down open client connections as well
+    pub close_connections: bool,
+}
+
+/// Outcome of a new-identity request
+#[derive(Debug, Default)]
+pub struct IdentityReport {
+    pub rotated: Vec<Protocol>,
+    pub failed: Vec<(Protocol, String)>,
+    pub isolation_epoch: u64,
+    pub closed_connections: usize,
+}
+
+impl fmt::Display for IdentityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rotated={:?} isolation_epoch={} closed_connections={}",
+            self.rotated, self.isolation_epoch, self.closed_connections
+        )?;
+        for (protocol, error) in &self.failed {
+            write!(f, " failed[{:?}]=\"{}\"", protocol, error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Switch every network to a new identity
+pub async fn new_identity(
+    pm: &ProcessManager,
+    server: &Socks5Server,
+    options: NewIdentityOptions,
+) -> IdentityReport {
+    let mut report = IdentityReport::default();
+
+    for (protocol, result) in pm.new_identity().await {
+        match result {
+            Ok(()) => report.rotated.push(protocol),
+            Err(e) => {
+                warn!("New identity failed for {:?}: {}", protocol, e);
+                report.failed.push((protocol, e.to_string()));
+            }
+        }
+    }
+
+    report.isolation_epoch = server.isolator().rotate();
+    if options.close_connections {
+        report.closed_connections = server.close_connections();
+    }
+
+    info!("New identity: {}", report);
+    report
+}
+
+/// Request a new identity on a fixed timer (no-op when the interval is 0)
+pub async fn run_identity_schedule(
+    pm: Arc<ProcessManager>,
+    server: Socks5Server,
+    settings: IdentitySettings,
+) {
+    if settings.rotate_interval_secs == 0 {
+        return;
+    }
+
+    let interval = Duration::from_secs(settings.rotate_interval_secs);
+    info!("New identity scheduled every {:?}", interval);
+    let options = NewIdentityOptions {
+        close_connections: settings.close_connections,
+    };
+
+    loop {
+        tokio::time::sleep(interval).await;
+        new_identity(&pm, &server, options).await;
+    }
+}
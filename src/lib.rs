@@ -1,5 +1,17 @@
 pub mod adapters;
 pub mod config;
+pub mod contribute;
+pub mod control;
 pub mod health_monitor;
+pub mod identity;
+pub mod isolation;
+pub mod ports;
 pub mod process_manager;
+pub mod pt_manager;
+pub mod registry;
 pub mod socks5;
+pub mod startup;
+pub mod stats;
+
+#[cfg(test)]
+mod test_support;
@@ -1,10 +1,16 @@
+use chimera_node::adapters::tor::onion_auth::OnionAuthStore;
 use chimera_node::config::Settings;
+use chimera_node::control::{self, ControlAuth, ControlServer};
 use chimera_node::health_monitor::Protocol;
+use chimera_node::identity::run_identity_schedule;
+use chimera_node::ports;
 use chimera_node::process_manager::ProcessManager;
 use chimera_node::socks5::Socks5Server;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use log::{error, info};
 use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -12,12 +18,48 @@ struct Args {
     /// Path to configuration file
     #[arg(short, long, default_value = "chimera.toml")]
     config: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the Super Node (default)
+    Run,
+    /// Ask a running node for a new identity on all networks
+    NewIdentity {
+        /// Also close existing connections
+        #[arg(long)]
+        close_connections: bool,
+    },
+    /// Ask a running node how it would route a destination
+    Explain { host: String },
+    /// Manage v3 onion service client authorization keys
+    OnionAuth {
+        #[command(subcommand)]
+        action: OnionAuthAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum OnionAuthAction {
+    /// Import keys from an .auth_private file
+    Import { file: PathBuf },
+    /// Print the .auth_private line for an onion address
+    Export { onion: String },
+    /// List onion addresses that have credentials
+    List,
+    /// Delete the credentials for an onion address
+    Remove { onion: String },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
     // 1. Load Configuration
-    let settings = match Settings::new() {
+    let mut settings = match Settings::new() {
         Ok(s) => s,
         Err(e) => {
             eprintln!("Failed to load configuration: {}", e);
@@ -35,58 +77,80 @@ async fn main() -> anyhow::Result<()> {
     }
     env_logger::init();
 
+    if let Some(Command::NewIdentity { close_connections }) = args.command {
+        let command = if close_connections {
+            "NEWIDENTITY CLOSE"
+        } else {
+            "NEWIDENTITY"
+        };
+        let auth = ControlAuth::load(&settings.server)?;
+        for line in control::send_command(settings.server.control_port, &auth, command).await? {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Explain { host }) = &args.command {
+        let auth = ControlAuth::load(&settings.server)?;
+        let command = format!("EXPLAIN {}", host);
+        for line in control::send_command(settings.server.control_port, &auth, &command).await? {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::OnionAuth { action }) = args.command {
+        return onion_auth(&settings, action).await;
+    }
+
     info!("🦁 Chimera Super Node starting...");
     info!(
         "Listening on {}:{}",
         settings.server.host, settings.server.port
     );
 
-    // 3. Start Sidecar Processes (Tor, I2P, Nym, Lokinet, IPFS, ZeroNet, Freenet)
-    // For standalone binary, we enable ALL protocols by default for now
-    let mut enabled_protocols = HashSet::new();
-    enabled_protocols.insert(Protocol::Tor);
-    enabled_protocols.insert(Protocol::I2p);
-    enabled_protocols.insert(Protocol::Nym);
-    enabled_protocols.insert(Protocol::Lokinet);
-    enabled_protocols.insert(Protocol::Ipfs);
-    enabled_protocols.insert(Protocol::ZeroNet);
-    enabled_protocols.insert(Protocol::Freenet);
-    enabled_protocols.insert(Protocol::RetroShare);
-    enabled_protocols.insert(Protocol::GnuNet);
-    enabled_protocols.insert(Protocol::Tribler);
-
-    let pm = ProcessManager::new(
-        settings.chain_mode.clone(),
-        enabled_protocols,
-        settings.tor.clone(),
-        settings.i2p.clone(),
-        settings.nym.clone(),
-        settings.lokinet.clone(),
-        settings.ipfs.clone(),
-        settings.zeronet.clone(),
-        settings.freenet.clone(),
-        settings.retroshare.clone(),
-        settings.gnunet.clone(),
-        settings.tribler.clone(),
-    );
+    // 3. Start Sidecar Processes
+    // For standalone binary, we enable ALL protocols by default for now; each
+    // section's `enabled` still decides what starts
+    let enabled_protocols: HashSet<Protocol> = Protocol::ALL.into_iter().collect();
+
+    // Resolve "auto" ports and refuse to start on a conflict
+    ports::allocate(&mut settings, &enabled_protocols)?;
+
+    let pm = ProcessManager::new(&settings, enabled_protocols);
+    let pm = Arc::new(pm);
+
+    // 4. Build the SOCKS5 proxy first: a bad chain or route fails here,
+    // before any sidecar is spawned
+    let server = Socks5Server::new(settings.server.port)
+        .with_registry(pm.registry())
+        .with_isolation(settings.tor.isolation)
+        .with_tor_pool(pm.tor())
+        .with_addressbook(pm.i2p().addressbook())
+        .with_stats(pm.stats())
+        .with_chains(&settings.chains, &settings.routes)?;
+
     if let Err(e) = pm.start_processes().await {
         error!("Failed to start background processes: {}", e);
     }
 
-    // 4. Start SOCKS5 Proxy
-    let server = Socks5Server::new(
-        settings.server.port,
-        settings.tor.socks_port,
-        settings.i2p.socks_port,
-        settings.lokinet.socks_port,
-        settings.nym.socks_port,
-        settings.ipfs.gateway_port,
-        settings.zeronet.port,
-        settings.freenet.fproxy_port,
-        settings.gnunet.socks_port,
-        settings.retroshare.api_url,
-        settings.tribler.api_url,
+    // 5. Control surface and scheduled identity rotation
+    let control = ControlServer::new(
+        settings.server.control_port,
+        pm.clone(),
+        server.clone(),
+        ControlAuth::create(&settings.server)?,
     );
+    tokio::spawn(async move {
+        if let Err(e) = control.run().await {
+            error!("Control port failed: {}", e);
+        }
+    });
+    tokio::spawn(run_identity_schedule(
+        pm.clone(),
+        server.clone(),
+        settings.identity.clone(),
+    ));
 
     if let Err(e) = server.run().await {
         error!("SOCKS5 Server crashed: {}", e);
@@ -94,3 +158,45 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Edit the key store directly; a running node also gets the change through
+/// its control port so Tor applies it without a restart
+async fn onion_auth(settings: &Settings, action: OnionAuthAction) -> anyhow::Result<()> {
+    let store = OnionAuthStore::new(&settings.tor.client_auth_dir);
+    let port = settings.server.control_port;
+    // No cookie means no running node to notify
+    let auth = ControlAuth::load(&settings.server).ok();
+
+    match action {
+        OnionAuthAction::Import { file } => {
+            for key in store.import_file(&file)? {
+                println!("Imported {}.onion", key.onion);
+                let command = format!("ONIONAUTH ADD {}", key.to_auth_private());
+                let sent = match &auth {
+                    Some(auth) => control::send_command(port, auth, &command).await.is_ok(),
+                    None => false,
+                };
+                if !sent {
+                    info!("Node not reachable; key applies on next Tor start");
+                }
+            }
+        }
+        OnionAuthAction::Export { onion } => println!("{}", store.export(&onion)?),
+        OnionAuthAction::List => {
+            for onion in store.list()? {
+                println!("{}.onion", onion);
+            }
+        }
+        OnionAuthAction::Remove { onion } => {
+            if !store.remove(&onion)? {
+                return Err(anyhow::anyhow!("no client authorization key for {}", onion));
+            }
+            if let Some(auth) = &auth {
+                let command = format!("ONIONAUTH REMOVE {}", onion);
+                let _ = control::send_command(port, auth, &command).await;
+            }
+            println!("Removed {}", onion);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,508 @@
+//! Port allocation and conflict detection.
+//!
+//! Runs once before the adapters are built. Ports set to `"auto"` get a
+//! free loopback port, written back into the settings so endpoints, routing
+//! and `STATUS` see the real one. Explicit ports must not be shared and,
+//! where Chimera binds them itself or spawns the daemon that does, must not
+//! already be bound by another process.
+
+use crate::adapters::endpoint;
+use crate::config::{AdapterMode, Settings};
+use crate::health_monitor::Protocol;
+use anyhow::{anyhow, bail, Result};
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::{IpAddr, TcpListener, UdpSocket};
+
+/// Tries at finding a free port (or run of ports) for an `"auto"` setting
+const PICK_ATTEMPTS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+/// A port Chimera or one of its daemons listens on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortUse {
+    /// Setting it comes from, e.g. `tor.socks_port`
+    pub key: String,
+    pub port: u16,
+    pub transport: Transport,
+    /// Picked at startup because the setting was `"auto"`
+    pub auto: bool,
+}
+
+impl fmt::Display for PortUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.port)?;
+        if self.transport == Transport::Udp {
+            write!(f, "/udp")?;
+        }
+        if self.auto {
+            write!(f, " (auto)")?;
+        }
+        Ok(())
+    }
+}
+
+/// One port setting of an enabled network
+struct Slot<'a> {
+    key: &'static str,
+    port: &'a mut u16,
+    transport: Transport,
+    host: &'a str,
+    /// Run of ports `port + stride * i` (Tor instances)
+    count: u16,
+    stride: u16,
+    mode: AdapterMode,
+    /// The daemon is told the port, so `"auto"` can work
+    auto_ok: bool,
+}
+
+impl<'a> Slot<'a> {
+    fn new(key: &'static str, port: &'a mut u16, mode: AdapterMode, auto_ok: bool) -> Self {
+        Self {
+            key,
+            port,
+            transport: Transport::Tcp,
+            host: "127.0.0.1",
+            count: 1,
+            stride: 1,
+            mode,
+            auto_ok,
+        }
+    }
+
+    fn udp(mut self) -> Self {
+        self.transport = Transport::Udp;
+        self
+    }
+
+    fn ports(&self, base: u16) -> Result<Vec<u16>> {
+        (0..self.count)
+            .map(|i| {
+                base.checked_add(i * self.stride).ok_or_else(|| {
+                    anyhow!(
+                        "{} = {} runs past port 65535 with {} instances",
+                        self.key,
+                        base,
+                        self.count
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+/// Resolve every `"auto"` port of the networks about to start and reject
+/// explicit conflicts. Returns the ports in use, auto-picked ones marked.
+pub fn allocate(settings: &mut Settings, enabled: &HashSet<Protocol>) -> Result<Vec<PortUse>> {
+    let on = |protocol: Protocol, section: bool| section && enabled.contains(&protocol);
+    let (managed, external) = (AdapterMode::Managed, AdapterMode::External);
+    let s = settings;
+    // Ports given inside URLs; a URL can't say "auto"
+    let mut ipfs_api = local_url_port(&s.ipfs.api_url);
+    let mut zeronet_proxy = local_url_port(&s.zeronet.proxy_url);
+    let mut slots = Vec::new();
+
+    slots.push(Slot {
+        host: &s.server.host,
+        ..Slot::new("server.port", &mut s.server.port, managed, false)
+    });
+    slots.push(Slot::new(
+        "server.control_port",
+        &mut s.server.control_port,
+        managed,
+        false,
+    ));
+    if on(Protocol::Tor, s.tor.enabled) {
+        let (mode, count) = (s.tor.mode, s.tor.instances.max(1) as u16);
+        for (key, port) in [
+            ("tor.socks_port", &mut s.tor.socks_port),
+            ("tor.control_port", &mut s.tor.control_port),
+        ] {
+            slots.push(Slot {
+                count,
+                stride: 2,
+                ..Slot::new(key, port, mode, true)
+            });
+        }
+    }
+    if on(Protocol::I2p, s.i2p.enabled) {
+        let mode = s.i2p.mode;
+        slots.push(Slot::new(
+            "i2p.socks_port",
+            &mut s.i2p.socks_port,
+            mode,
+            true,
+        ));
+        slots.push(Slot::new(
+            "i2p.http_proxy_port",
+            &mut s.i2p.http_proxy_port,
+            mode,
+            true,
+        ));
+        slots.push(Slot::new("i2p.sam_port", &mut s.i2p.sam_port, mode, true));
+        slots.push(Slot::new("i2p.sam_udp_port", &mut s.i2p.sam_udp_port, mode, true).udp());
+        slots.push(Slot::new(
+            "i2p.i2pcontrol_port",
+            &mut s.i2p.i2pcontrol_port,
+            mode,
+            true,
+        ));
+    }
+    if on(Protocol::Lokinet, s.lokinet.enabled) {
+        let mode = s.lokinet.mode;
+        slots.push(Slot::new("lokinet.dns_port", &mut s.lokinet.dns_port, mode, true).udp());
+    }
+    if on(Protocol::Nym, s.nym.enabled) {
+        // The SDK is told where to listen
+        slots.push(Slot::new(
+            "nym.socks_port",
+            &mut s.nym.socks_port,
+            managed,
+            true,
+        ));
+    }
+    if on(Protocol::Ipfs, s.ipfs.enabled) {
+        // The gateway shares the API's host, so both are local or neither
+        if let Some(port) = ipfs_api.as_mut() {
+            let mode = s.ipfs.mode;
+            slots.push(Slot::new("ipfs.api_url", port, mode, false));
+            // A managed node is told its gateway address
+            slots.push(Slot::new(
+                "ipfs.gateway_port",
+                &mut s.ipfs.gateway_port,
+                mode,
+                true,
+            ));
+        }
+    }
+    if on(Protocol::ZeroNet, s.zeronet.enabled) {
+        if let Some(port) = zeronet_proxy.as_mut() {
+            slots.push(Slot::new("zeronet.proxy_url", port, external, false));
+        }
+    }
+    if on(Protocol::Freenet, s.freenet.enabled) && is_local(&s.freenet.host) {
+        slots.push(Slot::new(
+            "freenet.fcp_port",
+            &mut s.freenet.fcp_port,
+            external,
+            false,
+        ));
+        slots.push(Slot::new(
+            "freenet.fproxy_port",
+            &mut s.freenet.fproxy_port,
+            external,
+            false,
+        ));
+    }
+    if on(Protocol::GnuNet, s.gnunet.enabled) {
+        // gnunet-gns-proxy takes its port from gnunet.conf
+        slots.push(Slot::new(
+            "gnunet.socks_port",
+            &mut s.gnunet.socks_port,
+            s.gnunet.mode,
+            false,
+        ));
+    }
+    for (protocol, key, client) in [
+        (Protocol::V2Ray, "v2ray.socks_port", &mut s.v2ray),
+        (Protocol::Trojan, "trojan.socks_port", &mut s.trojan),
+        (Protocol::Outline, "outline.socks_port", &mut s.outline),
+        (Protocol::Psiphon, "psiphon.socks_port", &mut s.psiphon),
+        (
+            Protocol::Mysterium,
+            "mysterium.socks_port",
+            &mut s.mysterium,
+        ),
+        (Protocol::Lantern, "lantern.socks_port", &mut s.lantern),
+        (Protocol::Sentinel, "sentinel.socks_port", &mut s.sentinel),
+    ] {
+        if on(protocol, client.enabled) {
+            // Only these are given the port on their command line
+            let auto_ok = matches!(
+                protocol,
+                Protocol::Outline | Protocol::Psiphon | Protocol::Lantern
+            );
+            slots.push(Slot::new(key, &mut client.socks_port, client.mode, auto_ok));
+        }
+    }
+
+    resolve(slots)
+}
+
+/// Whether `host` names this machine, where its ports can clash with ours
+fn is_local(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Port of a URL pointing at this machine
+fn local_url_port(url: &str) -> Option<u16> {
+    endpoint::url_addr(url)
+        .filter(|addr| is_local(&addr.host))
+        .map(|addr| addr.port)
+}
+
+fn resolve(mut slots: Vec<Slot<'_>>) -> Result<Vec<PortUse>> {
+    // Explicit ports first: they may not be shared
+    let mut taken: HashMap<(Transport, u16), &'static str> = HashMap::new();
+    for slot in slots.iter().filter(|slot| *slot.port != 0) {
+        for port in slot.ports(*slot.port)? {
+            if let Some(other) = taken.insert((slot.transport, port), slot.key) {
+                bail!("{} and {} both use port {}", other, slot.key, port);
+            }
+        }
+    }
+
+    let mut uses = Vec::new();
+    for slot in &mut slots {
+        let auto = *slot.port == 0;
+        if auto {
+            if slot.key.starts_with("server.") {
+                bail!("{} can't be \"auto\": clients connect to it", slot.key);
+            }
+            if !slot.auto_ok {
+                bail!(
+                    "{} can't be \"auto\": the daemon reads its port from its own configuration",
+                    slot.key
+                );
+            }
+            if slot.mode == AdapterMode::External {
+                bail!(
+                    "{} can't be \"auto\" in external mode: give the port the daemon listens on",
+                    slot.key
+                );
+            }
+            let port = pick(slot, &taken)?;
+            for p in slot.ports(port)? {
+                taken.insert((slot.transport, p), slot.key);
+            }
+            *slot.port = port;
+            info!("{} = {} (auto)", slot.key, port);
+        } else if slot.mode == AdapterMode::Managed {
+            // External and auto modes expect a running daemon on the port
+            for port in slot.ports(*slot.port)? {
+                if !is_free(slot.transport, slot.host, port) {
+                    bail!(
+                        "{} = {} is already in use by another process{}",
+                        slot.key,
+                        port,
+                        hint(slot)
+                    );
+                }
+            }
+        }
+        uses.push(PortUse {
+            key: slot.key.to_string(),
+            port: *slot.port,
+            transport: slot.transport,
+            auto,
+        });
+    }
+    Ok(uses)
+}
+
+/// What to do about a port someone else holds
+fn hint(slot: &Slot<'_>) -> String {
+    let network = slot.key.split('.').next().unwrap_or_default();
+    match (network, slot.auto_ok) {
+        ("server", _) if *slot.port == 9050 => {
+            "; choose another port (a system tor usually listens on 9050)".to_string()
+        }
+        ("server", _) => "; choose another port".to_string(),
+        (_, true) => format!(
+            "; choose another port, set it to \"auto\", or set {}.mode = \"auto\" to use the daemon already running",
+            network
+        ),
+        (_, false) => format!(
+            "; choose another port, or set {}.mode = \"auto\" to use the daemon already running",
+            network
+        ),
+    }
+}
+
+/// Free base port for `slot`, with its whole run free and unclaimed
+fn pick(slot: &Slot<'_>, taken: &HashMap<(Transport, u16), &'static str>) -> Result<u16> {
+    for _ in 0..PICK_ATTEMPTS {
+        let base = match slot.transport {
+            Transport::Tcp => TcpListener::bind((slot.host, 0))?.local_addr()?.port(),
+            Transport::Udp => UdpSocket::bind((slot.host, 0))?.local_addr()?.port(),
+        };
+        let Ok(ports) = slot.ports(base) else {
+            continue;
+        };
+        if ports.iter().all(|&p| {
+            !taken.contains_key(&(slot.transport, p)) && is_free(slot.transport, slot.host, p)
+        }) {
+            return Ok(base);
+        }
+    }
+    bail!("no free port found for {}", slot.key)
+}
+
+fn is_free(transport: Transport, host: &str, port: u16) -> bool {
+    match transport {
+        Transport::Tcp => TcpListener::bind((host, port)).is_ok(),
+        Transport::Udp => UdpSocket::bind((host, port)).is_ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A port nothing listens on right now
+    fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    /// Defaults with Chimera's own listeners moved to free ports
+    fn settings() -> Settings {
+        let mut settings = Settings::defaults().unwrap();
+        settings.server.port = free_port();
+        settings.server.control_port = free_port();
+        settings
+    }
+
+    fn only(protocols: &[Protocol]) -> HashSet<Protocol> {
+        protocols.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_auto_ports() {
+        let mut s = settings();
+        s.tor.socks_port = 0;
+        s.tor.control_port = 0;
+        s.tor.instances = 3;
+        let uses = allocate(&mut s, &only(&[Protocol::Tor])).unwrap();
+
+        let socks = uses.iter().find(|u| u.key == "tor.socks_port").unwrap();
+        assert!(socks.auto);
+        assert_eq!(socks.port, s.tor.socks_port);
+        assert!(!uses.iter().find(|u| u.key == "server.port").unwrap().auto);
+
+        // Every instance gets distinct ports
+        let mut ports: Vec<u16> = (0..3)
+            .flat_map(|i| [s.tor.socks_port + 2 * i, s.tor.control_port + 2 * i])
+            .chain([s.server.port, s.server.control_port])
+            .collect();
+        ports.sort();
+        ports.dedup();
+        assert_eq!(ports.len(), 8);
+
+        s.server.port = 0;
+        let err = allocate(&mut s, &only(&[])).unwrap_err();
+        assert!(
+            err.to_string().contains("server.port can't be \"auto\""),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_explicit_conflicts() {
+        let mut s = settings();
+        s.server.port = 9050;
+        s.tor.socks_port = 9050;
+        let err = allocate(&mut s, &only(&[Protocol::Tor])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "server.port and tor.socks_port both use port 9050"
+        );
+
+        // The second instance's SOCKS port is the first one's control port
+        s.server.port = free_port();
+        s.tor.socks_port = 9052;
+        s.tor.control_port = 9054;
+        s.tor.instances = 2;
+        let err = allocate(&mut s, &only(&[Protocol::Tor])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tor.socks_port and tor.control_port both use port 9054"
+        );
+
+        // Across networks, unless one of them isn't starting
+        s.tor.instances = 1;
+        s.i2p.mode = AdapterMode::External;
+        s.i2p.socks_port = 9054;
+        let err = allocate(&mut s, &only(&[Protocol::Tor, Protocol::I2p])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tor.control_port and i2p.socks_port both use port 9054"
+        );
+        allocate(&mut s, &only(&[Protocol::I2p])).unwrap();
+    }
+
+    #[test]
+    fn test_ports_in_use() {
+        let held = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = held.local_addr().unwrap().port();
+
+        let mut s = settings();
+        s.server.port = port;
+        let err = allocate(&mut s, &only(&[])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "server.port = {} is already in use by another process; choose another port",
+                port
+            )
+        );
+
+        let mut s = settings();
+        s.v2ray.enabled = true;
+        s.v2ray.socks_port = port;
+        let err = allocate(&mut s, &only(&[Protocol::V2Ray])).unwrap_err();
+        assert!(err.to_string().contains("v2ray.mode = \"auto\""), "{}", err);
+
+        // A daemon already on the port is what auto and external modes want
+        s.v2ray.mode = AdapterMode::Auto;
+        allocate(&mut s, &only(&[Protocol::V2Ray])).unwrap();
+
+        s.v2ray.socks_port = 0;
+        let err = allocate(&mut s, &only(&[Protocol::V2Ray])).unwrap_err();
+        assert!(err.to_string().contains("own configuration"), "{}", err);
+    }
+
+    #[test]
+    fn test_ports_of_other_daemons() {
+        let mut s = settings();
+        s.freenet.enabled = true;
+        s.freenet.fproxy_port = s.server.port;
+        let err = allocate(&mut s, &only(&[Protocol::Freenet])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "server.port and freenet.fproxy_port both use port {}",
+                s.server.port
+            )
+        );
+        // A node on another machine can't clash with ours
+        s.freenet.host = "10.0.0.2".into();
+        allocate(&mut s, &only(&[Protocol::Freenet])).unwrap();
+
+        s.ipfs.enabled = true;
+        s.ipfs.api_url = format!("http://localhost:{}", s.server.control_port);
+        let err = allocate(&mut s, &only(&[Protocol::Ipfs])).unwrap_err();
+        assert!(err.to_string().contains("ipfs.api_url"), "{}", err);
+
+        // A managed node is given its gateway port
+        s.ipfs.mode = AdapterMode::Managed;
+        s.ipfs.api_url = format!("http://127.0.0.1:{}", free_port());
+        s.ipfs.gateway_port = 0;
+        let uses = allocate(&mut s, &only(&[Protocol::Ipfs])).unwrap();
+        let gateway = uses.iter().find(|u| u.key == "ipfs.gateway_port").unwrap();
+        assert!(gateway.auto);
+        assert_eq!(gateway.port, s.ipfs.gateway_port);
+    }
+}
@@ -1,176 +1,557 @@
 use crate::adapters::{
-    freenet::FreenetAdapter, gnunet::GnunetAdapter, i2p::I2pAdapter, ipfs::IpfsAdapter,
-    lokinet::LokinetAdapter, nym::NymAdapter, retroshare::RetroShareAdapter, tor::TorAdapter,
-    tribler::TriblerAdapter, zeronet::ZeroNetAdapter, ProtocolAdapter,
-};
-use crate::config::{
-    ChainMode, FreenetSettings, GnunetSettings, I2pSettings, IpfsSettings, LokinetSettings,
-    NymSettings, RetroShareSettings, TorSettings, TriblerSettings, ZeroNetSettings,
+    cjdns::CjdnsAdapter, freenet::FreenetAdapter, gnunet::GnunetAdapter, i2p::I2pAdapter,
+    ipfs::IpfsAdapter, lantern::LanternAdapter, lokinet::LokinetAdapter, meek::MeekAdapter,
+    mysterium::MysteriumAdapter, nym::NymAdapter, obfs4::Obfs4Adapter, outline::OutlineAdapter,
+    psiphon::PsiphonAdapter, retroshare::RetroShareAdapter, sentinel::SentinelAdapter,
+    session::SessionAdapter, snowflake::SnowflakeAdapter, tor::pool::TorPool,
+    tribler::TriblerAdapter, trojan::TrojanAdapter, v2ray::V2RayAdapter,
+    wireguard::WireGuardAdapter, yggdrasil::YggdrasilAdapter, zeronet::ZeroNetAdapter, Endpoint,
 };
+use crate::config::{ChainMode, ContributeSettings, Settings, StartupSettings, TorRelayRole};
+use crate::contribute;
 use crate::health_monitor::{new_health_state, run_health_monitor, HealthState, Protocol};
-use log::{error, info};
+use crate::pt_manager::PtManager;
+use crate::registry::{self, AdapterInfo, Entry, Registry};
+use crate::startup::{self, Gate, Node, Readiness, SharedAdapter, StartupReport};
+use crate::stats::Stats;
+use anyhow::{anyhow, bail};
+use log::{error, info, warn};
 use std::collections::HashSet;
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 pub struct ProcessManager {
     chain_mode: ChainMode,
-    tor_adapter: Arc<TorAdapter>,
+    /// Every network, started, monitored and routed to by iterating it
+    registry: Registry,
+    // Typed handles for network-specific features
+    tor_pool: Arc<TorPool>,
     i2p_adapter: Arc<I2pAdapter>,
     nym_adapter: Arc<NymAdapter>,
-    lokinet_adapter: Arc<LokinetAdapter>,
     ipfs_adapter: Arc<IpfsAdapter>,
-    zeronet_adapter: Arc<ZeroNetAdapter>,
-    freenet_adapter: Arc<FreenetAdapter>,
-    retroshare_adapter: Arc<RetroShareAdapter>,
-    gnunet_adapter: Arc<GnunetAdapter>,
-    tribler_adapter: Arc<TriblerAdapter>,
+    pt_manager: Arc<PtManager>,
+    contribute: ContributeSettings,
+    stats: Arc<Stats>,
     pub health_state: HealthState,
     enabled_protocols: HashSet<Protocol>,
+    startup: StartupSettings,
+    tor_upstream: Option<String>,
+    /// (chain, first-hop network) of every chain a route uses
+    routed: Vec<(String, Protocol)>,
+    readiness: Mutex<StartupReport>,
 }
 
 impl ProcessManager {
-    #[allow(clippy::too_many_arguments)]
-    pub fn new(
-        chain_mode: ChainMode,
-        enabled_protocols: HashSet<Protocol>,
-        tor: TorSettings,
-        i2p: I2pSettings,
-        nym: NymSettings,
-        lokinet: LokinetSettings,
-        ipfs: IpfsSettings,
-        zeronet: ZeroNetSettings,
-        freenet: FreenetSettings,
-        retroshare: RetroShareSettings,
-        gnunet: GnunetSettings,
-        tribler: TriblerSettings,
-    ) -> Self {
+    /// Register every built-in network from `settings`. Only networks in
+    /// `enabled_protocols` whose own section is enabled are started.
+    pub fn new(settings: &Settings, enabled_protocols: HashSet<Protocol>) -> Self {
+        let s = settings;
+        let stats = Arc::new(Stats::new());
+        let contribute = s.contribute.clone();
+        let snowflake_enabled = contribute.enabled && contribute.snowflake.enabled;
+
+        let tor_pool = Arc::new(TorPool::with_contribution(s.tor.clone(), &contribute));
+        let i2p_adapter = Arc::new(
+            I2pAdapter::new(s.i2p.clone())
+                .with_contribution(&contribute)
+                .with_stats(stats.clone()),
+        );
+        let nym_adapter = Arc::new(NymAdapter::new(s.nym.clone()));
+        let ipfs_adapter = Arc::new(IpfsAdapter::new(s.ipfs.clone()));
+        let snowflake = SnowflakeAdapter::new(
+            snowflake_enabled,
+            contribute.snowflake.binary_path.clone(),
+            contribute.snowflake.broker_url.clone(),
+        )
+        .with_args(contribute::snowflake_args(&contribute))
+        .with_stats(stats.clone());
+
+        // Routing precedence follows registration order
+        let adapters: [(Protocol, SharedAdapter, bool); 24] = [
+            (Protocol::Tor, tor_pool.clone(), s.tor.enabled),
+            (Protocol::I2p, i2p_adapter.clone(), s.i2p.enabled),
+            (
+                Protocol::Lokinet,
+                Arc::new(LokinetAdapter::new(s.lokinet.clone())),
+                s.lokinet.enabled,
+            ),
+            (Protocol::Nym, nym_adapter.clone(), s.nym.enabled),
+            (
+                Protocol::ZeroNet,
+                Arc::new(ZeroNetAdapter::new(s.zeronet.clone())),
+                s.zeronet.enabled,
+            ),
+            (Protocol::Ipfs, ipfs_adapter.clone(), s.ipfs.enabled),
+            (
+                Protocol::Freenet,
+                Arc::new(FreenetAdapter::new(s.freenet.clone())),
+                s.freenet.enabled,
+            ),
+            (
+                Protocol::GnuNet,
+                Arc::new(GnunetAdapter::new(s.gnunet.clone())),
+                s.gnunet.enabled,
+            ),
+            (
+                Protocol::RetroShare,
+                Arc::new(RetroShareAdapter::new(s.retroshare.clone())),
+                s.retroshare.enabled,
+            ),
+            (
+                Protocol::Tribler,
+                Arc::new(TriblerAdapter::new(s.tribler.clone())),
+                s.tribler.enabled,
+            ),
+            (
+                Protocol::V2Ray,
+                Arc::new(
+                    V2RayAdapter::new(
+                        s.v2ray.enabled,
+                        s.v2ray.binary_path.clone(),
+                        s.v2ray.config_path.clone(),
+                        s.v2ray.socks_port,
+                    )
+                    .with_mode(s.v2ray.mode),
+                ),
+                s.v2ray.enabled,
+            ),
+            (
+                Protocol::Trojan,
+                Arc::new(
+                    TrojanAdapter::new(
+                        s.trojan.enabled,
+                        s.trojan.binary_path.clone(),
+                        s.trojan.config_path.clone(),
+                        s.trojan.socks_port,
+                    )
+                    .with_mode(s.trojan.mode),
+                ),
+                s.trojan.enabled,
+            ),
+            (
+                Protocol::Outline,
+                Arc::new(
+                    OutlineAdapter::new(
+                        s.outline.enabled,
+                        s.outline.binary_path.clone(),
+                        s.outline.config_path.clone(),
+                        s.outline.socks_port,
+                    )
+                    .with_mode(s.outline.mode),
+                ),
+                s.outline.enabled,
+            ),
+            (
+                Protocol::Psiphon,
+                Arc::new(
+                    PsiphonAdapter::new(
+                        s.psiphon.enabled,
+                        s.psiphon.binary_path.clone(),
+                        s.psiphon.config_path.clone(),
+                        s.psiphon.socks_port,
+                    )
+                    .with_mode(s.psiphon.mode),
+                ),
+                s.psiphon.enabled,
+            ),
+            (
+                Protocol::Mysterium,
+                Arc::new(
+                    MysteriumAdapter::new(
+                        s.mysterium.enabled,
+                        s.mysterium.binary_path.clone(),
+                        s.mysterium.socks_port,
+                    )
+                    .with_mode(s.mysterium.mode),
+                ),
+                s.mysterium.enabled,
+            ),
+            (
+                Protocol::Lantern,
+                Arc::new(
+                    LanternAdapter::new(
+                        s.lantern.enabled,
+                        s.lantern.binary_path.clone(),
+                        s.lantern.socks_port,
+                    )
+                    .with_mode(s.lantern.mode),
+                ),
+                s.lantern.enabled,
+            ),
+            (
+                Protocol::Sentinel,
+                Arc::new(
+                    SentinelAdapter::new(
+                        s.sentinel.enabled,
+                        s.sentinel.binary_path.clone(),
+                        s.sentinel.socks_port,
+                    )
+                    .with_mode(s.sentinel.mode),
+                ),
+                s.sentinel.enabled,
+            ),
+            (
+                Protocol::WireGuard,
+                Arc::new(
+                    WireGuardAdapter::new(
+                        s.wireguard.enabled,
+                        s.wireguard.binary_path.clone(),
+                        s.wireguard.interface.clone(),
+                        s.wireguard.config_path.clone(),
+                    )
+                    .with_mode(s.wireguard.mode),
+                ),
+                s.wireguard.enabled,
+            ),
+            (
+                Protocol::Yggdrasil,
+                Arc::new(
+                    YggdrasilAdapter::new(
+                        s.yggdrasil.enabled,
+                        s.yggdrasil.binary_path.clone(),
+                        s.yggdrasil.config_path.clone(),
+                    )
+                    .with_mode(s.yggdrasil.mode),
+                ),
+                s.yggdrasil.enabled,
+            ),
+            (
+                Protocol::Cjdns,
+                Arc::new(
+                    CjdnsAdapter::new(
+                        s.cjdns.enabled,
+                        s.cjdns.binary_path.clone(),
+                        s.cjdns.config_path.clone(),
+                    )
+                    .with_mode(s.cjdns.mode),
+                ),
+                s.cjdns.enabled,
+            ),
+            (
+                Protocol::Session,
+                Arc::new(
+                    SessionAdapter::new(s.session.enabled, s.session.binary_path.clone())
+                        .with_mode(s.session.mode),
+                ),
+                s.session.enabled,
+            ),
+            (Protocol::Snowflake, Arc::new(snowflake), snowflake_enabled),
+            (
+                Protocol::Obfs4,
+                Arc::new(Obfs4Adapter::new(
+                    s.obfs4.enabled,
+                    s.obfs4.binary_path.clone(),
+                )),
+                s.obfs4.enabled,
+            ),
+            (
+                Protocol::Meek,
+                Arc::new(MeekAdapter::new(
+                    s.meek.enabled,
+                    s.meek.binary_path.clone(),
+                    s.meek.front_domain.clone(),
+                )),
+                s.meek.enabled,
+            ),
+        ];
+        let mut registry = Registry::default();
+        for (protocol, adapter, enabled) in adapters {
+            registry.register(registry::builtin(protocol), adapter, enabled);
+        }
+
+        let routed = s
+            .routes
+            .iter()
+            .filter_map(|r| {
+                let first = s.chains.get(&r.chain)?.first()?;
+                Some((r.chain.clone(), first.parse().ok()?))
+            })
+            .collect();
         Self {
-            chain_mode,
-            tor_adapter: Arc::new(TorAdapter::new(tor)),
-            i2p_adapter: Arc::new(I2pAdapter::new(i2p)),
-            nym_adapter: Arc::new(NymAdapter::new(nym)),
-            lokinet_adapter: Arc::new(LokinetAdapter::new(lokinet)),
-            ipfs_adapter: Arc::new(IpfsAdapter::new(ipfs)),
-            zeronet_adapter: Arc::new(ZeroNetAdapter::new(zeronet)),
-            freenet_adapter: Arc::new(FreenetAdapter::new(freenet)),
-            retroshare_adapter: Arc::new(RetroShareAdapter::new(retroshare)),
-            gnunet_adapter: Arc::new(GnunetAdapter::new(gnunet)),
-            tribler_adapter: Arc::new(TriblerAdapter::new(tribler)),
+            chain_mode: s.chain_mode.clone(),
+            registry,
+            tor_pool,
+            i2p_adapter,
+            nym_adapter,
+            ipfs_adapter,
+            pt_manager: Arc::new(PtManager::new(s.pluggable_transports.clone())),
+            contribute,
+            stats,
             health_state: new_health_state(),
             enabled_protocols,
+            startup: s.startup.clone(),
+            tor_upstream: s.tor.upstream_proxy.clone(),
+            routed,
+            readiness: Mutex::new(StartupReport::default()),
+        }
+    }
+
+    /// Register another adapter, or replace a built-in one (e.g. with a
+    /// test double or an externally managed daemon)
+    pub fn with_adapter(
+        mut self,
+        info: AdapterInfo,
+        adapter: SharedAdapter,
+        enabled: bool,
+    ) -> Self {
+        self.registry.register(info, adapter, enabled);
+        self
+    }
+
+    /// Every registered network, for routing and status
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// How each adapter settled during the last `start_processes`
+    pub fn readiness(&self) -> StartupReport {
+        self.readiness.lock().unwrap().clone()
+    }
+
+    /// The Tor instance pool, for routing and control-port operations such
+    /// as client authorization
+    pub fn tor(&self) -> Arc<TorPool> {
+        self.tor_pool.clone()
+    }
+
+    /// Statistics shared with the SOCKS server and fed by network daemons
+    pub fn stats(&self) -> Arc<Stats> {
+        self.stats.clone()
+    }
+
+    /// The I2P adapter, for SAM sessions and published tunnels
+    pub fn i2p(&self) -> Arc<I2pAdapter> {
+        self.i2p_adapter.clone()
+    }
+
+    /// The Nym adapter, for native mixnet messaging
+    pub fn nym(&self) -> Arc<NymAdapter> {
+        self.nym_adapter.clone()
+    }
+
+    /// Managed pluggable transports, for fronting Tor or chaining other networks
+    pub fn pt_manager(&self) -> Arc<PtManager> {
+        self.pt_manager.clone()
+    }
+
+    /// Rotate network-level identities: NEWNYM for Tor and fresh keys for
+    /// the Nym client. Returns the outcome per protocol.
+    pub async fn new_identity(&self) -> Vec<(Protocol, anyhow::Result<()>)> {
+        let mut results = Vec::new();
+        if self.enabled_protocols.contains(&Protocol::Tor) {
+            results.push((Protocol::Tor, self.tor_pool.signal_newnym().await));
+        }
+        if self.enabled_protocols.contains(&Protocol::Nym) {
+            results.push((Protocol::Nym, self.nym_adapter.reconnect().await));
         }
+        results
     }
 
     pub async fn start_processes(&self) -> anyhow::Result<()> {
-        // Phase 4: Protocol Chaining - Start dependencies first
-        match self.chain_mode {
-            ChainMode::TorOverNym => {
-                if self.enabled_protocols.contains(&Protocol::Nym) {
-                    info!("Chain Mode: Tor over Nym. Starting Nym first.");
-                    if let Err(e) = self.nym_adapter.start().await {
-                        error!("Failed to start Nym (required for chaining): {}", e);
-                    }
-                    // Give Nym time to initialize before starting Tor
-                    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-                }
-                
-                if self.enabled_protocols.contains(&Protocol::Tor) {
-                    if let Err(e) = self.tor_adapter.start().await {
-                        error!("Failed to start Tor: {}", e);
-                    }
-                }
-            }
-            ChainMode::NymOverTor => {
-                if self.enabled_protocols.contains(&Protocol::Tor) {
-                    info!("Chain Mode: Nym over Tor. Starting Tor first.");
-                    if let Err(e) = self.tor_adapter.start().await {
-                        error!("Failed to start Tor (required for chaining): {}", e);
-                    }
-                    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-                }
+        if self.chain_mode == ChainMode::NymOverTor {
+            bail!(
+                "chain_mode \"nym_over_tor\" is not supported: the Nym SDK cannot send its gateway connection through Tor. Use \"tor_over_nym\" or \"none\""
+            );
+        }
 
-                if self.enabled_protocols.contains(&Protocol::Nym) {
-                    if let Err(e) = self.nym_adapter.start().await {
-                        error!("Failed to start Nym: {}", e);
-                    }
-                }
-            }
-            ChainMode::None => {
-                // Normal startup order
-                if self.enabled_protocols.contains(&Protocol::Tor) {
-                    if let Err(e) = self.tor_adapter.start().await {
-                        error!("Failed to start Tor: {}", e);
-                    }
-                }
-                if self.enabled_protocols.contains(&Protocol::Nym) {
-                    if let Err(e) = self.nym_adapter.start().await {
-                        error!("Failed to start Nym: {}", e);
-                    }
-                }
-            }
+        // Pluggable transports come first so Tor can use them for its bridges
+        if !self.pt_manager.is_empty() {
+            info!("Starting pluggable transports...");
+            let methods = self.pt_manager.start_all().await;
+            self.tor_pool.set_transport_plugins(methods).await;
         }
 
-        // Start remaining adapters if enabled
-        if self.enabled_protocols.contains(&Protocol::I2p) {
-            if let Err(e) = self.i2p_adapter.start().await {
-                error!("Failed to start I2PD: {}", e);
-            }
+        // Dependents wait for real readiness; everything else starts at once.
+        // A broken chain is reported once everything else has started.
+        let (nodes, excluded, chain) = self.plan_startup().await;
+        let mut report = startup::run(nodes).await?;
+        report.entries.extend(excluded.entries);
+        let chain = chain.and_then(|()| match self.chain_mode {
+            ChainMode::TorOverNym => check_tor_over_nym(&report),
+            ChainMode::None | ChainMode::NymOverTor => Ok(()),
+        });
+        *self.readiness.lock().unwrap() = report;
+
+        if self.contribute.enabled {
+            self.start_contribution().await;
         }
-        if self.enabled_protocols.contains(&Protocol::Lokinet) {
-            if let Err(e) = self.lokinet_adapter.start().await {
-                error!("Failed to start Lokinet: {}", e);
+
+        // Start Health Monitors (only for enabled protocols)
+        let interval = Duration::from_secs(5);
+        for entry in self.registry.iter() {
+            if self.enabled_protocols.contains(&entry.info.protocol) {
+                tokio::spawn(run_health_monitor(
+                    entry.info.protocol,
+                    entry.adapter.clone(),
+                    self.health_state.clone(),
+                    interval,
+                ));
             }
         }
-        
-        // Phase 2.5: Decentralized Web
-        if self.enabled_protocols.contains(&Protocol::Ipfs) {
-            if let Err(e) = self.ipfs_adapter.start().await {
-                error!("Failed to start IPFS: {}", e);
-            }
+        if self.enabled_protocols.contains(&Protocol::Nym) {
+            tokio::spawn(self.nym_adapter.clone().run_failover());
         }
-        if self.enabled_protocols.contains(&Protocol::ZeroNet) {
-            if let Err(e) = self.zeronet_adapter.start().await {
-                error!("Failed to start ZeroNet: {}", e);
-            }
+
+        if let Err(e) = &chain {
+            error!("Chain mode {:?} is not active: {}", self.chain_mode, e);
         }
-        if self.enabled_protocols.contains(&Protocol::Freenet) {
-            if let Err(e) = self.freenet_adapter.start().await {
-                error!("Failed to start Freenet: {}", e);
-            }
+        chain
+    }
+
+    /// What "ready" means for each network
+    fn gate(&self, entry: &Entry) -> Gate {
+        match entry.info.protocol {
+            Protocol::Tor => Gate::TorBootstrap(self.tor_pool.clone()),
+            Protocol::Ipfs => Gate::KuboApi(self.ipfs_adapter.api_url().to_string()),
+            _ => match entry.endpoint() {
+                Endpoint::Socks5(addr) => Gate::Socks(addr),
+                _ => Gate::Healthy,
+            },
         }
-        
-        // Extended Phase 2.5
-        if self.enabled_protocols.contains(&Protocol::RetroShare) {
-            if let Err(e) = self.retroshare_adapter.start().await {
-                error!("Failed to start RetroShare: {}", e);
+    }
+
+    /// Nodes to start, report entries for networks the plan leaves out, and
+    /// whether the chain mode can be honoured.
+    ///
+    /// Dependencies: `tor_over_nym` (and a `tor.upstream_proxy` pointing at
+    /// another network's SOCKS port) makes Tor wait for that network.
+    /// Chains used by `[[routes]]` only need their first hop enabled.
+    async fn plan_startup(&self) -> (Vec<Node>, StartupReport, anyhow::Result<()>) {
+        let mut excluded = StartupReport::default();
+        let mut active: HashSet<Protocol> = self
+            .registry
+            .enabled()
+            .map(|e| e.info.protocol)
+            .filter(|p| self.enabled_protocols.contains(p))
+            .collect();
+        let mut exclude = |active: &mut HashSet<Protocol>, protocol: Protocol, why: String| {
+            if active.remove(&protocol) {
+                warn!("{:?} skipped: {}", protocol, why);
+                excluded.push(protocol, "none".to_string(), Readiness::Skipped(why));
+            }
+        };
+
+        let chain = match self.chain_mode {
+            ChainMode::TorOverNym => {
+                let missing: Vec<String> = [Protocol::Nym, Protocol::Tor]
+                    .into_iter()
+                    .filter(|p| !active.contains(p))
+                    .map(|p| format!("{:?}", p))
+                    .collect();
+                if missing.is_empty() {
+                    info!("Chain Mode: Tor over Nym. Tor starts once Nym is ready.");
+                    Ok(())
+                } else {
+                    // Neither hop starts on its own, so nothing leaves unchained
+                    for protocol in [Protocol::Nym, Protocol::Tor] {
+                        let why = "chain_mode \"tor_over_nym\" is incomplete".to_string();
+                        exclude(&mut active, protocol, why);
+                    }
+                    Err(anyhow!(
+                        "chain_mode \"tor_over_nym\" needs {} enabled",
+                        missing.join(" and ")
+                    ))
+                }
+            }
+            ChainMode::None | ChainMode::NymOverTor => Ok(()),
+        };
+
+        for (chain_name, protocol) in &self.routed {
+            if !active.contains(protocol) && excluded.get(*protocol).is_none() {
+                let why = format!("not enabled, but chain {} starts with it", chain_name);
+                warn!("{:?} {}", protocol, why);
+                excluded.push(*protocol, "none".to_string(), Readiness::Skipped(why));
             }
         }
-        if self.enabled_protocols.contains(&Protocol::GnuNet) {
-            if let Err(e) = self.gnunet_adapter.start().await {
-                error!("Failed to start GNUnet: {}", e);
+
+        let mut nodes: Vec<Node> = self
+            .registry
+            .iter()
+            .filter(|e| active.contains(&e.info.protocol))
+            .map(|entry| {
+                let mut node = Node::new(
+                    entry.info.protocol,
+                    entry.adapter.clone(),
+                    self.gate(entry),
+                    self.startup.timeout_for(&entry.info.name()),
+                );
+                node.after = entry.info.after.to_vec();
+                node
+            })
+            .collect();
+
+        if let Some(tor) = nodes.iter_mut().find(|n| n.protocol == Protocol::Tor) {
+            if self.chain_mode == ChainMode::TorOverNym {
+                tor.after.push(Protocol::Nym);
+                let (nym, pool) = (self.nym_adapter.clone(), self.tor_pool.clone());
+                tor.prepare = Some(Box::pin(async move {
+                    let addr = nym
+                        .socks_addr()
+                        .await
+                        .ok_or_else(|| anyhow!("Nym hop has no SOCKS5 listener"))?;
+                    pool.set_upstream_proxy(addr.to_string()).await;
+                    Ok(())
+                }));
+            } else if let Some(upstream) = self
+                .tor_upstream
+                .as_deref()
+                .and_then(|u| u.parse::<SocketAddr>().ok())
+            {
+                let provider = active.iter().copied().find(|&p| {
+                    p != Protocol::Tor
+                        && self.registry.get(p).and_then(|e| e.endpoint().socks5())
+                            == Some(upstream)
+                });
+                if let Some(provider) = provider {
+                    info!(
+                        "Tor reaches the network through {:?}; starting it first",
+                        provider
+                    );
+                    tor.after.push(provider);
+                }
             }
         }
-        if self.enabled_protocols.contains(&Protocol::Tribler) {
-            if let Err(e) = self.tribler_adapter.start().await {
-                error!("Failed to start Tribler: {}", e);
+        (nodes, excluded, chain)
+    }
+
+    /// Contribution extras: relay statistics. Tor and I2P already started
+    /// with their relay options, and the Snowflake proxy as `Snowflake`.
+    async fn start_contribution(&self) {
+        let c = &self.contribute;
+        if c.snowflake.enabled && c.bandwidth_kbps > 0 {
+            warn!("The Snowflake proxy has no bandwidth limit; contribute.bandwidth_kbps does not apply to it");
+        }
+        if c.tor.role != TorRelayRole::None && self.enabled_protocols.contains(&Protocol::Tor) {
+            if c.max_connections > 0 {
+                warn!("Tor has no relay connection limit; contribute.max_connections does not apply to it");
             }
+            tokio::spawn(contribute::run_tor_relay_stats(
+                self.tor_pool.primary(),
+                c.clone(),
+                self.stats.clone(),
+                Duration::from_secs(60),
+            ));
         }
+    }
+}
 
-        // Start Health Monitors (only for enabled protocols)
-        let interval = Duration::from_secs(5);
-        
-        if self.enabled_protocols.contains(&Protocol::Tor) { tokio::spawn(run_health_monitor(Protocol::Tor, self.tor_adapter.clone(), self.health_state.clone(), interval)); }
-        if self.enabled_protocols.contains(&Protocol::I2p) { tokio::spawn(run_health_monitor(Protocol::I2p, self.i2p_adapter.clone(), self.health_state.clone(), interval)); }
-        if self.enabled_protocols.contains(&Protocol::Nym) { tokio::spawn(run_health_monitor(Protocol::Nym, self.nym_adapter.clone(), self.health_state.clone(), interval)); }
-        if self.enabled_protocols.contains(&Protocol::Lokinet) { tokio::spawn(run_health_monitor(Protocol::Lokinet, self.lokinet_adapter.clone(), self.health_state.clone(), interval)); }
-        if self.enabled_protocols.contains(&Protocol::Ipfs) { tokio::spawn(run_health_monitor(Protocol::Ipfs, self.ipfs_adapter.clone(), self.health_state.clone(), interval)); }
-        if self.enabled_protocols.contains(&Protocol::ZeroNet) { tokio::spawn(run_health_monitor(Protocol::ZeroNet, self.zeronet_adapter.clone(), self.health_state.clone(), interval)); }
-        if self.enabled_protocols.contains(&Protocol::Freenet) { tokio::spawn(run_health_monitor(Protocol::Freenet, self.freenet_adapter.clone(), self.health_state.clone(), interval)); }
-        if self.enabled_protocols.contains(&Protocol::RetroShare) { tokio::spawn(run_health_monitor(Protocol::RetroShare, self.retroshare_adapter.clone(), self.health_state.clone(), interval)); }
-        if self.enabled_protocols.contains(&Protocol::GnuNet) { tokio::spawn(run_health_monitor(Protocol::GnuNet, self.gnunet_adapter.clone(), self.health_state.clone(), interval)); }
-        if self.enabled_protocols.contains(&Protocol::Tribler) { tokio::spawn(run_health_monitor(Protocol::Tribler, self.tribler_adapter.clone(), self.health_state.clone(), interval)); }
-
-        Ok(())
+/// Both hops came up: Nym before Tor, with Tor pointed at Nym
+fn check_tor_over_nym(report: &StartupReport) -> anyhow::Result<()> {
+    let state = |p| {
+        report
+            .get(p)
+            .map_or("not planned".to_string(), |r| r.to_string())
+    };
+    if !report.is_ready(Protocol::Nym) {
+        bail!(
+            "Nym hop is not ready ({}); Tor was not started",
+            state(Protocol::Nym)
+        );
+    }
+    if !report.is_ready(Protocol::Tor) {
+        bail!("Tor hop is not ready ({})", state(Protocol::Tor));
     }
+    info!("Chain active: client -> Tor -> Nym -> exit");
+    Ok(())
 }
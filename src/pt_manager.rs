@@ -0,0 +1,510 @@
+//! Pluggable Transport manager.
+//!
+//! Launches PT binaries (obfs4proxy/lyrebird, meek, snowflake client, ...) as
+//! managed proxies following the Tor PT 1.0 IPC spec: configuration is passed
+//! through `TOR_PT_*` environment variables and the transport answers on
+//! stdout with `VERSION`, `CMETHOD` and `CMETHODS DONE` lines. The resulting
+//! SOCKS endpoints can front Tor (`ClientTransportPlugin`) or be chained in
+//! front of any other network.
+
+use crate::config::PtSettings;
+use anyhow::{anyhow, bail, Result};
+use log::{debug, error, info, warn};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+/// How long a transport may take to finish its `CMETHODS DONE` handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// SOCKS flavour a client method speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocksVersion {
+    Socks4,
+    Socks5,
+}
+
+impl SocksVersion {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SocksVersion::Socks4 => "socks4",
+            SocksVersion::Socks5 => "socks5",
+        }
+    }
+}
+
+/// A client transport method announced by a `CMETHOD` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientMethod {
+    pub transport: String,
+    pub protocol: SocksVersion,
+    pub addr: SocketAddr,
+}
+
+impl ClientMethod {
+    /// Value for Tor's `ClientTransportPlugin` option (external proxy form)
+    pub fn tor_plugin_line(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.transport,
+            self.protocol.as_str(),
+            self.addr
+        )
+    }
+}
+
+/// A single line of the managed-proxy stdout protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PtMessage {
+    Version(String),
+    VersionError(String),
+    EnvError(String),
+    CMethod(ClientMethod),
+    CMethodError { transport: String, message: String },
+    CMethodsDone,
+    ProxyDone,
+    ProxyError(String),
+    Log { severity: String, message: String },
+    Status(String),
+    Unknown(String),
+}
+
+/// Parse one line written by a managed transport on stdout
+pub fn parse_line(line: &str) -> PtMessage {
+    let line = line.trim();
+    let (keyword, rest) = match line.split_once(' ') {
+        Some((k, r)) => (k, r.trim()),
+        None => (line, ""),
+    };
+
+    match keyword {
+        "VERSION" => PtMessage::Version(rest.to_string()),
+        "VERSION-ERROR" => PtMessage::VersionError(rest.to_string()),
+        "ENV-ERROR" => PtMessage::EnvError(rest.to_string()),
+        "CMETHOD" => {
+            // CMETHOD <transport> <socks4|socks5> <address:port> [options]
+            let mut parts = rest.split_whitespace();
+            let transport = parts.next();
+            let protocol = match parts.next() {
+                Some("socks4") => Some(SocksVersion::Socks4),
+                Some("socks5") => Some(SocksVersion::Socks5),
+                _ => None,
+            };
+            let addr = parts.next().and_then(|a| a.parse::<SocketAddr>().ok());
+            match (transport, protocol, addr) {
+                (Some(transport), Some(protocol), Some(addr)) => PtMessage::CMethod(ClientMethod {
+                    transport: transport.to_string(),
+                    protocol,
+                    addr,
+                }),
+                _ => PtMessage::Unknown(line.to_string()),
+            }
+        }
+        "CMETHOD-ERROR" => {
+            let (transport, message) = rest.split_once(' ').unwrap_or((rest, ""));
+            PtMessage::CMethodError {
+                transport: transport.to_string(),
+                message: message.to_string(),
+            }
+        }
+        "CMETHODS" if rest == "DONE" => PtMessage::CMethodsDone,
+        "PROXY" if rest == "DONE" => PtMessage::ProxyDone,
+        "PROXY-ERROR" => PtMessage::ProxyError(rest.to_string()),
+        "LOG" => {
+            let severity = kv_value(rest, "SEVERITY").unwrap_or_else(|| "notice".to_string());
+            let message = kv_value(rest, "MESSAGE").unwrap_or_default();
+            PtMessage::Log { severity, message }
+        }
+        "STATUS" => PtMessage::Status(rest.to_string()),
+        _ => PtMessage::Unknown(line.to_string()),
+    }
+}
+
+/// Extract `KEY=value` (or `KEY="quoted value"`) from a LOG line. Keys
+/// are compared whole, so `ARGS` doesn't match `XARGS=...`.
+fn kv_value(rest: &str, key: &str) -> Option<String> {
+    let mut chars = rest.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        chars.peek()?;
+        let name: String =
+            std::iter::from_fn(|| chars.next_if(|&c| c != '=' && !c.is_whitespace())).collect();
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => value.extend(chars.next()),
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                value.extend(std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())));
+            }
+        }
+        if name == key {
+            return Some(value);
+        }
+    }
+}
+
+/// Encode transport arguments (e.g. `cert=...;iat-mode=0`) as SOCKS5
+/// username/password, as required when dialing a bridge through a CMETHOD
+/// endpoint. The argument string is split across the two fields and the
+/// password is a single NUL byte when it fits in the username. Each field
+/// holds at most 255 bytes (RFC 1929); longer arguments are rejected.
+pub fn encode_socks_args(args: &[(&str, &str)]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let escape = |s: &str| {
+        s.replace('\\', "\\\\")
+            .replace(';', "\\;")
+            .replace('=', "\\=")
+    };
+    let joined = args
+        .iter()
+        .map(|(k, v)| format!("{}={}", escape(k), escape(v)))
+        .collect::<Vec<_>>()
+        .join(";");
+    let bytes = joined.into_bytes();
+
+    if bytes.len() > 2 * 255 {
+        bail!(
+            "transport arguments are {} bytes; SOCKS5 username and password carry at most 510",
+            bytes.len()
+        );
+    }
+    if bytes.len() <= 255 {
+        Ok((bytes, vec![0]))
+    } else {
+        Ok((bytes[..255].to_vec(), bytes[255..].to_vec()))
+    }
+}
+
+/// One PT binary running as a managed proxy
+pub struct ManagedTransport {
+    settings: PtSettings,
+    process: Arc<Mutex<Option<Child>>>,
+    methods: Arc<RwLock<Vec<ClientMethod>>>,
+}
+
+impl ManagedTransport {
+    pub fn new(settings: PtSettings) -> Self {
+        Self {
+            settings,
+            process: Arc::new(Mutex::new(None)),
+            methods: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    /// Transports this binary was asked to provide
+    pub fn transports(&self) -> &[String] {
+        &self.settings.transports
+    }
+
+    fn state_dir(&self) -> PathBuf {
+        match &self.settings.state_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let name = Path::new(&self.settings.binary_path)
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("pt");
+                Path::new("data/pt").join(name)
+            }
+        }
+    }
+
+    /// Launch the transport and wait for the `CMETHODS DONE` handshake
+    pub async fn launch(&self) -> Result<Vec<ClientMethod>> {
+        if self.settings.transports.is_empty() {
+            return Err(anyhow!(
+                "No transports configured for {}",
+                self.settings.binary_path
+            ));
+        }
+
+        let state_dir = self.state_dir();
+        if !state_dir.exists() {
+            std::fs::create_dir_all(&state_dir)?;
+        }
+
+        info!(
+            "Launching pluggable transport {} ({})",
+            self.settings.binary_path,
+            self.settings.transports.join(",")
+        );
+
+        let mut cmd = Command::new(&self.settings.binary_path);
+        cmd.env("TOR_PT_MANAGED_TRANSPORT_VER", "1")
+            .env("TOR_PT_STATE_LOCATION", &state_dir)
+            .env("TOR_PT_EXIT_ON_STDIN_CLOSE", "1")
+            .env(
+                "TOR_PT_CLIENT_TRANSPORTS",
+                self.settings.transports.join(","),
+            );
+        if let Some(ref proxy) = self.settings.upstream_proxy {
+            cmd.env("TOR_PT_PROXY", proxy);
+        }
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut child = cmd.spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("PT stdout not captured"))?;
+        let mut lines = BufReader::new(stdout).lines();
+
+        if let Some(stderr) = child.stderr.take() {
+            let tag = self.settings.transports.join(",");
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = reader.next_line().await {
+                    warn!("[PT {}] {}", tag, line);
+                }
+            });
+        }
+
+        let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, self.handshake(&mut lines)).await;
+        let methods = match handshake {
+            Ok(Ok(methods)) => methods,
+            Ok(Err(e)) => {
+                let _ = child.kill().await;
+                return Err(e);
+            }
+            Err(_) => {
+                let _ = child.kill().await;
+                return Err(anyhow!(
+                    "{} did not finish the PT handshake within {:?}",
+                    self.settings.binary_path,
+                    HANDSHAKE_TIMEOUT
+                ));
+            }
+        };
+
+        // Keep draining stdout for LOG/STATUS messages
+        let tag = self.settings.transports.join(",");
+        tokio::spawn(async move {
+            while let Ok(Some(line)) = lines.next_line().await {
+                match parse_line(&line) {
+                    PtMessage::Log { severity, message } => match severity.as_str() {
+                        "error" | "warning" => warn!("[PT {}] {}", tag, message),
+                        _ => info!("[PT {}] {}", tag, message),
+                    },
+                    other => debug!("[PT {}] {:?}", tag, other),
+                }
+            }
+        });
+
+        *self.methods.write().unwrap() = methods.clone();
+        *self.process.lock().await = Some(child);
+
+        for m in &methods {
+            info!("Pluggable transport {} ready on {}", m.transport, m.addr);
+        }
+        Ok(methods)
+    }
+
+    async fn handshake(
+        &self,
+        lines: &mut Lines<BufReader<ChildStdout>>,
+    ) -> Result<Vec<ClientMethod>> {
+        let mut methods = Vec::new();
+        let mut proxy_acked = self.settings.upstream_proxy.is_none();
+
+        loop {
+            let line = lines.next_line().await?.ok_or_else(|| {
+                anyhow!("{} exited before CMETHODS DONE", self.settings.binary_path)
+            })?;
+
+            match parse_line(&line) {
+                PtMessage::Version(v) if v == "1" => {}
+                PtMessage::Version(v) => return Err(anyhow!("Unsupported PT version {}", v)),
+                PtMessage::VersionError(e) => return Err(anyhow!("PT VERSION-ERROR: {}", e)),
+                PtMessage::EnvError(e) => return Err(anyhow!("PT ENV-ERROR: {}", e)),
+                PtMessage::ProxyError(e) => return Err(anyhow!("PT PROXY-ERROR: {}", e)),
+                PtMessage::ProxyDone => proxy_acked = true,
+                PtMessage::CMethod(m) => methods.push(m),
+                PtMessage::CMethodError { transport, message } => {
+                    warn!("Transport {} failed to start: {}", transport, message);
+                }
+                PtMessage::CMethodsDone => break,
+                PtMessage::Log { message, .. } => debug!("[PT] {}", message),
+                other => debug!("Ignoring PT line during handshake: {:?}", other),
+            }
+        }
+
+        if !proxy_acked {
+            return Err(anyhow!(
+                "{} ignored TOR_PT_PROXY; refusing to bypass the upstream proxy",
+                self.settings.binary_path
+            ));
+        }
+        if methods.is_empty() {
+            return Err(anyhow!(
+                "{} did not provide any of: {}",
+                self.settings.binary_path,
+                self.settings.transports.join(",")
+            ));
+        }
+        Ok(methods)
+    }
+
+    /// SOCKS endpoint for a transport, once the handshake has completed
+    pub fn endpoint(&self, transport: &str) -> Option<SocketAddr> {
+        self.methods
+            .read()
+            .unwrap()
+            .iter()
+            .find(|m| m.transport == transport)
+            .map(|m| m.addr)
+    }
+
+    pub fn methods(&self) -> Vec<ClientMethod> {
+        self.methods.read().unwrap().clone()
+    }
+
+    pub async fn is_running(&self) -> bool {
+        self.process.lock().await.is_some()
+    }
+
+    pub async fn shutdown(&self) -> Result<()> {
+        let mut proc_lock = self.process.lock().await;
+        if let Some(mut child) = proc_lock.take() {
+            info!("Stopping pluggable transport {}", self.settings.binary_path);
+            child.kill().await?;
+        }
+        self.methods.write().unwrap().clear();
+        Ok(())
+    }
+}
+
+/// Owns every configured managed transport
+pub struct PtManager {
+    transports: Vec<Arc<ManagedTransport>>,
+}
+
+impl PtManager {
+    pub fn new(settings: Vec<PtSettings>) -> Self {
+        Self {
+            transports: settings
+                .into_iter()
+                .map(|s| Arc::new(ManagedTransport::new(s)))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transports.is_empty()
+    }
+
+    /// Launch all transports. Failures are logged and skipped so one broken
+    /// binary does not take down the others.
+    pub async fn start_all(&self) -> Vec<ClientMethod> {
+        let mut methods = Vec::new();
+        for transport in &self.transports {
+            match transport.launch().await {
+                Ok(m) => methods.extend(m),
+                Err(e) => error!(
+                    "Failed to start pluggable transport ({}): {}",
+                    transport.transports().join(","),
+                    e
+                ),
+            }
+        }
+        methods
+    }
+
+    /// SOCKS endpoint of a named transport (e.g. "obfs4")
+    pub fn endpoint(&self, transport: &str) -> Option<SocketAddr> {
+        self.transports.iter().find_map(|t| t.endpoint(transport))
+    }
+
+    /// All client methods currently available
+    pub fn client_methods(&self) -> Vec<ClientMethod> {
+        self.transports.iter().flat_map(|t| t.methods()).collect()
+    }
+
+    pub async fn stop_all(&self) -> Result<()> {
+        for transport in &self.transports {
+            transport.shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cmethod() {
+        let msg = parse_line("CMETHOD obfs4 socks5 127.0.0.1:40123");
+        assert_eq!(
+            msg,
+            PtMessage::CMethod(ClientMethod {
+                transport: "obfs4".to_string(),
+                protocol: SocksVersion::Socks5,
+                addr: "127.0.0.1:40123".parse().unwrap(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_control_lines() {
+        assert_eq!(parse_line("VERSION 1"), PtMessage::Version("1".to_string()));
+        assert_eq!(parse_line("CMETHODS DONE"), PtMessage::CMethodsDone);
+        assert_eq!(parse_line("PROXY DONE"), PtMessage::ProxyDone);
+        assert_eq!(
+            parse_line("CMETHOD-ERROR meek_lite no such transport"),
+            PtMessage::CMethodError {
+                transport: "meek_lite".to_string(),
+                message: "no such transport".to_string(),
+            }
+        );
+        assert_eq!(
+            parse_line("LOG SEVERITY=warning MESSAGE=\"bridge unreachable\""),
+            PtMessage::Log {
+                severity: "warning".to_string(),
+                message: "bridge unreachable".to_string(),
+            }
+        );
+        // Whole keys only; quoted values may contain spaces, `=` and quotes
+        assert_eq!(
+            parse_line("LOG XSEVERITY=debug MESSAGE=\"a SEVERITY=x \\\"b\\\"\" SEVERITY=info"),
+            PtMessage::Log {
+                severity: "info".to_string(),
+                message: "a SEVERITY=x \"b\"".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_tor_plugin_line() {
+        let m = ClientMethod {
+            transport: "obfs4".to_string(),
+            protocol: SocksVersion::Socks5,
+            addr: "127.0.0.1:40123".parse().unwrap(),
+        };
+        assert_eq!(m.tor_plugin_line(), "obfs4 socks5 127.0.0.1:40123");
+    }
+
+    #[test]
+    fn test_encode_socks_args() {
+        let (user, pass) = encode_socks_args(&[("cert", "a=b;c"), ("iat-mode", "0")]).unwrap();
+        assert_eq!(user, b"cert=a\\=b\\;c;iat-mode=0".to_vec());
+        assert_eq!(pass, vec![0]);
+
+        let cert = "x".repeat(400);
+        let (user, pass) = encode_socks_args(&[("cert", &cert)]).unwrap();
+        assert_eq!((user.len(), pass.len()), (255, 150));
+        let cert = "x".repeat(510);
+        assert!(encode_socks_args(&[("cert", &cert)]).is_err());
+    }
+}
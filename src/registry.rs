@@ -0,0 +1,247 @@
+//! Adapter registry keyed by `Protocol`.
+//!
+//! Each entry pairs an adapter with what the rest of Chimera needs to know
+//! about it: display name, the hosts it handles (TLDs and schemes), what
+//! kind of endpoint it exposes and which networks must be ready before it
+//! starts. Startup, health monitoring, routing and the GUI iterate the
+//! registry, so a new network only needs to be registered.
+
+use crate::adapters::{Capability, Endpoint, EndpointKind};
+use crate::health_monitor::Protocol;
+use crate::startup::SharedAdapter;
+
+/// Static description of a network
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdapterInfo {
+    pub protocol: Protocol,
+    pub display_name: &'static str,
+    /// Host suffixes without the dot ("onion" matches `x.onion`)
+    pub tlds: &'static [&'static str],
+    /// Host prefixes: URI schemes ("retroshare://") and key types ("USK@")
+    pub schemes: &'static [&'static str],
+    pub endpoint: EndpointKind,
+    /// Networks that must be ready before this one starts
+    pub after: &'static [Protocol],
+}
+
+impl AdapterInfo {
+    const fn new(protocol: Protocol, display_name: &'static str, endpoint: EndpointKind) -> Self {
+        Self {
+            protocol,
+            display_name,
+            tlds: &[],
+            schemes: &[],
+            endpoint,
+            after: &[],
+        }
+    }
+
+    const fn tlds(mut self, tlds: &'static [&'static str]) -> Self {
+        self.tlds = tlds;
+        self
+    }
+
+    const fn schemes(mut self, schemes: &'static [&'static str]) -> Self {
+        self.schemes = schemes;
+        self
+    }
+
+    /// Lower-case name used in the config, chain hops and `STATUS`
+    pub fn name(&self) -> String {
+        format!("{:?}", self.protocol).to_lowercase()
+    }
+
+    /// Whether this network handles `host` by TLD or scheme
+    pub fn handles(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.tlds.iter().any(|tld| {
+            host.strip_suffix(tld)
+                .is_some_and(|rest| rest.ends_with('.'))
+        }) || self
+            .schemes
+            .iter()
+            .any(|scheme| host.starts_with(&scheme.to_ascii_lowercase()))
+    }
+}
+
+/// Built-in networks, in routing precedence order
+pub const BUILTIN: [AdapterInfo; 24] = [
+    AdapterInfo::new(Protocol::Tor, "Tor", EndpointKind::Socks5).tlds(&["onion"]),
+    AdapterInfo::new(Protocol::I2p, "I2P", EndpointKind::Socks5).tlds(&["i2p"]),
+    AdapterInfo::new(Protocol::Lokinet, "Lokinet", EndpointKind::Tun).tlds(&["loki", "snode"]),
+    AdapterInfo::new(Protocol::Nym, "Nym", EndpointKind::Socks5).tlds(&["nym"]),
+    AdapterInfo::new(Protocol::ZeroNet, "ZeroNet", EndpointKind::HttpGateway).tlds(&["bit"]),
+    AdapterInfo::new(Protocol::Ipfs, "IPFS", EndpointKind::HttpGateway).tlds(&["eth", "ipfs"]),
+    AdapterInfo::new(Protocol::Freenet, "Freenet", EndpointKind::HttpGateway)
+        .tlds(&["freenet"])
+        .schemes(&["USK@", "SSK@"]),
+    AdapterInfo::new(Protocol::GnuNet, "GNUnet", EndpointKind::Socks5).tlds(&["gnu", "zkey"]),
+    AdapterInfo::new(Protocol::RetroShare, "RetroShare", EndpointKind::Api)
+        .schemes(&["retroshare://"]),
+    AdapterInfo::new(Protocol::Tribler, "Tribler", EndpointKind::Api).schemes(&["tribler://"]),
+    AdapterInfo::new(Protocol::V2Ray, "V2Ray", EndpointKind::Socks5),
+    AdapterInfo::new(Protocol::Trojan, "Trojan", EndpointKind::Socks5),
+    AdapterInfo::new(Protocol::Outline, "Outline", EndpointKind::Socks5),
+    AdapterInfo::new(Protocol::Psiphon, "Psiphon", EndpointKind::Socks5),
+    AdapterInfo::new(Protocol::Mysterium, "Mysterium", EndpointKind::Socks5),
+    AdapterInfo::new(Protocol::Lantern, "Lantern", EndpointKind::Socks5),
+    AdapterInfo::new(Protocol::Sentinel, "Sentinel", EndpointKind::Socks5),
+    AdapterInfo::new(Protocol::WireGuard, "WireGuard", EndpointKind::Tun),
+    AdapterInfo::new(Protocol::Yggdrasil, "Yggdrasil", EndpointKind::Tun),
+    AdapterInfo::new(Protocol::Cjdns, "cjdns", EndpointKind::Tun),
+    AdapterInfo::new(Protocol::Session, "Session", EndpointKind::None),
+    AdapterInfo::new(Protocol::Snowflake, "Snowflake", EndpointKind::None),
+    AdapterInfo::new(Protocol::Obfs4, "obfs4", EndpointKind::Transport),
+    AdapterInfo::new(Protocol::Meek, "meek", EndpointKind::Transport),
+];
+
+/// Description of a built-in network
+pub fn builtin(protocol: Protocol) -> AdapterInfo {
+    BUILTIN
+        .iter()
+        .find(|info| info.protocol == protocol)
+        .cloned()
+        .expect("every protocol has a built-in entry")
+}
+
+/// A registered adapter
+#[derive(Clone)]
+pub struct Entry {
+    pub info: AdapterInfo,
+    pub adapter: SharedAdapter,
+    /// `enabled = true` in the network's own settings
+    pub enabled: bool,
+}
+
+impl Entry {
+    /// Where the adapter can be reached now
+    pub fn endpoint(&self) -> Endpoint {
+        self.adapter.endpoint()
+    }
+
+    pub fn capabilities(&self) -> Vec<Capability> {
+        self.adapter.capabilities()
+    }
+}
+
+/// Adapters in registration order; registering a protocol again replaces
+/// its entry in place
+#[derive(Clone, Default)]
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    pub fn register(&mut self, info: AdapterInfo, adapter: SharedAdapter, enabled: bool) {
+        let entry = Entry {
+            info,
+            adapter,
+            enabled,
+        };
+        match self
+            .entries
+            .iter_mut()
+            .find(|e| e.info.protocol == entry.info.protocol)
+        {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    pub fn get(&self, protocol: Protocol) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.info.protocol == protocol)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    /// Entries enabled in their settings
+    pub fn enabled(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|e| e.enabled)
+    }
+
+    /// Entries whose adapters implement `capability`, e.g. every
+    /// `ContentStore`
+    pub fn with_capability(&self, capability: Capability) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .filter(move |e| e.capabilities().contains(&capability))
+    }
+
+    /// First registered network whose TLDs or schemes cover `host`
+    pub fn protocol_for(&self, host: &str) -> Option<Protocol> {
+        self.entries
+            .iter()
+            .find(|e| e.info.handles(host))
+            .map(|e| e.info.protocol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::ProtocolAdapter;
+    use async_trait::async_trait;
+    use std::sync::Arc;
+
+    struct Fixed(Endpoint);
+
+    #[async_trait]
+    impl ProtocolAdapter for Fixed {
+        async fn start(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+        async fn stop(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn endpoint(&self) -> Endpoint {
+            self.0.clone()
+        }
+        async fn is_healthy(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_builtin_matching() {
+        assert_eq!(BUILTIN.len(), Protocol::ALL.len());
+        let handles = |p, host| builtin(p).handles(host);
+        assert!(handles(Protocol::Tor, "abc.onion"));
+        assert!(handles(Protocol::Lokinet, "Router.SNODE."));
+        assert!(handles(Protocol::Freenet, "USK@key/site"));
+        assert!(handles(Protocol::RetroShare, "retroshare://friend"));
+        assert!(!handles(Protocol::Tor, "onion"));
+        assert!(!handles(Protocol::I2p, "example.xi2p"));
+        // Only the scheme counts, not the name appearing anywhere
+        assert!(!handles(Protocol::Tribler, "tribler.org"));
+    }
+
+    #[test]
+    fn test_register_and_lookup() {
+        let mut registry = Registry::default();
+        for info in [builtin(Protocol::Tor), builtin(Protocol::ZeroNet)] {
+            let socks = Endpoint::Socks5("127.0.0.1:9052".parse().unwrap());
+            registry.register(info, Arc::new(Fixed(socks)), true);
+        }
+        registry.register(
+            builtin(Protocol::ZeroNet),
+            Arc::new(Fixed(Endpoint::HttpGateway(
+                "127.0.0.1:43110".parse().unwrap(),
+            ))),
+            false,
+        );
+
+        assert_eq!(registry.iter().count(), 2);
+        assert_eq!(registry.enabled().count(), 1);
+        assert_eq!(registry.protocol_for("talk.bit"), Some(Protocol::ZeroNet));
+        assert_eq!(registry.protocol_for("example.com"), None);
+        let zeronet = registry.get(Protocol::ZeroNet).unwrap();
+        assert_eq!(
+            zeronet.endpoint().addr(),
+            Some("127.0.0.1:43110".parse().unwrap())
+        );
+        assert_eq!(zeronet.endpoint().kind(), zeronet.info.endpoint);
+        assert_eq!(registry.with_capability(Capability::Messaging).count(), 0);
+    }
+}
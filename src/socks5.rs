@@ -1,103 +1,519 @@
+use crate::adapters::i2p::addressbook::AddressBook;
+use crate::adapters::lokinet::dns as lokinet_dns;
+use crate::adapters::tor::pool::TorPool;
+use crate::adapters::{Endpoint, HostPort};
+use crate::config::{IsolationMode, RouteRule};
+use crate::health_monitor::Protocol;
+use crate::isolation::{IsolationContext, StreamIsolator};
+use crate::registry::{self, AdapterInfo, Registry};
+use crate::stats::Stats;
 use log::{debug, error, info};
-use reqwest;
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Socks5Server {
     port: u16,
-    tor_proxy: SocketAddr,
-    i2p_proxy: SocketAddr,
-    lokinet_proxy: SocketAddr,
-    nym_proxy: SocketAddr,
-    ipfs_proxy: SocketAddr,
-    zeronet_proxy: SocketAddr,
-    freenet_proxy: SocketAddr,
-    gnunet_proxy: SocketAddr,
-    retroshare_proxy: SocketAddr, // Typically HTTP/API
-    tribler_proxy: SocketAddr,    // Typically REST API
+    /// Which network handles a host, in precedence order
+    networks: Arc<Vec<AdapterInfo>>,
+    /// Where each network is reached. TUN networks are dialled directly, at
+    /// the address their resolver returns for a name (Lokinet) or at IP
+    /// literals inside their prefixes.
+    endpoints: Arc<HashMap<Protocol, Endpoint>>,
+    isolator: Arc<StreamIsolator>,
+    /// Bumped to tear down every open connection
+    close_signal: Arc<watch::Sender<u64>>,
+    active_connections: Arc<AtomicUsize>,
+    stats: Arc<Stats>,
+    tor_pool: Option<Arc<TorPool>>,
+    addressbook: Option<Arc<AddressBook>>,
+    chains: Arc<HashMap<String, Vec<ChainHop>>>,
+    routes: Arc<Vec<RouteRule>>,
 }
 
-impl Socks5Server {
-    #[allow(clippy::too_many_arguments)]
-    pub fn new(
-        port: u16,
-        tor_port: u16,
-        i2p_port: u16,
-        lokinet_port: u16,
-        nym_port: u16,
-        ipfs_port: u16,
-        zeronet_port: u16,
-        freenet_port: u16,
-        gnunet_port: u16,
-        retroshare_url: String, // parse port from URL
-        tribler_url: String,    // parse port from URL
-    ) -> Self {
-        // Simple helper to parse port from URL or default
-        let get_port = |url: &str, def: u16| -> u16 {
-            url.parse::<reqwest::Url>()
-                .ok()
-                .and_then(|u| u.port())
-                .unwrap_or(def)
-        };
+/// One SOCKS5 proxy of a chain. Every hop after the first is reached with a
+/// CONNECT through the hops before it, so it must be reachable from the
+/// previous hop's exit; only the first hop can be a local network proxy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainHop {
+    /// Network name or `host:port`, for logs, `EXPLAIN` and `STATS`
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    /// Set when the hop is one of our networks' local proxies
+    pub protocol: Option<Protocol>,
+    credentials: Option<(String, String)>,
+}
+
+/// How a destination is routed. Built for every connection and shown by the
+/// control command `EXPLAIN`.
+#[derive(Debug, Clone)]
+pub struct RoutePlan {
+    pub host: String,
+    /// Network of the proxy dialled first; for a chain starting at a plain
+    /// proxy, the destination's network
+    pub protocol: Protocol,
+    pub upstream: HostPort,
+    /// Named chain the destination is routed through
+    pub chain: Option<String>,
+    /// The chain's hops, nearest first; empty without a chain
+    pub hops: Vec<ChainHop>,
+    /// Host sent in the upstream CONNECT; differs from `host` after name
+    /// translation (e.g. `.i2p` petname to `.b32.i2p`)
+    pub connect_host: String,
+    /// Human-readable decisions, in order
+    pub steps: Vec<String>,
+}
+
+impl RoutePlan {
+    /// Network whose local proxy is dialled first, if any
+    pub fn first_network(&self) -> Option<Protocol> {
+        match self.hops.first() {
+            Some(hop) => hop.protocol,
+            None => Some(self.protocol),
+        }
+    }
+}
 
-        let rs_port = get_port(&retroshare_url, 9090);
-        let tr_port = get_port(&tribler_url, 8085);
+impl std::fmt::Display for RoutePlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.chain {
+            Some(chain) => write!(
+                f,
+                "{} -> chain {} [{}] (connect {})",
+                self.host,
+                chain,
+                self.hops
+                    .iter()
+                    .map(|h| h.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" > "),
+                self.connect_host
+            ),
+            None => write!(
+                f,
+                "{} -> {:?} via {} (connect {})",
+                self.host, self.protocol, self.upstream, self.connect_host
+            ),
+        }
+    }
+}
+
+/// Whether a route rule's host pattern covers `host`
+fn host_matches(pattern: &str, host: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    match pattern.strip_prefix("*.") {
+        Some(domain) => {
+            host.eq_ignore_ascii_case(domain)
+                || host.len().checked_sub(domain.len() + 1).is_some_and(|dot| {
+                    host.as_bytes()[dot] == b'.' && host[dot + 1..].eq_ignore_ascii_case(domain)
+                })
+        }
+        None => host.eq_ignore_ascii_case(pattern),
+    }
+}
+
+/// Username prefix a client uses to opt into Tor's SOCKS extensions
+/// (extended reply codes are forwarded unchanged to such clients)
+const TOR_EXTENSIONS_PREFIX: &str = "<torS0X>";
+
+/// Whether a reply code is one of Tor's extended onion-service errors
+pub fn is_tor_extended_error(code: u8) -> bool {
+    (0xF0..=0xF7).contains(&code)
+}
+
+/// Human-readable meaning of a SOCKS5 reply code, including Tor's extended
+/// onion-service codes (socks-extensions.txt, "Extended error codes")
+pub fn describe_reply(code: u8) -> &'static str {
+    match code {
+        0x00 => "succeeded",
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        0xF0 => "onion service offline (descriptor not found)",
+        0xF1 => "onion service descriptor is invalid",
+        0xF2 => "onion service introduction failed",
+        0xF3 => "onion service rendezvous failed",
+        0xF4 => "client authorization required",
+        0xF5 => "client authorization rejected",
+        0xF6 => "invalid onion address",
+        0xF7 => "onion service introduction timed out",
+        _ => "unknown error",
+    }
+}
+
+impl Socks5Server {
+    /// Server routing with the built-in networks' TLDs and schemes. Their
+    /// endpoints come from `with_registry` or `with_endpoint`.
+    pub fn new(port: u16) -> Self {
+        let (close_tx, _) = watch::channel(0);
 
         Self {
             port,
-            tor_proxy: SocketAddr::from(([127, 0, 0, 1], tor_port)),
-            i2p_proxy: SocketAddr::from(([127, 0, 0, 1], i2p_port)),
-            lokinet_proxy: SocketAddr::from(([127, 0, 0, 1], lokinet_port)),
-            nym_proxy: SocketAddr::from(([127, 0, 0, 1], nym_port)),
-            ipfs_proxy: SocketAddr::from(([127, 0, 0, 1], ipfs_port)),
-            zeronet_proxy: SocketAddr::from(([127, 0, 0, 1], zeronet_port)),
-            freenet_proxy: SocketAddr::from(([127, 0, 0, 1], freenet_port)),
-            gnunet_proxy: SocketAddr::from(([127, 0, 0, 1], gnunet_port)),
-            retroshare_proxy: SocketAddr::from(([127, 0, 0, 1], rs_port)),
-            tribler_proxy: SocketAddr::from(([127, 0, 0, 1], tr_port)),
+            networks: Arc::new(registry::BUILTIN.to_vec()),
+            endpoints: Arc::new(HashMap::new()),
+            isolator: Arc::new(StreamIsolator::new(IsolationMode::None)),
+            close_signal: Arc::new(close_tx),
+            active_connections: Arc::new(AtomicUsize::new(0)),
+            stats: Arc::new(Stats::new()),
+            tor_pool: None,
+            addressbook: None,
+            chains: Arc::new(HashMap::new()),
+            routes: Arc::new(Vec::new()),
         }
     }
 
-    /// Resolve the upstream proxy address based on the target host TLD/heuristics
-    pub fn resolve_upstream(&self, target_host: &str) -> SocketAddr {
-        if target_host.ends_with(".onion") {
-            debug!("Routing {} to Tor", target_host);
-            self.tor_proxy
-        } else if target_host.ends_with(".i2p") {
-            debug!("Routing {} to I2P", target_host);
-            self.i2p_proxy
-        } else if target_host.ends_with(".loki") {
-            debug!("Routing {} to Lokinet", target_host);
-            self.lokinet_proxy
-        } else if target_host.ends_with(".nym") {
-            debug!("Routing {} to Nym", target_host);
-            self.nym_proxy
-        } else if target_host.ends_with(".bit") {
-            debug!("Routing {} to ZeroNet", target_host);
-            self.zeronet_proxy
-        } else if target_host.ends_with(".eth") || target_host.ends_with(".ipfs") {
-            debug!("Routing {} to IPFS", target_host);
-            self.ipfs_proxy
-        } else if target_host.starts_with("USK@")
-            || target_host.starts_with("SSK@")
-            || target_host.ends_with(".freenet")
+    /// Route to the registered networks, in registration order, through the
+    /// endpoints their adapters report. Call before `with_chains`.
+    pub fn with_registry(mut self, registry: &Registry) -> Self {
+        self.networks = Arc::new(registry.iter().map(|e| e.info.clone()).collect());
+        self.endpoints = Arc::new(
+            registry
+                .iter()
+                .map(|e| (e.info.protocol, e.endpoint()))
+                .filter(|(_, endpoint)| *endpoint != Endpoint::None)
+                .collect(),
+        );
+        self
+    }
+
+    /// Set one network's endpoint
+    pub fn with_endpoint(mut self, protocol: Protocol, endpoint: Endpoint) -> Self {
+        Arc::make_mut(&mut self.endpoints).insert(protocol, endpoint);
+        self
+    }
+
+    /// Enable Tor stream isolation
+    pub fn with_isolation(mut self, mode: IsolationMode) -> Self {
+        self.isolator = Arc::new(StreamIsolator::new(mode));
+        self
+    }
+
+    /// Spread Tor streams across the healthy instances of a pool; the fixed
+    /// Tor proxy is only used when no instance is healthy
+    pub fn with_tor_pool(mut self, pool: Arc<TorPool>) -> Self {
+        self.tor_pool = Some(pool);
+        self
+    }
+
+    /// Translate human-readable `.i2p` names to `.b32.i2p` before connecting
+    pub fn with_addressbook(mut self, addressbook: Arc<AddressBook>) -> Self {
+        self.addressbook = Some(addressbook);
+        self
+    }
+
+    /// Build the named chains and route matching destinations through them.
+    /// Fails on unknown networks, local proxies after the first hop and
+    /// rules naming a missing chain.
+    pub fn with_chains(
+        mut self,
+        chains: &HashMap<String, Vec<String>>,
+        routes: &[RouteRule],
+    ) -> anyhow::Result<Self> {
+        let mut built = HashMap::new();
+        for (name, hops) in chains {
+            if hops.is_empty() {
+                anyhow::bail!("chain {:?} has no hops", name);
+            }
+            let hops = hops
+                .iter()
+                .enumerate()
+                .map(|(i, hop)| self.parse_hop(hop, i == 0))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .map_err(|e| anyhow::anyhow!("chain {:?}: {}", name, e))?;
+            built.insert(name.clone(), hops);
+        }
+        if let Some(rule) = routes.iter().find(|r| !built.contains_key(&r.chain)) {
+            anyhow::bail!(
+                "route for {:?} names unknown chain {:?}",
+                rule.host,
+                rule.chain
+            );
+        }
+        self.chains = Arc::new(built);
+        self.routes = Arc::new(routes.to_vec());
+        Ok(self)
+    }
+
+    /// A SOCKS network's local proxy (first hop only) or
+    /// `socks5://[user:pass@]host:port`
+    fn parse_hop(&self, hop: &str, first: bool) -> anyhow::Result<ChainHop> {
+        let Some(rest) = hop
+            .strip_prefix("socks5://")
+            .or_else(|| hop.strip_prefix("socks5h://"))
+        else {
+            let protocol: Protocol = hop.parse()?;
+            match self.endpoints.get(&protocol) {
+                Some(Endpoint::Socks5(_)) => {}
+                Some(endpoint) => {
+                    anyhow::bail!(
+                        "{} has no SOCKS5 proxy to chain through ({})",
+                        hop,
+                        endpoint
+                    )
+                }
+                None => anyhow::bail!("{} has no local endpoint", hop),
+            }
+            if !first {
+                anyhow::bail!(
+                    "{} listens on this machine and cannot be reached through the hop before it; \
+                     list it first or use chain_mode",
+                    hop
+                );
+            }
+            let HostPort { host, port } = self.proxy_for(protocol);
+            return Ok(ChainHop {
+                name: hop.to_ascii_lowercase(),
+                host,
+                port,
+                protocol: Some(protocol),
+                credentials: None,
+            });
+        };
+
+        let (credentials, addr) = match rest.trim_end_matches('/').rsplit_once('@') {
+            Some((user, addr)) => {
+                let (name, pass) = user.split_once(':').unwrap_or((user, ""));
+                (Some((name.to_string(), pass.to_string())), addr)
+            }
+            None => (None, rest.trim_end_matches('/')),
+        };
+        let (host, port) = addr
+            .rsplit_once(':')
+            .and_then(|(h, p)| Some((h.trim_matches(['[', ']']), p.parse::<u16>().ok()?)))
+            .filter(|(h, _)| !h.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("invalid hop {:?}, expected socks5://host:port", hop))?;
+        // The first hop is dialled from here without a DNS lookup
+        if first && host.parse::<std::net::IpAddr>().is_err() {
+            anyhow::bail!("first hop {:?} must be an IP address", hop);
+        }
+        Ok(ChainHop {
+            name: addr.to_string(),
+            host: host.to_string(),
+            port,
+            protocol: None,
+            credentials,
+        })
+    }
+
+    /// Named chain the first matching route rule sends `target_host` through
+    fn route_chain(&self, target_host: &str) -> Option<(&RouteRule, &[ChainHop])> {
+        let rule = self
+            .routes
+            .iter()
+            .find(|r| host_matches(&r.host, target_host))?;
+        Some((rule, self.chains.get(&rule.chain)?))
+    }
+
+    /// Shared isolation state (e.g. to rotate tokens)
+    pub fn isolator(&self) -> Arc<StreamIsolator> {
+        self.isolator.clone()
+    }
+
+    /// Share counters with other components (e.g. the process manager)
+    pub fn with_stats(mut self, stats: Arc<Stats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Connection counters and failure reasons
+    pub fn stats(&self) -> Arc<Stats> {
+        self.stats.clone()
+    }
+
+    /// Number of client connections currently being served
+    pub fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::SeqCst)
+    }
+
+    /// Close every open client connection; returns how many were open
+    pub fn close_connections(&self) -> usize {
+        let open = self.active_connections();
+        self.close_signal.send_modify(|generation| *generation += 1);
+        open
+    }
+
+    /// Decide which network handles the target host (TLDs and schemes, or
+    /// a TUN network's prefixes for IP literals)
+    pub fn resolve_protocol(&self, target_host: &str) -> Protocol {
+        let tun = |info: &&AdapterInfo| match target_host.parse() {
+            Ok(ip) => self
+                .endpoints
+                .get(&info.protocol)
+                .is_some_and(|e| e.routes(ip)),
+            Err(_) => false,
+        };
+        match self
+            .networks
+            .iter()
+            .find(|info| info.handles(target_host))
+            .or_else(|| self.networks.iter().find(tun))
         {
-            debug!("Routing {} to Freenet", target_host);
-            self.freenet_proxy
-        } else if target_host.ends_with(".gnu") || target_host.ends_with(".zkey") {
-            debug!("Routing {} to GNUnet", target_host);
-            self.gnunet_proxy
-        } else if target_host.contains("retroshare") {
-            debug!("Routing {} to RetroShare", target_host);
-            self.retroshare_proxy
-        } else if target_host.contains("tribler") {
-            debug!("Routing {} to Tribler", target_host);
-            self.tribler_proxy
-        } else {
-            debug!("Routing {} to Tor (Default)", target_host);
-            self.tor_proxy
+            Some(info) => info.protocol,
+            None => {
+                debug!(
+                    "No specific network for {}, using Tor (Default)",
+                    target_host
+                );
+                Protocol::Tor
+            }
+        }
+    }
+
+    /// Proxy/gateway address for a network (TUN networks: their resolver).
+    /// Port 0, which never connects, when the network has no endpoint.
+    fn proxy_for(&self, protocol: Protocol) -> HostPort {
+        self.endpoints
+            .get(&protocol)
+            .and_then(Endpoint::addr)
+            .unwrap_or_else(|| HostPort::new("127.0.0.1", 0))
+    }
+
+    /// Whether a network's endpoint speaks SOCKS5 (as opposed to HTTP/API)
+    fn is_socks(&self, protocol: Protocol) -> bool {
+        matches!(self.endpoints.get(&protocol), Some(Endpoint::Socks5(_)))
+    }
+
+    /// Whether a network is reached through its interface rather than a proxy
+    fn is_tun(&self, protocol: Protocol) -> bool {
+        matches!(self.endpoints.get(&protocol), Some(Endpoint::Tun { .. }))
+    }
+
+    /// Resolve the upstream proxy address based on the target host TLD/heuristics
+    pub fn resolve_upstream(&self, target_host: &str) -> HostPort {
+        let protocol = self.resolve_protocol(target_host);
+        debug!("Routing {} to {:?}", target_host, protocol);
+        self.proxy_for(protocol)
+    }
+
+    /// Work out network, upstream and the host to CONNECT to. May query the
+    /// I2P jump service for unknown names.
+    pub async fn plan_route(&self, target_host: &str) -> RoutePlan {
+        if let Some((rule, hops)) = self.route_chain(target_host) {
+            return self.plan_chain(target_host, rule, hops);
+        }
+
+        let protocol = self.resolve_protocol(target_host);
+        let mut plan = RoutePlan {
+            host: target_host.to_string(),
+            protocol,
+            upstream: self.proxy_for(protocol),
+            chain: None,
+            hops: Vec::new(),
+            connect_host: target_host.to_string(),
+            steps: vec![format!("network {:?}", protocol)],
+        };
+
+        if protocol == Protocol::I2p && !target_host.ends_with(".b32.i2p") {
+            match &self.addressbook {
+                Some(book) => match book.resolve(target_host).await {
+                    Some(entry) => {
+                        plan.steps.push(format!(
+                            "address book: {} = {} ({})",
+                            target_host, entry.b32, entry.source
+                        ));
+                        plan.connect_host = entry.b32;
+                    }
+                    None => plan.steps.push(format!(
+                        "address book: {} unknown, left to i2pd",
+                        target_host
+                    )),
+                },
+                None => plan
+                    .steps
+                    .push("no address book, name left to i2pd".to_string()),
+            }
+        }
+
+        if let Some(Endpoint::Tun { interface, dns, .. }) = self.endpoints.get(&protocol) {
+            let is_ip = |host: &str| host.parse::<std::net::IpAddr>().is_ok();
+            if let (Some(resolver), false) = (dns, is_ip(target_host)) {
+                match lokinet_dns::resolve(*resolver, target_host).await {
+                    Ok(addr) => {
+                        plan.steps.push(format!(
+                            "{:?} dns {}: {} = {}",
+                            protocol, resolver, target_host, addr
+                        ));
+                        plan.connect_host = addr.to_string();
+                    }
+                    Err(e) => plan
+                        .steps
+                        .push(format!("{:?} dns {}: {}", protocol, resolver, e)),
+                }
+            }
+            if is_ip(&plan.connect_host) {
+                plan.steps
+                    .push(format!("direct connection through interface {}", interface));
+            }
+        }
+
+        if let (Some(pool), Protocol::Tor) = (&self.tor_pool, protocol) {
+            match pool.pick(target_host) {
+                Some(index) => plan.steps.push(format!(
+                    "tor instance {} of {} ({:?})",
+                    index,
+                    pool.len(),
+                    pool.strategy()
+                )),
+                None => plan
+                    .steps
+                    .push("no healthy tor instance, using the primary".to_string()),
+            }
+        }
+
+        if !self.is_tun(protocol) {
+            plan.steps.push(format!("upstream {}", plan.upstream));
+        }
+        plan
+    }
+
+    /// Plan for a destination a route rule sends through a chain. Names are
+    /// resolved by the last hop, so network-specific translation is skipped.
+    fn plan_chain(&self, target_host: &str, rule: &RouteRule, hops: &[ChainHop]) -> RoutePlan {
+        let first = &hops[0];
+        let protocol = first
+            .protocol
+            .unwrap_or_else(|| self.resolve_protocol(target_host));
+        // parse_hop only accepts IP literals for the first hop
+        let upstream = HostPort::new(&first.host, first.port);
+        let mut steps = vec![format!("route {} -> chain {}", rule.host, rule.chain)];
+        for (i, hop) in hops.iter().enumerate() {
+            steps.push(format!(
+                "hop {} {} ({}:{})",
+                i + 1,
+                hop.name,
+                hop.host,
+                hop.port
+            ));
+        }
+        if let (Some(pool), Some(Protocol::Tor)) = (&self.tor_pool, first.protocol) {
+            match pool.pick(target_host) {
+                Some(index) => steps.push(format!("first hop on tor instance {}", index)),
+                None => steps.push("no healthy tor instance, using the primary".to_string()),
+            }
+        }
+        steps.push(format!("upstream {}", upstream));
+        RoutePlan {
+            host: target_host.to_string(),
+            protocol,
+            upstream,
+            chain: Some(rule.chain.clone()),
+            hops: hops.to_vec(),
+            connect_host: target_host.to_string(),
+            steps,
         }
     }
 
@@ -111,19 +527,23 @@ impl Socks5Server {
     pub async fn serve(&self, listener: TcpListener) -> anyhow::Result<()> {
         loop {
             let (socket, _) = listener.accept().await?;
-            let server = *self;
+            let server = self.clone();
+            let active = self.active_connections.clone();
+            active.fetch_add(1, Ordering::SeqCst);
 
             tokio::spawn(async move {
                 if let Err(e) = handle_connection(socket, server).await {
                     error!("Connection error: {}", e);
                 }
+                active.fetch_sub(1, Ordering::SeqCst);
             });
         }
     }
 }
 
-async fn handle_connection(mut client: TcpStream, server: Socks5Server) -> anyhow::Result<()> {
-    // 1. Handshake
+/// Pick the client's auth method: username/password (0x02) when offered so the
+/// username can drive isolation, otherwise no-auth (0x00).
+async fn negotiate_client_auth(client: &mut TcpStream) -> anyhow::Result<Option<String>> {
     let mut buf = [0u8; 2];
     client.read_exact(&mut buf).await?;
 
@@ -135,8 +555,179 @@ async fn handle_connection(mut client: TcpStream, server: Socks5Server) -> anyho
     let mut methods = vec![0u8; n_methods];
     client.read_exact(&mut methods).await?;
 
-    // We only support no-auth (0x00)
-    client.write_all(&[0x05, 0x00]).await?;
+    if methods.contains(&0x02) {
+        client.write_all(&[0x05, 0x02]).await?;
+
+        // RFC 1929 sub-negotiation; any credentials are accepted
+        let ver = client.read_u8().await?;
+        if ver != 0x01 {
+            return Err(anyhow::anyhow!("Invalid auth sub-negotiation version"));
+        }
+        let ulen = client.read_u8().await? as usize;
+        let mut username = vec![0u8; ulen];
+        client.read_exact(&mut username).await?;
+        let plen = client.read_u8().await? as usize;
+        let mut password = vec![0u8; plen];
+        client.read_exact(&mut password).await?;
+
+        client.write_all(&[0x01, 0x00]).await?;
+        Ok(Some(String::from_utf8_lossy(&username).to_string()))
+    } else if methods.contains(&0x00) {
+        client.write_all(&[0x05, 0x00]).await?;
+        Ok(None)
+    } else {
+        client.write_all(&[0x05, 0xFF]).await?;
+        Err(anyhow::anyhow!("No acceptable SOCKS auth method offered"))
+    }
+}
+
+/// Greet an upstream SOCKS5 proxy, authenticating with username/password
+/// (method 0x02) when isolation credentials are given.
+async fn upstream_handshake(
+    upstream: &mut TcpStream,
+    credentials: Option<&(String, String)>,
+) -> anyhow::Result<()> {
+    match credentials {
+        Some(_) => upstream.write_all(&[0x05, 0x01, 0x02]).await?,
+        None => upstream.write_all(&[0x05, 0x01, 0x00]).await?,
+    }
+    let mut up_buf = [0u8; 2];
+    upstream.read_exact(&mut up_buf).await?;
+
+    match (up_buf[1], credentials) {
+        (0x00, _) => Ok(()),
+        (0x02, Some((username, password))) => {
+            let mut packet = vec![0x01, username.len() as u8];
+            packet.extend_from_slice(username.as_bytes());
+            packet.push(password.len() as u8);
+            packet.extend_from_slice(password.as_bytes());
+            upstream.write_all(&packet).await?;
+
+            let mut status = [0u8; 2];
+            upstream.read_exact(&mut status).await?;
+            if status[1] != 0x00 {
+                return Err(anyhow::anyhow!("Upstream rejected isolation credentials"));
+            }
+            Ok(())
+        }
+        (method, _) => Err(anyhow::anyhow!(
+            "Upstream selected unsupported auth method {:#04x}",
+            method
+        )),
+    }
+}
+
+/// Send a CONNECT request by name (so the upstream resolves it) and return
+/// the upstream's reply code after consuming its bound address
+async fn upstream_connect(
+    upstream: &mut TcpStream,
+    target_host: &str,
+    target_port: u16,
+) -> anyhow::Result<u8> {
+    let mut packet = vec![0x05, 0x01, 0x00, 0x03];
+    packet.push(target_host.len() as u8);
+    packet.extend_from_slice(target_host.as_bytes());
+    packet.extend_from_slice(&target_port.to_be_bytes());
+    upstream.write_all(&packet).await?;
+
+    let mut rep_head = [0u8; 4];
+    upstream.read_exact(&mut rep_head).await?;
+    match rep_head[3] {
+        0x01 => {
+            let mut buf = [0u8; 6];
+            upstream.read_exact(&mut buf).await?;
+        }
+        0x03 => {
+            let len = upstream.read_u8().await?;
+            let mut buf = vec![0u8; len as usize + 2];
+            upstream.read_exact(&mut buf).await?;
+        }
+        0x04 => {
+            let mut buf = [0u8; 18];
+            upstream.read_exact(&mut buf).await?;
+        }
+        _ => {}
+    }
+    Ok(rep_head[1])
+}
+
+/// Tunnel from the first hop to each following one: CONNECT to the next
+/// hop through the stream so far, then greet it. Each step's latency is
+/// recorded under the chain's name.
+async fn extend_chain(
+    upstream: &mut TcpStream,
+    chain: &str,
+    hops: &[ChainHop],
+    stats: &Stats,
+) -> anyhow::Result<()> {
+    for (i, pair) in hops.windows(2).enumerate() {
+        let (prev, hop) = (&pair[0], &pair[1]);
+        let started = Instant::now();
+        let reply = upstream_connect(upstream, &hop.host, hop.port).await?;
+        if reply != 0x00 {
+            return Err(anyhow::anyhow!(
+                "chain {}: {} could not reach hop {}: {} ({:#04x})",
+                chain,
+                prev.name,
+                hop.name,
+                describe_reply(reply),
+                reply
+            ));
+        }
+        upstream_handshake(upstream, hop.credentials.as_ref()).await?;
+        stats.record_hop_latency(chain, i + 1, &hop.name, started.elapsed());
+    }
+    Ok(())
+}
+
+/// Open a CONNECT to `host:port` through the SOCKS5 proxy at `proxy` and
+/// return its reply code (0x00 = the proxy reached the destination)
+pub async fn probe_connect(proxy: SocketAddr, host: &str, port: u16) -> anyhow::Result<u8> {
+    let mut upstream = TcpStream::connect(proxy).await?;
+    upstream_handshake(&mut upstream, None).await?;
+    upstream_connect(&mut upstream, host, port).await
+}
+
+/// Open a stream to `host:port` through the SOCKS5 proxy at `proxy`
+pub async fn connect_through(
+    proxy: SocketAddr,
+    host: &str,
+    port: u16,
+) -> anyhow::Result<TcpStream> {
+    let mut upstream = TcpStream::connect(proxy).await?;
+    upstream_handshake(&mut upstream, None).await?;
+    match upstream_connect(&mut upstream, host, port).await? {
+        0x00 => Ok(upstream),
+        reply => Err(anyhow::anyhow!(
+            "{} could not reach {}:{}: {} ({:#04x})",
+            proxy,
+            host,
+            port,
+            describe_reply(reply),
+            reply
+        )),
+    }
+}
+
+/// Whether the SOCKS5 proxy at `proxy` accepts a no-auth greeting
+pub async fn probe_handshake(proxy: SocketAddr) -> anyhow::Result<()> {
+    let mut upstream = TcpStream::connect(proxy).await?;
+    upstream_handshake(&mut upstream, None).await
+}
+
+/// Reply to the client's CONNECT with an unspecified IPv4 bound address
+async fn send_reply(client: &mut TcpStream, code: u8) -> anyhow::Result<()> {
+    client
+        .write_all(&[0x05, code, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+        .await?;
+    Ok(())
+}
+
+async fn handle_connection(mut client: TcpStream, server: Socks5Server) -> anyhow::Result<()> {
+    let mut close_rx = server.close_signal.subscribe();
+
+    // 1. Handshake
+    let socks_username = negotiate_client_auth(&mut client).await?;
 
     // 2. Request
     let mut head = [0u8; 4];
@@ -179,66 +770,126 @@ async fn handle_connection(mut client: TcpStream, server: Socks5Server) -> anyho
     debug!("Request: {}:{}", target_host, target_port);
 
     // 3. Routing Logic
-    let upstream_addr = server.resolve_upstream(&target_host);
+    let plan = server.plan_route(&target_host).await;
+    let protocol = plan.protocol;
+    let mut upstream_addr = plan.upstream.clone();
+    debug!("{}", plan);
 
-    // 4. Connect to Upstream
-    let mut upstream = TcpStream::connect(upstream_addr).await?;
+    let first_network = plan.first_network();
 
-    // Send success reply to client
-    client
-        .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
-        .await?;
+    // Held for the lifetime of the connection so the pool sees its load
+    let lease = match &server.tor_pool {
+        Some(pool) if first_network == Some(Protocol::Tor) => pool.acquire(&target_host),
+        _ => None,
+    };
+    if let Some(lease) = &lease {
+        debug!("{} -> Tor instance {}", target_host, lease.index());
+        upstream_addr = lease.addr().into();
+    }
 
-    let is_socks_upstream = target_host.ends_with(".onion")
-        || target_host.ends_with(".i2p")
-        || target_host.ends_with(".loki")
-        || target_host.ends_with(".nym")
-        || target_host.ends_with(".gnu"); // GNUnet GNS proxy is SOCKS
+    // TUN networks have no proxy: dial the address (as resolved) directly
+    if server.is_tun(protocol) && plan.chain.is_none() {
+        match plan.connect_host.parse::<std::net::IpAddr>() {
+            Ok(ip) => upstream_addr = SocketAddr::new(ip, target_port).into(),
+            Err(_) => {
+                server.stats.record_connection(protocol);
+                server.stats.record_error("name not resolved");
+                send_reply(&mut client, 0x04).await?;
+                return Err(anyhow::anyhow!(
+                    "{:?} could not resolve {}: {}",
+                    protocol,
+                    target_host,
+                    plan.steps.last().map(String::as_str).unwrap_or("no answer")
+                ));
+            }
+        }
+    }
 
-    if is_socks_upstream {
-        // Handshake with SOCKS5 Upstream
-        upstream.write_all(&[0x05, 0x01, 0x00]).await?;
-        let mut up_buf = [0u8; 2];
-        upstream.read_exact(&mut up_buf).await?;
-
-        // Send Connect Request to Upstream
-        let mut packet = vec![0x05, 0x01, 0x00];
-        if addr_type == 0x03 {
-            packet.push(0x03);
-            packet.push(target_host.len() as u8);
-            packet.extend_from_slice(target_host.as_bytes());
+    // 4. Connect to Upstream
+    server.stats.record_connection(protocol);
+    let dialled = Instant::now();
+    // Configured gateway and API hosts are resolved here, not when routing
+    let mut upstream = match upstream_addr.connect().await {
+        Ok(stream) => stream,
+        Err(e) => {
+            server.stats.record_error("upstream unavailable");
+            send_reply(&mut client, 0x01).await?;
+            return Err(anyhow::anyhow!(
+                "{:?} upstream {} unavailable: {}",
+                protocol,
+                upstream_addr,
+                e
+            ));
+        }
+    };
+
+    if !plan.hops.is_empty() || server.is_socks(protocol) {
+        // Per-stream credentials only matter to Tor, which isolates on them
+        let credentials = if first_network == Some(Protocol::Tor) {
+            server.isolator.credentials(&IsolationContext {
+                socks_username: socks_username.as_deref(),
+                client_addr: client.peer_addr()?,
+                listener_addr: client.local_addr()?,
+                target_host: &target_host,
+            })
         } else {
-            packet.push(0x01);
-            // Re-using dummy logic from before (assumes we have IPv4 in target_host if addr_type=1 which requires string parsing if we didn't save bytes)
-            // Ideally we pass bytes.
-            packet.push(0x03);
-            packet.push(target_host.len() as u8);
-            packet.extend_from_slice(target_host.as_bytes());
-        }
-        packet.extend_from_slice(&target_port.to_be_bytes());
-
-        upstream.write_all(&packet).await?;
-
-        // Read Upstream Reply
-        let mut rep_head = [0u8; 4];
-        upstream.read_exact(&mut rep_head).await?;
-        let up_addr_type = rep_head[3];
-        match up_addr_type {
-            0x01 => {
-                let mut buf = [0u8; 6];
-                upstream.read_exact(&mut buf).await?;
-            }
-            0x03 => {
-                let len = upstream.read_u8().await?;
-                let mut buf = vec![0u8; len as usize + 2];
-                upstream.read_exact(&mut buf).await?;
+            plan.hops.first().and_then(|hop| hop.credentials.clone())
+        };
+
+        // Handshake with SOCKS5 Upstream
+        upstream_handshake(&mut upstream, credentials.as_ref()).await?;
+
+        if let Some(chain) = &plan.chain {
+            server
+                .stats
+                .record_hop_latency(chain, 0, &plan.hops[0].name, dialled.elapsed());
+            if let Err(e) = extend_chain(&mut upstream, chain, &plan.hops, &server.stats).await {
+                server.stats.record_error("chain hop unreachable");
+                send_reply(&mut client, 0x01).await?;
+                return Err(e);
             }
-            _ => {}
         }
+
+        let reply = upstream_connect(&mut upstream, &plan.connect_host, target_port).await?;
+        if reply != 0x00 {
+            let reason = describe_reply(reply);
+            server.stats.record_error(reason);
+
+            // Extended codes only go to clients that opted into Tor's extensions
+            let tor_aware = socks_username
+                .as_deref()
+                .is_some_and(|u| u.starts_with(TOR_EXTENSIONS_PREFIX));
+            let client_code = if is_tor_extended_error(reply) && !tor_aware {
+                0x04 // host unreachable
+            } else {
+                reply
+            };
+            send_reply(&mut client, client_code).await?;
+
+            // Point at the key store when a private onion turned us away
+            let hint = if matches!(reply, 0xF4 | 0xF5) {
+                "; add a key with `chimera_node onion-auth import`"
+            } else {
+                ""
+            };
+            return Err(anyhow::anyhow!(
+                "{:?} could not reach {}: {} ({:#04x}){}",
+                protocol,
+                target_host,
+                reason,
+                reply,
+                hint
+            ));
+        }
+    } else if protocol == Protocol::Lokinet {
+        debug!("Connected through the Lokinet interface: {}", upstream_addr);
     } else {
         debug!("Connected to HTTP/API upstream: {}", upstream_addr);
     }
 
+    // Send success reply to client
+    send_reply(&mut client, 0x00).await?;
+
     // 5. Pipe Data
     let (mut c_rx, mut c_tx) = client.split();
     let (mut u_rx, mut u_tx) = upstream.split();
@@ -246,7 +897,14 @@ async fn handle_connection(mut client: TcpStream, server: Socks5Server) -> anyho
     let client_to_upstream = tokio::io::copy(&mut c_rx, &mut u_tx);
     let upstream_to_client = tokio::io::copy(&mut u_rx, &mut c_tx);
 
-    tokio::try_join!(client_to_upstream, upstream_to_client)?;
+    tokio::select! {
+        res = async { tokio::try_join!(client_to_upstream, upstream_to_client) } => {
+            res?;
+        }
+        _ = close_rx.changed() => {
+            debug!("Closing connection to {} (new identity)", target_host);
+        }
+    }
 
     Ok(())
 }
@@ -254,100 +912,250 @@ async fn handle_connection(mut client: TcpStream, server: Socks5Server) -> anyho
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::adapters::IpPrefix;
 
     // Helper to create a dummy server
     fn create_dummy_server() -> Socks5Server {
-        Socks5Server::new(
-            9050,
-            9051,
-            9052,
-            9053,
-            9054,
-            9055,
-            9056,
-            9057,
-            9058,
-            "http://1.2.3.4:9090".to_string(),
-            "http://5.6.7.8:8085".to_string(),
-        )
+        let local = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        Socks5Server::new(9050)
+            .with_endpoint(Protocol::Tor, Endpoint::Socks5(local(9150)))
+            .with_endpoint(Protocol::I2p, Endpoint::Socks5(local(9052)))
+            .with_endpoint(
+                Protocol::Lokinet,
+                Endpoint::Tun {
+                    interface: "chimera0".to_string(),
+                    prefixes: Vec::new(),
+                    dns: Some(local(9053)),
+                },
+            )
+            .with_endpoint(Protocol::Nym, Endpoint::Socks5(local(9054)))
+            .with_endpoint(Protocol::Ipfs, Endpoint::HttpGateway(local(9055).into()))
+            .with_endpoint(Protocol::ZeroNet, Endpoint::HttpGateway(local(9056).into()))
+            .with_endpoint(Protocol::Freenet, Endpoint::HttpGateway(local(9057).into()))
+            .with_endpoint(Protocol::GnuNet, Endpoint::Socks5(local(9058)))
+            .with_endpoint(
+                Protocol::RetroShare,
+                Endpoint::Api("1.2.3.4:9090".parse().unwrap()),
+            )
+            .with_endpoint(
+                Protocol::Tribler,
+                Endpoint::Api("5.6.7.8:8085".parse().unwrap()),
+            )
+            .with_endpoint(
+                Protocol::Yggdrasil,
+                Endpoint::Tun {
+                    interface: "ygg0".to_string(),
+                    prefixes: vec![IpPrefix::new("200::".parse().unwrap(), 7)],
+                    dns: None,
+                },
+            )
     }
 
     #[test]
     fn test_onion_routing() {
         let server = create_dummy_server();
         let addr = server.resolve_upstream("darkmarket.onion");
-        assert_eq!(addr.port(), 9051);
+        assert_eq!(addr.port, 9150);
     }
 
     #[test]
     fn test_i2p_routing() {
         let server = create_dummy_server();
         let addr = server.resolve_upstream("site.i2p");
-        assert_eq!(addr.port(), 9052);
+        assert_eq!(addr.port, 9052);
     }
 
     #[test]
     fn test_lokinet_routing() {
         let server = create_dummy_server();
+        // Lokinet's resolver, not a proxy
         let addr = server.resolve_upstream("service.loki");
-        assert_eq!(addr.port(), 9053);
+        assert_eq!(addr.port, 9053);
+        let addr = server.resolve_upstream("router.snode");
+        assert_eq!(addr.port, 9053);
+    }
+
+    #[tokio::test]
+    async fn test_tun_prefix_routing() {
+        let server = create_dummy_server();
+        assert_eq!(server.resolve_protocol("201:abcd::1"), Protocol::Yggdrasil);
+        assert_eq!(server.resolve_protocol("93.184.216.34"), Protocol::Tor);
+
+        let plan = server.plan_route("201:abcd::1").await;
+        assert_eq!(plan.connect_host, "201:abcd::1");
+        assert_eq!(
+            plan.steps.last().unwrap(),
+            "direct connection through interface ygg0"
+        );
+        // The HTTP gateway is not a chain hop
+        assert!(create_dummy_server()
+            .with_chains(&chains(&[("c", &["ipfs"])]), &[])
+            .is_err());
     }
 
     #[test]
     fn test_nym_routing() {
         let server = create_dummy_server();
         let addr = server.resolve_upstream("service.nym");
-        assert_eq!(addr.port(), 9054);
+        assert_eq!(addr.port, 9054);
     }
 
     #[test]
     fn test_ipfs_routing() {
         let server = create_dummy_server();
         let addr = server.resolve_upstream("bafy...ipfs");
-        assert_eq!(addr.port(), 9055);
+        assert_eq!(addr.port, 9055);
         let addr = server.resolve_upstream("vitalik.eth");
-        assert_eq!(addr.port(), 9055);
+        assert_eq!(addr.port, 9055);
     }
 
     #[test]
     fn test_zeronet_routing() {
         let server = create_dummy_server();
         let addr = server.resolve_upstream("play.bit");
-        assert_eq!(addr.port(), 9056);
+        assert_eq!(addr.port, 9056);
     }
 
     #[test]
     fn test_freenet_routing() {
         let server = create_dummy_server();
         let addr = server.resolve_upstream("USK@mykey");
-        assert_eq!(addr.port(), 9057);
+        assert_eq!(addr.port, 9057);
     }
 
     #[test]
     fn test_gnunet_routing() {
         let server = create_dummy_server();
         let addr = server.resolve_upstream("gnu.org.gnu");
-        assert_eq!(addr.port(), 9058);
+        assert_eq!(addr.port, 9058);
     }
 
     #[test]
     fn test_retroshare_routing() {
         let server = create_dummy_server();
         let addr = server.resolve_upstream("retroshare://friend");
-        assert_eq!(addr.port(), 9090);
+        assert_eq!(addr.port, 9090);
     }
 
     #[test]
     fn test_tribler_routing() {
         let server = create_dummy_server();
         let addr = server.resolve_upstream("tribler://download");
-        assert_eq!(addr.port(), 8085);
+        assert_eq!(addr.port, 8085);
     }
 
     #[test]
     fn test_default_routing() {
         let server = create_dummy_server();
         let addr = server.resolve_upstream("google.com");
-        assert_eq!(addr.port(), 9051); // Defaults to Tor
+        assert_eq!(addr.port, 9150); // Defaults to Tor
+    }
+
+    #[test]
+    fn test_route_rule_patterns() {
+        assert!(host_matches("*", "anything.example"));
+        assert!(host_matches("*.example.com", "example.com"));
+        assert!(host_matches("*.example.com", "a.b.Example.com"));
+        assert!(!host_matches("*.example.com", "badexample.com"));
+        assert!(host_matches("example.com", "EXAMPLE.com"));
+        assert!(!host_matches("example.com", "www.example.com"));
+    }
+
+    fn chains(list: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        list.iter()
+            .map(|(name, hops)| {
+                (
+                    name.to_string(),
+                    hops.iter().map(|h| h.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn rule(host: &str, chain: &str) -> RouteRule {
+        RouteRule {
+            host: host.to_string(),
+            chain: chain.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_chain_validation() {
+        let build = |hops: &[&str]| {
+            create_dummy_server().with_chains(&chains(&[("c", hops)]), &[rule("*", "c")])
+        };
+        assert!(build(&["nym", "socks5://user:pw@exit.example:1080"]).is_ok());
+        assert!(build(&["socks5://192.0.2.1:1080", "socks5://[2001:db8::1]:1080"]).is_ok());
+        // Local proxies cannot be reached through a remote hop
+        assert!(build(&["socks5://192.0.2.1:1080", "tor"]).is_err());
+        assert!(build(&["ipfs"]).is_err());
+        // No endpoint registered for it
+        assert!(build(&["psiphon"]).is_err());
+        assert!(build(&["lokinet"]).is_err());
+        assert!(build(&["socks5://proxy.example:1080"]).is_err());
+        assert!(build(&["socks5://192.0.2.1"]).is_err());
+        assert!(build(&[]).is_err());
+        assert!(create_dummy_server()
+            .with_chains(&chains(&[("c", &["tor"])]), &[rule("*", "missing")])
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_client_as_first_hop() {
+        let server = create_dummy_server()
+            .with_endpoint(
+                Protocol::Psiphon,
+                Endpoint::Socks5("127.0.0.1:10811".parse().unwrap()),
+            )
+            .with_chains(
+                &chains(&[("censored", &["psiphon"])]),
+                &[rule("*.example.org", "censored")],
+            )
+            .unwrap();
+
+        let plan = server.plan_route("news.example.org").await;
+        assert_eq!(plan.protocol, Protocol::Psiphon);
+        assert_eq!(plan.upstream.port, 10811);
+        assert!(create_dummy_server()
+            .with_chains(&chains(&[("c", &["v2ray"])]), &[])
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_plan_route_through_chain() {
+        let server = create_dummy_server()
+            .with_chains(
+                &chains(&[("stealth", &["nym", "socks5://user:pw@exit.example:1080"])]),
+                &[rule("*.example.com", "stealth")],
+            )
+            .unwrap();
+
+        let plan = server.plan_route("www.example.com").await;
+        assert_eq!(plan.chain.as_deref(), Some("stealth"));
+        assert_eq!(plan.protocol, Protocol::Nym);
+        assert_eq!(plan.upstream.port, 9054);
+        assert_eq!(plan.hops[1].name, "exit.example:1080");
+        assert_eq!(plan.connect_host, "www.example.com");
+        assert_eq!(
+            plan.to_string(),
+            "www.example.com -> chain stealth [nym > exit.example:1080] (connect www.example.com)"
+        );
+        assert!(!plan.steps.iter().any(|s| s.contains("pw")));
+
+        let plan = server.plan_route("other.org").await;
+        assert!(plan.chain.is_none());
+        assert_eq!(plan.protocol, Protocol::Tor);
+    }
+
+    #[test]
+    fn test_describe_tor_extended_errors() {
+        assert!(is_tor_extended_error(0xF0));
+        assert!(is_tor_extended_error(0xF7));
+        assert!(!is_tor_extended_error(0x04));
+        assert!(!is_tor_extended_error(0xF8));
+        assert_eq!(describe_reply(0xF4), "client authorization required");
+        assert_eq!(describe_reply(0xF6), "invalid onion address");
+        assert_eq!(describe_reply(0x05), "connection refused");
+        assert_eq!(describe_reply(0xAA), "unknown error");
     }
 }
@@ -0,0 +1,385 @@
+//! Dependency-ordered startup with readiness gates.
+//!
+//! Each adapter is a node. All nodes start together, and each one waits only
+//! for the nodes it depends on to be ready, so a slow network holds back its
+//! own dependents and nothing else. An adapter is ready once its gate passes (Tor bootstrapped, SOCKS5 port
+//! accepting a handshake, Kubo answering `/api/v0/id`, or its own health
+//! check) within its timeout. Dependents of an adapter that is not ready are
+//! skipped.
+
+use crate::adapters::tor::pool::TorPool;
+use crate::adapters::ProtocolAdapter;
+use crate::health_monitor::Protocol;
+use anyhow::{bail, Result};
+use futures::future::BoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// How often a readiness gate is re-checked
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub type SharedAdapter = Arc<dyn ProtocolAdapter + Send + Sync>;
+
+/// Signal that an adapter can serve traffic and dependents
+#[derive(Clone)]
+pub enum Gate {
+    /// An instance reports bootstrap progress 100 on its control port; an
+    /// attached tor whose control port we can't use passes on SOCKS alone
+    TorBootstrap(Arc<TorPool>),
+    /// The SOCKS5 port accepts a no-auth greeting
+    Socks(SocketAddr),
+    /// The Kubo RPC API at this base URL answers `/api/v0/id`
+    KuboApi(String),
+    /// The adapter's own health check
+    Healthy,
+}
+
+impl fmt::Display for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Gate::TorBootstrap(_) => write!(f, "tor bootstrap 100%"),
+            Gate::Socks(addr) => write!(f, "socks5 handshake on {}", addr),
+            Gate::KuboApi(url) => write!(f, "{}/api/v0/id", url),
+            Gate::Healthy => write!(f, "health check"),
+        }
+    }
+}
+
+impl Gate {
+    async fn is_open(&self, adapter: &SharedAdapter) -> bool {
+        match self {
+            Gate::TorBootstrap(pool) => match pool.bootstrap_progress().await {
+                Some(progress) => progress == 100,
+                None if pool.primary().is_attached() => {
+                    let addr = pool.primary().socks_addr();
+                    crate::socks5::probe_handshake(addr).await.is_ok()
+                }
+                None => false,
+            },
+            Gate::Socks(addr) => crate::socks5::probe_handshake(*addr).await.is_ok(),
+            Gate::KuboApi(url) => reqwest::Client::new()
+                .post(format!("{}/api/v0/id", url))
+                .timeout(Duration::from_secs(5))
+                .send()
+                .await
+                .is_ok_and(|resp| resp.status().is_success()),
+            Gate::Healthy => adapter.is_healthy().await,
+        }
+    }
+}
+
+/// One adapter to start
+pub struct Node {
+    pub protocol: Protocol,
+    pub adapter: SharedAdapter,
+    pub gate: Gate,
+    pub timeout: Duration,
+    /// Networks that must be ready before this one starts
+    pub after: Vec<Protocol>,
+    /// Runs once the dependencies are ready, right before `start` (e.g. to
+    /// point the adapter at a dependency's proxy); counts towards the timeout
+    pub prepare: Option<BoxFuture<'static, Result<()>>>,
+}
+
+impl Node {
+    pub fn new(protocol: Protocol, adapter: SharedAdapter, gate: Gate, timeout: Duration) -> Self {
+        Self {
+            protocol,
+            adapter,
+            gate,
+            timeout,
+            after: Vec::new(),
+            prepare: None,
+        }
+    }
+}
+
+/// Outcome of starting one adapter
+#[derive(Debug, Clone, PartialEq)]
+pub enum Readiness {
+    Ready(Duration),
+    /// Started, but the gate did not pass in time
+    TimedOut(Duration),
+    Failed(String),
+    /// Not started (a dependency is not ready, or the plan excluded it)
+    Skipped(String),
+}
+
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Readiness::Ready(after) => write!(f, "ready in {:.1}s", after.as_secs_f64()),
+            Readiness::TimedOut(limit) => write!(f, "not ready after {}s", limit.as_secs()),
+            Readiness::Failed(e) => write!(f, "failed: {}", e),
+            Readiness::Skipped(why) => write!(f, "skipped: {}", why),
+        }
+    }
+}
+
+/// Readiness of every planned adapter, in the order they settled
+#[derive(Debug, Clone, Default)]
+pub struct StartupReport {
+    pub entries: Vec<(Protocol, String, Readiness)>,
+}
+
+impl StartupReport {
+    pub fn push(&mut self, protocol: Protocol, gate: String, readiness: Readiness) {
+        self.entries.push((protocol, gate, readiness));
+    }
+
+    pub fn get(&self, protocol: Protocol) -> Option<&Readiness> {
+        self.entries
+            .iter()
+            .find(|(p, _, _)| *p == protocol)
+            .map(|(_, _, r)| r)
+    }
+
+    pub fn is_ready(&self, protocol: Protocol) -> bool {
+        matches!(self.get(protocol), Some(Readiness::Ready(_)))
+    }
+}
+
+/// Group networks into stages so that every dependency sits in an earlier
+/// stage. Dependencies outside the graph do not constrain the order.
+pub fn stages(deps: &HashMap<Protocol, Vec<Protocol>>) -> Result<Vec<Vec<Protocol>>> {
+    let mut placed = HashSet::new();
+    let mut stages = Vec::new();
+    while placed.len() < deps.len() {
+        let mut stage: Vec<Protocol> = deps
+            .iter()
+            .filter(|(p, after)| {
+                !placed.contains(*p)
+                    && after
+                        .iter()
+                        .all(|d| placed.contains(d) || !deps.contains_key(d))
+            })
+            .map(|(p, _)| *p)
+            .collect();
+        if stage.is_empty() {
+            let mut cycle: Vec<String> = deps
+                .keys()
+                .filter(|p| !placed.contains(*p))
+                .map(|p| format!("{:?}", p))
+                .collect();
+            cycle.sort();
+            bail!("startup dependencies form a cycle: {}", cycle.join(", "));
+        }
+        stage.sort_by_key(|p| format!("{:?}", p));
+        placed.extend(stage.iter().copied());
+        stages.push(stage);
+    }
+    Ok(stages)
+}
+
+async fn start_node(mut node: Node) -> Readiness {
+    let started = Instant::now();
+    let prepare = node.prepare.take();
+    let attempt = async {
+        if let Some(prepare) = prepare {
+            prepare.await?;
+        }
+        node.adapter.start().await?;
+        while !node.gate.is_open(&node.adapter).await {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    match tokio::time::timeout(node.timeout, attempt).await {
+        Ok(Ok(())) => Readiness::Ready(started.elapsed()),
+        Ok(Err(e)) => Readiness::Failed(e.to_string()),
+        Err(_) => Readiness::TimedOut(node.timeout),
+    }
+}
+
+/// Start every node at once, each after its own dependencies are ready, and
+/// report how each one settled
+pub async fn run(nodes: Vec<Node>) -> Result<StartupReport> {
+    let deps = nodes
+        .iter()
+        .map(|n| (n.protocol, n.after.clone()))
+        .collect();
+    // A cycle would leave its nodes waiting on each other forever
+    stages(&deps)?;
+
+    // Each node announces whether it came up; `None` while it is starting
+    let (mut announce, watch): (HashMap<_, _>, HashMap<_, _>) = deps
+        .keys()
+        .map(|&p| {
+            let (tx, rx) = watch::channel(None::<bool>);
+            ((p, tx), (p, rx))
+        })
+        .unzip();
+
+    let mut settling: FuturesUnordered<_> = nodes
+        .into_iter()
+        .filter_map(|node| {
+            let done = announce.remove(&node.protocol)?;
+            // Dependencies outside the plan do not hold a node back
+            let waits: Vec<_> = node
+                .after
+                .iter()
+                .filter_map(|d| watch.get(d).map(|rx| (*d, rx.clone())))
+                .collect();
+            Some(async move {
+                let (protocol, gate) = (node.protocol, node.gate.to_string());
+                let mut blocked = None;
+                for (dep, mut rx) in waits {
+                    let ready = rx
+                        .wait_for(Option::is_some)
+                        .await
+                        .is_ok_and(|ready| *ready == Some(true));
+                    if !ready {
+                        blocked = Some(dep);
+                        break;
+                    }
+                }
+                let readiness = match blocked {
+                    Some(dep) => Readiness::Skipped(format!("{:?} is not ready", dep)),
+                    None => start_node(node).await,
+                };
+                let _ = done.send(Some(matches!(readiness, Readiness::Ready(_))));
+                (protocol, gate, readiness)
+            })
+        })
+        .collect();
+
+    let mut report = StartupReport::default();
+    while let Some((protocol, gate, readiness)) = settling.next().await {
+        match &readiness {
+            Readiness::Ready(_) => info!("{:?} {} ({})", protocol, readiness, gate),
+            _ => warn!("{:?} {} ({})", protocol, readiness, gate),
+        }
+        report.push(protocol, gate, readiness);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::Endpoint;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_stages_follow_dependencies() {
+        let deps = HashMap::from([
+            (Protocol::Tor, vec![Protocol::Nym]),
+            (Protocol::Nym, vec![]),
+            (Protocol::I2p, vec![]),
+            // Not planned, so it does not hold Lokinet back
+            (Protocol::Lokinet, vec![Protocol::GnuNet]),
+        ]);
+        assert_eq!(
+            stages(&deps).unwrap(),
+            [
+                vec![Protocol::I2p, Protocol::Lokinet, Protocol::Nym],
+                vec![Protocol::Tor]
+            ]
+        );
+
+        let cycle = HashMap::from([
+            (Protocol::Tor, vec![Protocol::Nym]),
+            (Protocol::Nym, vec![Protocol::Tor]),
+        ]);
+        assert!(stages(&cycle).is_err());
+    }
+
+    /// Becomes healthy on start, if it is meant to
+    struct Fake {
+        healthy: AtomicBool,
+        comes_up: bool,
+        log: Arc<Mutex<Vec<&'static str>>>,
+        name: &'static str,
+    }
+
+    #[async_trait]
+    impl ProtocolAdapter for Fake {
+        async fn start(&self) -> Result<()> {
+            self.log.lock().unwrap().push(self.name);
+            self.healthy.store(self.comes_up, Ordering::Relaxed);
+            Ok(())
+        }
+        async fn stop(&self) -> Result<()> {
+            Ok(())
+        }
+        fn endpoint(&self) -> Endpoint {
+            Endpoint::None
+        }
+        async fn is_healthy(&self) -> bool {
+            self.healthy.load(Ordering::Relaxed)
+        }
+    }
+
+    fn node(
+        protocol: Protocol,
+        name: &'static str,
+        comes_up: bool,
+        log: &Arc<Mutex<Vec<&'static str>>>,
+    ) -> Node {
+        let adapter = Arc::new(Fake {
+            healthy: AtomicBool::new(false),
+            comes_up,
+            log: log.clone(),
+            name,
+        });
+        Node::new(protocol, adapter, Gate::Healthy, Duration::from_secs(1))
+    }
+
+    #[tokio::test]
+    async fn test_dependents_wait_for_readiness() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut tor = node(Protocol::Tor, "tor", true, &log);
+        tor.after = vec![Protocol::Nym];
+        let mut i2p = node(Protocol::I2p, "i2p", true, &log);
+        i2p.after = vec![Protocol::Lokinet];
+        let nodes = vec![
+            tor,
+            node(Protocol::Nym, "nym", true, &log),
+            i2p,
+            node(Protocol::Lokinet, "lokinet", false, &log),
+        ];
+
+        let report = run(nodes).await.unwrap();
+        let started = log.lock().unwrap().clone();
+        let position = |name| started.iter().position(|s| *s == name);
+        assert!(position("nym") < position("tor"));
+        assert!(report.is_ready(Protocol::Tor));
+        assert_eq!(
+            report.get(Protocol::Lokinet),
+            Some(&Readiness::TimedOut(Duration::from_secs(1)))
+        );
+        // Never started: its dependency did not come up
+        assert_eq!(position("i2p"), None);
+        assert!(matches!(
+            report.get(Protocol::I2p),
+            Some(Readiness::Skipped(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_slow_node_holds_back_only_its_dependents() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut tor = node(Protocol::Tor, "tor", true, &log);
+        tor.after = vec![Protocol::Nym];
+        let nodes = vec![
+            tor,
+            node(Protocol::Nym, "nym", true, &log),
+            // Never comes up, so it settles only when its timeout expires
+            node(Protocol::Lokinet, "lokinet", false, &log),
+        ];
+
+        let report = run(nodes).await.unwrap();
+        let settled: Vec<Protocol> = report.entries.iter().map(|(p, _, _)| *p).collect();
+        let position = |p| settled.iter().position(|s| *s == p);
+        assert!(position(Protocol::Tor) < position(Protocol::Lokinet));
+        assert!(report.is_ready(Protocol::Tor));
+    }
+}
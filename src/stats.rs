@@ -0,0 +1,111 @@
+//! Connection statistics shared by the SOCKS5 server and the control surface.
+
+use crate::health_monitor::Protocol;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    connections_total: AtomicU64,
+    connections_failed: AtomicU64,
+    per_protocol: Mutex<HashMap<Protocol, u64>>,
+    /// Failure counts keyed by human-readable reason
+    errors: Mutex<HashMap<String, u64>>,
+    /// Latest statistics reported by network daemons (e.g. the I2P router)
+    networks: Mutex<HashMap<String, serde_json::Value>>,
+    /// Per-hop latency of Chimera-built chains, keyed by chain name
+    chains: Mutex<HashMap<String, Vec<HopLatency>>>,
+}
+
+/// Time to reach one hop of a chain (connect or CONNECT, plus greeting)
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HopLatency {
+    pub hop: String,
+    pub samples: u64,
+    pub last_ms: u64,
+    pub avg_ms: f64,
+}
+
+/// Point-in-time copy of the counters
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StatsSnapshot {
+    pub connections_total: u64,
+    pub connections_failed: u64,
+    pub per_protocol: HashMap<String, u64>,
+    pub errors: HashMap<String, u64>,
+    pub networks: HashMap<String, serde_json::Value>,
+    pub chains: HashMap<String, Vec<HopLatency>>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a CONNECT routed to a network
+    pub fn record_connection(&self, protocol: Protocol) {
+        self.connections_total.fetch_add(1, Ordering::Relaxed);
+        *self
+            .per_protocol
+            .lock()
+            .unwrap()
+            .entry(protocol)
+            .or_insert(0) += 1;
+    }
+
+    /// Count a failed CONNECT under a human-readable reason
+    pub fn record_error(&self, reason: &str) {
+        self.connections_failed.fetch_add(1, Ordering::Relaxed);
+        *self
+            .errors
+            .lock()
+            .unwrap()
+            .entry(reason.to_string())
+            .or_insert(0) += 1;
+    }
+
+    /// Replace the statistics a network daemon last reported
+    pub fn record_network<T: Serialize>(&self, network: &str, stats: &T) {
+        if let Ok(value) = serde_json::to_value(stats) {
+            self.networks
+                .lock()
+                .unwrap()
+                .insert(network.to_string(), value);
+        }
+    }
+
+    /// Add a latency sample for hop `index` of `chain`
+    pub fn record_hop_latency(&self, chain: &str, index: usize, hop: &str, latency: Duration) {
+        let mut chains = self.chains.lock().unwrap();
+        let hops = chains.entry(chain.to_string()).or_default();
+        if hops.len() <= index {
+            hops.resize(index + 1, HopLatency::default());
+        }
+        let entry = &mut hops[index];
+        let ms = latency.as_millis() as u64;
+        entry.hop = hop.to_string();
+        entry.samples += 1;
+        entry.last_ms = ms;
+        entry.avg_ms += (ms as f64 - entry.avg_ms) / entry.samples as f64;
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            connections_total: self.connections_total.load(Ordering::Relaxed),
+            connections_failed: self.connections_failed.load(Ordering::Relaxed),
+            per_protocol: self
+                .per_protocol
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(p, n)| (format!("{:?}", p).to_lowercase(), *n))
+                .collect(),
+            errors: self.errors.lock().unwrap().clone(),
+            networks: self.networks.lock().unwrap().clone(),
+            chains: self.chains.lock().unwrap().clone(),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Stand-in SOCKS5 proxy for the unit tests and, through `tests/common`,
+//! the integration tests.
+#![allow(dead_code)]
+
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+pub const SUCCEEDED: u8 = 0x00;
+
+/// A CONNECT request as the proxy saw it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connect {
+    pub host: String,
+    pub port: u16,
+    /// RFC 1929 username, when the client offered username/password
+    pub username: Option<String>,
+}
+
+impl Connect {
+    pub fn target(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+/// Read the greeting, username/password when offered, and a CONNECT
+pub async fn accept_connect(socket: &mut TcpStream) -> io::Result<Connect> {
+    let mut head = [0u8; 2];
+    socket.read_exact(&mut head).await?;
+    let mut methods = vec![0u8; head[1] as usize];
+    socket.read_exact(&mut methods).await?;
+
+    let username = if methods.contains(&0x02) {
+        socket.write_all(&[0x05, 0x02]).await?;
+        let mut ver_ulen = [0u8; 2];
+        socket.read_exact(&mut ver_ulen).await?;
+        let mut user = vec![0u8; ver_ulen[1] as usize];
+        socket.read_exact(&mut user).await?;
+        let plen = socket.read_u8().await?;
+        let mut pass = vec![0u8; plen as usize];
+        socket.read_exact(&mut pass).await?;
+        socket.write_all(&[0x01, 0x00]).await?;
+        Some(String::from_utf8_lossy(&user).to_string())
+    } else {
+        socket.write_all(&[0x05, 0x00]).await?;
+        None
+    };
+
+    let mut request = [0u8; 4];
+    socket.read_exact(&mut request).await?;
+    let host = match request[3] {
+        0x01 => {
+            let mut ip = [0u8; 4];
+            socket.read_exact(&mut ip).await?;
+            std::net::Ipv4Addr::from(ip).to_string()
+        }
+        0x03 => {
+            let len = socket.read_u8().await?;
+            let mut name = vec![0u8; len as usize];
+            socket.read_exact(&mut name).await?;
+            String::from_utf8_lossy(&name).to_string()
+        }
+        0x04 => {
+            let mut ip = [0u8; 16];
+            socket.read_exact(&mut ip).await?;
+            std::net::Ipv6Addr::from(ip).to_string()
+        }
+        atyp => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported ATYP {}", atyp),
+            ))
+        }
+    };
+    let port = socket.read_u16().await?;
+    Ok(Connect {
+        host,
+        port,
+        username,
+    })
+}
+
+/// Answer a CONNECT with `code` and an empty IPv4 bound address
+pub async fn reply(socket: &mut TcpStream, code: u8) -> io::Result<()> {
+    socket
+        .write_all(&[0x05, code, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+        .await
+}
+
+/// SOCKS5 proxy on a free loopback port. Each CONNECT is reported on the
+/// channel and answered with `code`; successful streams are echoed back.
+/// Clients that only greet (health probes) are answered too.
+pub async fn socks_proxy(code: u8) -> (SocketAddr, mpsc::UnboundedReceiver<Connect>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let Ok(connect) = accept_connect(&mut socket).await else {
+                    return;
+                };
+                let _ = tx.send(connect);
+                if reply(&mut socket, code).await.is_ok() && code == SUCCEEDED {
+                    let (mut rd, mut wr) = socket.split();
+                    let _ = tokio::io::copy(&mut rd, &mut wr).await;
+                }
+            });
+        }
+    });
+    (addr, rx)
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use chimera_node::adapters::tor::TorAdapter;
+use chimera_node::adapters::v2ray::V2RayAdapter;
+use chimera_node::adapters::ProtocolAdapter;
+use chimera_node::config::{AdapterMode, Settings};
+use tokio::net::TcpListener;
+
+/// Stand-in for a daemon someone else runs
+async fn running_daemon() -> u16 {
+    common::socks_proxy(common::SUCCEEDED).await.0.port()
+}
+
+#[tokio::test]
+async fn test_external_tor() {
+    let port = running_daemon().await;
+    let mut settings = Settings::defaults().unwrap().tor;
+    settings.enabled = true;
+    settings.mode = AdapterMode::External;
+    settings.socks_port = port;
+    // Never spawned: the binary doesn't exist
+    settings.binary_path = "bin/does-not-exist".to_string();
+
+    let tor = TorAdapter::new(settings.clone());
+    tor.start().await.unwrap();
+    assert!(tor.is_attached());
+    assert!(tor.is_healthy().await);
+    tor.stop().await.unwrap();
+    assert!(!tor.is_attached());
+
+    // Nothing answers on the port any more
+    let released = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    settings.socks_port = released.local_addr().unwrap().port();
+    drop(released);
+    let err = TorAdapter::new(settings).start().await.unwrap_err();
+    assert!(err.to_string().contains("nothing answers"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_running_tor_cannot_join_a_chain() {
+    let port = running_daemon().await;
+    let mut settings = Settings::defaults().unwrap().tor;
+    settings.enabled = true;
+    settings.mode = AdapterMode::Auto;
+    settings.socks_port = port;
+    settings.binary_path = "bin/does-not-exist".to_string();
+
+    // tor_over_nym points Tor at Nym before starting it
+    let tor = TorAdapter::new(settings);
+    tor.set_upstream_proxy("127.0.0.1:1080".to_string()).await;
+    let err = tor.start().await.unwrap_err();
+    assert!(err.to_string().contains("tor.mode"), "{}", err);
+    assert!(!tor.is_attached());
+}
+
+#[tokio::test]
+async fn test_auto_attaches_before_spawning() {
+    let port = running_daemon().await;
+    let v2ray = V2RayAdapter::new(true, "bin/does-not-exist".to_string(), None, port)
+        .with_mode(AdapterMode::Auto);
+    // Spawning the missing binary would fail
+    v2ray.start().await.unwrap();
+    assert!(v2ray.is_healthy().await);
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use chimera_node::adapters::gnunet::GnunetAdapter;
+use chimera_node::adapters::{Capability, ProtocolAdapter};
+use chimera_node::config::{AdapterMode, GnunetSettings, Settings};
+use chimera_node::health_monitor::Protocol;
+use chimera_node::process_manager::ProcessManager;
+use std::collections::HashSet;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[test]
+fn test_registry_capabilities() {
+    let settings = Settings::defaults().unwrap();
+    let pm = ProcessManager::new(&settings, HashSet::new());
+    let with = |capability| -> Vec<Protocol> {
+        pm.registry()
+            .with_capability(capability)
+            .map(|e| e.info.protocol)
+            .collect()
+    };
+
+    assert_eq!(
+        with(Capability::ContentStore),
+        [Protocol::Ipfs, Protocol::Freenet]
+    );
+    assert_eq!(with(Capability::Messaging), [Protocol::Nym]);
+    assert_eq!(
+        with(Capability::TunnelInterface),
+        [
+            Protocol::Lokinet,
+            Protocol::WireGuard,
+            Protocol::Yggdrasil,
+            Protocol::Cjdns
+        ]
+    );
+    let streams = with(Capability::StreamTransport);
+    for protocol in [
+        Protocol::Tor,
+        Protocol::I2p,
+        Protocol::Nym,
+        Protocol::Psiphon,
+    ] {
+        assert!(streams.contains(&protocol), "{:?}", protocol);
+    }
+    assert!(!streams.contains(&Protocol::Ipfs));
+    assert!(!streams.contains(&Protocol::RetroShare));
+}
+
+#[tokio::test]
+async fn test_stream_transport_through_socks() {
+    // Stand-in SOCKS5 proxy that accepts any CONNECT and echoes
+    let (proxy, mut connects) = common::socks_proxy(common::SUCCEEDED).await;
+    let port = proxy.port();
+
+    let gnunet = GnunetAdapter::new(GnunetSettings {
+        enabled: true,
+        mode: AdapterMode::External,
+        binary_path: "gnunet-arm".to_string(),
+        socks_port: port,
+    });
+    let transport = gnunet.as_stream_transport().unwrap();
+    let mut stream = transport.open("peer.gnu", 80).await.unwrap();
+    stream.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+    assert_eq!(connects.recv().await.unwrap().target(), "peer.gnu:80");
+    assert!(gnunet.as_content_store().is_none());
+}
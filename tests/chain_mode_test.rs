@@ -0,0 +1,16 @@
+use chimera_node::config::{ChainMode, Settings};
+use chimera_node::health_monitor::Protocol;
+use chimera_node::process_manager::ProcessManager;
+use std::collections::HashSet;
+
+#[tokio::test]
+async fn test_nym_over_tor_refused_before_start() {
+    let mut settings = Settings::defaults().unwrap();
+    settings.chain_mode = ChainMode::NymOverTor;
+    let enabled = HashSet::from([Protocol::Tor, Protocol::Nym]);
+    let pm = ProcessManager::new(&settings, enabled);
+
+    let err = pm.start_processes().await.unwrap_err();
+    assert!(err.to_string().contains("nym_over_tor"), "{}", err);
+    assert!(pm.readiness().entries.is_empty());
+}
@@ -0,0 +1,7 @@
+//! The stand-in SOCKS5 proxy from `src/test_support.rs`
+#![allow(dead_code)]
+
+#[path = "../../src/test_support.rs"]
+mod test_support;
+
+pub use test_support::*;
@@ -0,0 +1,218 @@
+use chimera_node::config::{IsolationMode, Settings};
+use chimera_node::control::{send_command, ControlAuth, ControlServer};
+use chimera_node::health_monitor::Protocol;
+use chimera_node::ports;
+use chimera_node::process_manager::ProcessManager;
+use chimera_node::socks5::Socks5Server;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Process manager with no protocols enabled
+fn idle_process_manager(settings: &Settings) -> Arc<ProcessManager> {
+    Arc::new(ProcessManager::new(settings, HashSet::new()))
+}
+
+fn idle_server() -> Socks5Server {
+    Socks5Server::new(0)
+}
+
+/// Running control port and the cookie it accepts
+struct Control {
+    port: u16,
+    auth: ControlAuth,
+}
+
+impl Control {
+    async fn send(&self, command: &str) -> anyhow::Result<Vec<String>> {
+        send_command(self.port, &self.auth, command).await
+    }
+}
+
+async fn spawn_control(pm: Arc<ProcessManager>, server: Socks5Server) -> Control {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let auth = ControlAuth::new(None).unwrap();
+    let control = ControlServer::new(port, pm, server, auth.clone());
+    tokio::spawn(async move {
+        control.serve(listener).await.unwrap();
+    });
+    Control { port, auth }
+}
+
+#[tokio::test]
+async fn test_control_new_identity_rotates_isolation() {
+    let settings = Settings::defaults().unwrap();
+    // No protocols enabled: only the isolation tokens rotate
+    let pm = idle_process_manager(&settings);
+    let server = idle_server().with_isolation(IsolationMode::DestinationDomain);
+    let control = spawn_control(pm, server.clone()).await;
+
+    let reply = control.send("NEWIDENTITY CLOSE").await.unwrap();
+    assert_eq!(reply.last().unwrap(), "250 OK");
+    assert!(reply[0].contains("isolation_epoch=1"));
+    assert_eq!(server.isolator().epoch(), 1);
+
+    let reply = control.send("STATUS").await.unwrap();
+    assert!(reply.iter().any(|l| l == "250-tor=down"));
+    assert!(reply.iter().any(|l| l == "250-psiphon=down"));
+
+    assert!(control.send("BOGUS").await.is_err());
+}
+
+#[tokio::test]
+async fn test_control_new_identity_reports_failures() {
+    let settings = Settings::defaults().unwrap();
+    // Tor is enabled but not running, so NEWNYM can't be sent
+    let pm = Arc::new(ProcessManager::new(
+        &settings,
+        HashSet::from([Protocol::Tor]),
+    ));
+    let control = spawn_control(pm, idle_server()).await;
+
+    let err = control.send("NEWIDENTITY").await.unwrap_err();
+    assert!(
+        err.to_string().contains("550 New identity incomplete"),
+        "{}",
+        err
+    );
+    assert!(err.to_string().contains("failed[Tor]"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_control_onion_auth_keys() {
+    let dir = std::env::temp_dir().join(format!("chimera-control-auth-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut settings = Settings::defaults().unwrap();
+    settings.tor.client_auth_dir = dir.to_string_lossy().to_string();
+    let control = spawn_control(idle_process_manager(&settings), idle_server()).await;
+
+    let onion = "25njqamcweflpvkl73j4szahhihoc4xt3ktcgjnpaingr5yhkenl5sid";
+    let line = format!(
+        "{}:descriptor:x25519:3FVW6TKGMN3YIL4YCXKVWGNWJ3GTI4ZF6FHQUBR4EKNVZP2XBJQQ",
+        onion
+    );
+
+    // Tor is not running, so the key is only stored
+    control
+        .send(&format!("ONIONAUTH ADD {}", line))
+        .await
+        .unwrap();
+    let reply = control.send("ONIONAUTH LIST").await.unwrap();
+    assert_eq!(reply[0], format!("250-{}.onion", onion));
+
+    assert!(control.send("ONIONAUTH ADD not-a-key").await.is_err());
+
+    let reply = control
+        .send(&format!("ONIONAUTH REMOVE {}.onion", onion))
+        .await
+        .unwrap();
+    assert_eq!(reply[0], "250-removed=true");
+    let reply = control.send("ONIONAUTH LIST").await.unwrap();
+    assert_eq!(reply, vec!["250 OK"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_control_status_reports_allocated_ports() {
+    let mut settings = Settings::defaults().unwrap();
+    // Keep clear of anything bound on the defaults
+    for port in [&mut settings.server.port, &mut settings.server.control_port] {
+        *port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+    }
+    settings.tor.enabled = true;
+    settings.tor.socks_port = 0; // "auto"
+    settings.tor.control_port = 0;
+    let enabled = HashSet::from([Protocol::Tor]);
+    ports::allocate(&mut settings, &enabled).unwrap();
+    let socks = settings.tor.socks_port;
+    assert_ne!(socks, 0);
+
+    let pm = Arc::new(ProcessManager::new(&settings, enabled));
+    let control = spawn_control(pm, idle_server()).await;
+    let reply = control.send("STATUS").await.unwrap();
+    let line = format!("250-endpoint.tor=socks5 127.0.0.1:{}", socks);
+    assert!(reply.contains(&line), "{:?}", reply);
+}
+
+/// Raw exchange: send `lines`, read until the node closes the connection
+async fn raw_exchange(port: u16, lines: &str) -> String {
+    let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+        .await
+        .unwrap();
+    stream.write_all(lines.as_bytes()).await.unwrap();
+    let mut reply = Vec::new();
+    // Unread input may turn the close into a reset; keep what arrived
+    let _ = stream.read_to_end(&mut reply).await;
+    String::from_utf8(reply).unwrap()
+}
+
+#[tokio::test]
+async fn test_control_requires_authentication() {
+    let settings = Settings::defaults().unwrap();
+    let control = spawn_control(idle_process_manager(&settings), idle_server()).await;
+
+    // Commands before AUTHENTICATE are refused and the connection closed
+    let reply = raw_exchange(control.port, "NEWIDENTITY CLOSE\r\n").await;
+    assert_eq!(reply, "515 Authentication failed\r\n");
+    // Cross-protocol requests from a browser get no reply at all
+    let reply = raw_exchange(
+        control.port,
+        "POST / HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\nNEWIDENTITY\r\n",
+    )
+    .await;
+    assert_eq!(reply, "");
+
+    let stranger = ControlAuth::new(None).unwrap();
+    let err = send_command(control.port, &stranger, "STATUS")
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("515"), "{}", err);
+    control.send("STATUS").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_control_cookie_file_and_password() {
+    let dir = std::env::temp_dir().join(format!("chimera-control-cookie-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut server_settings = Settings::defaults().unwrap().server;
+    server_settings.control_cookie_file = dir.join("cookie").to_string_lossy().to_string();
+    server_settings.control_password = Some("s3cret \"quoted\"".to_string());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let settings = Settings::defaults().unwrap();
+    let control = ControlServer::new(
+        port,
+        idle_process_manager(&settings),
+        idle_server(),
+        ControlAuth::create(&server_settings).unwrap(),
+    );
+    tokio::spawn(async move {
+        control.serve(listener).await.unwrap();
+    });
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&server_settings.control_cookie_file)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // The CLI authenticates with the password, or with the cookie file
+    let by_password = ControlAuth::load(&server_settings).unwrap();
+    send_command(port, &by_password, "STATUS").await.unwrap();
+    server_settings.control_password = None;
+    let by_cookie = ControlAuth::load(&server_settings).unwrap();
+    send_command(port, &by_cookie, "STATUS").await.unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,188 @@
+mod common;
+
+use chimera_node::adapters::i2p::addressbook::{AddressBook, AddressSource};
+use chimera_node::adapters::i2p::destination::b32_from_base64;
+use chimera_node::adapters::Endpoint;
+use chimera_node::config::I2pAddressBookSettings;
+use chimera_node::health_monitor::Protocol;
+use chimera_node::socks5::Socks5Server;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+const PETNAME_B32: &str = "ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkdq.b32.i2p";
+
+/// I2P-base64 destination with a NULL certificate
+fn destination() -> String {
+    let mut bytes = vec![0x42u8; 384];
+    bytes.extend_from_slice(&[0, 0, 0]);
+    data_encoding::BASE64
+        .encode(&bytes)
+        .replace('+', "-")
+        .replace('/', "~")
+}
+
+fn cache_path(tag: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("chimera-ab-{}-{}.txt", tag, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn dummy_server(i2p_port: u16) -> Socks5Server {
+    Socks5Server::new(0).with_endpoint(
+        Protocol::I2p,
+        Endpoint::Socks5(SocketAddr::from(([127, 0, 0, 1], i2p_port))),
+    )
+}
+
+#[tokio::test]
+async fn test_petname_translated_before_connect() {
+    // Mock i2pd SOCKS port that reports the host it was asked for
+    let (i2p_addr, mut rx) = common::socks_proxy(common::SUCCEEDED).await;
+    let i2p_port = i2p_addr.port();
+
+    let mut settings = I2pAddressBookSettings::default();
+    settings
+        .petnames
+        .insert("stats.i2p".to_string(), PETNAME_B32.to_string());
+    let book = Arc::new(AddressBook::new(settings, cache_path("petname")));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let server = dummy_server(i2p_port).with_addressbook(book);
+
+    let plan = server.plan_route("stats.i2p").await;
+    assert_eq!(plan.connect_host, PETNAME_B32);
+    assert!(plan.steps.iter().any(|s| s.contains("petname")));
+
+    tokio::spawn(async move {
+        server.serve(listener).await.unwrap();
+    });
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut buf = [0u8; 2];
+    client.read_exact(&mut buf).await.unwrap();
+    let target = "stats.i2p";
+    let mut pkt = vec![0x05, 0x01, 0x00, 0x03, target.len() as u8];
+    pkt.extend_from_slice(target.as_bytes());
+    pkt.extend_from_slice(&80u16.to_be_bytes());
+    client.write_all(&pkt).await.unwrap();
+    let mut reply = [0u8; 10];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+
+    assert_eq!(rx.recv().await.unwrap().host, PETNAME_B32);
+}
+
+#[tokio::test]
+async fn test_jump_service_lookup() {
+    // Stand-in for i2pd's HTTP proxy in front of a jump service
+    let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = proxy.local_addr().unwrap();
+    let dest = destination();
+    let misses = Arc::new(AtomicUsize::new(0));
+    let counter = misses.clone();
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = proxy.accept().await.unwrap();
+            let dest = dest.clone();
+            let counter = counter.clone();
+            tokio::spawn(async move {
+                let (rx, mut tx) = socket.into_split();
+                let mut lines = BufReader::new(rx).lines();
+                let request = lines.next_line().await.unwrap().unwrap();
+                while let Some(line) = lines.next_line().await.unwrap() {
+                    if line.is_empty() {
+                        break;
+                    }
+                }
+                let response = if request.contains("/jump?a=fresh.i2p") {
+                    format!(
+                        "HTTP/1.1 302 Found\r\nLocation: http://fresh.i2p/?i2paddresshelper={}\r\nContent-Length: 0\r\n\r\n",
+                        dest
+                    )
+                } else {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string()
+                };
+                tx.write_all(response.as_bytes()).await.unwrap();
+            });
+        }
+    });
+
+    let settings = I2pAddressBookSettings {
+        jump_service: Some("http://jump.i2p/jump?a=".to_string()),
+        ..Default::default()
+    };
+    let book = Arc::new(
+        AddressBook::new(settings, cache_path("jump"))
+            .with_http_proxy(format!("http://{}", proxy_addr)),
+    );
+
+    let entry = book.resolve("fresh.i2p").await.unwrap();
+    assert_eq!(entry.b32, b32_from_base64(&destination()).unwrap());
+    assert!(matches!(entry.source, AddressSource::JumpService(_)));
+    // Remembered for later lookups without the network
+    assert_eq!(book.lookup("fresh.i2p"), Some(entry));
+
+    assert!(book.resolve("nobody.i2p").await.is_none());
+
+    let server = dummy_server(4447).with_addressbook(book);
+    let plan = server.plan_route("nobody.i2p").await;
+    assert_eq!(plan.connect_host, "nobody.i2p");
+    assert!(plan.steps.iter().any(|s| s.contains("unknown")));
+    // The failed lookup is remembered rather than asked again
+    assert_eq!(misses.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_failed_subscription_does_not_stop_refresh() {
+    // Stand-in for i2pd's HTTP proxy: the first subscription errors, the
+    // second serves a hosts.txt
+    let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = proxy.local_addr().unwrap();
+    let hosts = format!("listed.i2p={}\n", destination());
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = proxy.accept().await.unwrap();
+            let hosts = hosts.clone();
+            tokio::spawn(async move {
+                let (rx, mut tx) = socket.into_split();
+                let mut lines = BufReader::new(rx).lines();
+                let request = lines.next_line().await.unwrap().unwrap();
+                while let Some(line) = lines.next_line().await.unwrap() {
+                    if line.is_empty() {
+                        break;
+                    }
+                }
+                let response = if request.contains("broken.i2p") {
+                    "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n".to_string()
+                } else {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                        hosts.len(),
+                        hosts
+                    )
+                };
+                tx.write_all(response.as_bytes()).await.unwrap();
+            });
+        }
+    });
+
+    let settings = I2pAddressBookSettings {
+        subscriptions: vec![
+            "http://broken.i2p/hosts.txt".to_string(),
+            "http://working.i2p/hosts.txt".to_string(),
+        ],
+        ..Default::default()
+    };
+    let book = AddressBook::new(settings, cache_path("subscriptions"))
+        .with_http_proxy(format!("http://{}", proxy_addr));
+
+    assert_eq!(book.refresh_subscriptions().await.unwrap(), 1);
+    let entry = book.lookup("listed.i2p").unwrap();
+    assert_eq!(entry.b32, b32_from_base64(&destination()).unwrap());
+}
@@ -0,0 +1,162 @@
+use chimera_node::adapters::i2p::sam::{SamClient, SessionStyle};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, UdpSocket};
+
+const SESSION_PRIV: &str = "sessionPrivKeys~AAAA==";
+const SESSION_DEST: &str = "sessionPubDest~AAAA";
+const KNOWN_DEST: &str = "knownPeerDest-AAAA";
+
+fn arg<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    line.split_whitespace()
+        .find_map(|t| t.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// Local SAM stand-in: answers the v3 commands the client uses, echoes
+/// streams to known destinations and reflects datagrams back to the session
+async fn spawn_fake_sam() -> SamClient {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let tcp_addr = listener.local_addr().unwrap();
+    let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let udp_addr = udp.local_addr().unwrap();
+    let forward: Arc<Mutex<Option<SocketAddr>>> = Arc::new(Mutex::new(None));
+
+    let udp_forward = forward.clone();
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 65536];
+        loop {
+            let (n, _) = udp.recv_from(&mut buf).await.unwrap();
+            let newline = buf[..n].iter().position(|b| *b == b'\n').unwrap();
+            let header = String::from_utf8_lossy(&buf[..newline]).to_string();
+            assert!(header.starts_with("3.0 dgram "));
+            let dest = header.split_whitespace().nth(2).unwrap().to_string();
+            let mut reply = format!("{} FROM_PORT=0 TO_PORT=0\n", dest).into_bytes();
+            reply.extend_from_slice(&buf[newline + 1..n]);
+            let target = udp_forward.lock().unwrap().unwrap();
+            udp.send_to(&reply, target).await.unwrap();
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let forward = forward.clone();
+            tokio::spawn(async move {
+                let (rx, mut tx) = socket.into_split();
+                let mut rx = BufReader::new(rx);
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if rx.read_line(&mut line).await.unwrap() == 0 {
+                        return;
+                    }
+                    let reply = if line.starts_with("HELLO VERSION") {
+                        "HELLO REPLY RESULT=OK VERSION=3.3".to_string()
+                    } else if line.starts_with("DEST GENERATE") {
+                        "DEST REPLY PUB=newPub~AAAA PRIV=newPriv~AAAA==".to_string()
+                    } else if line.starts_with("NAMING LOOKUP") {
+                        match arg(&line, "NAME").unwrap() {
+                            "ME" => {
+                                format!("NAMING REPLY RESULT=OK NAME=ME VALUE={}", SESSION_DEST)
+                            }
+                            "known.i2p" => {
+                                format!(
+                                    "NAMING REPLY RESULT=OK NAME=known.i2p VALUE={}",
+                                    KNOWN_DEST
+                                )
+                            }
+                            name => format!("NAMING REPLY RESULT=KEY_NOT_FOUND NAME={}", name),
+                        }
+                    } else if line.starts_with("SESSION CREATE") {
+                        if let Some(port) = arg(&line, "PORT") {
+                            let host = arg(&line, "HOST").unwrap();
+                            *forward.lock().unwrap() =
+                                Some(format!("{}:{}", host, port).parse().unwrap());
+                        }
+                        format!("SESSION STATUS RESULT=OK DESTINATION={}", SESSION_PRIV)
+                    } else if line.starts_with("STREAM CONNECT") {
+                        if arg(&line, "DESTINATION") != Some(KNOWN_DEST) {
+                            let msg = "STREAM STATUS RESULT=CANT_REACH_PEER MESSAGE=\"no route\"\n";
+                            tx.write_all(msg.as_bytes()).await.unwrap();
+                            continue;
+                        }
+                        tx.write_all(b"STREAM STATUS RESULT=OK\n").await.unwrap();
+                        tokio::io::copy(&mut rx, &mut tx).await.unwrap();
+                        return;
+                    } else if line.starts_with("STREAM ACCEPT") {
+                        tx.write_all(b"STREAM STATUS RESULT=OK\n").await.unwrap();
+                        let incoming = format!("{} FROM_PORT=0 TO_PORT=0\nhi there", KNOWN_DEST);
+                        tx.write_all(incoming.as_bytes()).await.unwrap();
+                        return;
+                    } else {
+                        "ERROR RESULT=I2P_ERROR".to_string()
+                    };
+                    tx.write_all(format!("{}\n", reply).as_bytes())
+                        .await
+                        .unwrap();
+                }
+            });
+        }
+    });
+
+    SamClient::new(tcp_addr, udp_addr)
+}
+
+#[tokio::test]
+async fn test_sam_naming_and_dest_generate() {
+    let sam = spawn_fake_sam().await;
+    assert!(sam.probe().await);
+
+    assert_eq!(sam.naming_lookup("known.i2p").await.unwrap(), KNOWN_DEST);
+    let err = sam.naming_lookup("missing.i2p").await.unwrap_err();
+    assert!(err.to_string().contains("KEY_NOT_FOUND"));
+
+    let keys = sam.generate_destination(7).await.unwrap();
+    assert_eq!(keys.public, "newPub~AAAA");
+    assert_eq!(keys.private, "newPriv~AAAA==");
+}
+
+#[tokio::test]
+async fn test_sam_stream_connect_and_accept() {
+    let sam = spawn_fake_sam().await;
+    let session = sam.create_stream_session("chimera", None).await.unwrap();
+    assert_eq!(session.style(), SessionStyle::Stream);
+    assert_eq!(session.private_keys(), SESSION_PRIV);
+    assert_eq!(session.destination(), SESSION_DEST);
+    assert_eq!(session.lookup("known.i2p").await.unwrap(), KNOWN_DEST);
+
+    // Once connected the socket is a plain data channel
+    let mut stream = session.connect(KNOWN_DEST).await.unwrap();
+    stream.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+
+    let err = session.connect("unknown").await.unwrap_err();
+    assert!(err.to_string().contains("no route"));
+
+    let (peer, mut incoming) = session.accept().await.unwrap();
+    assert_eq!(peer, KNOWN_DEST);
+    let mut greeting = String::new();
+    incoming.read_to_string(&mut greeting).await.unwrap();
+    assert_eq!(greeting, "hi there");
+
+    // Datagram operations need a datagram session
+    assert!(session.send_datagram(KNOWN_DEST, b"x").await.is_err());
+}
+
+#[tokio::test]
+async fn test_sam_datagrams() {
+    let sam = spawn_fake_sam().await;
+    let session = sam.create_datagram_session("dgram", None).await.unwrap();
+    assert!(session.connect(KNOWN_DEST).await.is_err());
+
+    session
+        .send_datagram(KNOWN_DEST, b"hello i2p")
+        .await
+        .unwrap();
+    let (sender, payload) = session.recv_datagram().await.unwrap();
+    assert_eq!(sender, KNOWN_DEST);
+    assert_eq!(payload, b"hello i2p");
+}
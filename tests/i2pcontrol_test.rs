@@ -0,0 +1,109 @@
+use chimera_node::adapters::i2p::i2pcontrol::{I2pControlClient, RouterCommand};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+/// Answer one JSON-RPC request per connection
+async fn handle(request: Value, auths: &AtomicUsize) -> Value {
+    let params = &request["params"];
+    let result = match request["method"].as_str().unwrap() {
+        "Authenticate" if params["Password"] != "secret" => {
+            return json!({ "id": 1, "jsonrpc": "2.0",
+                "error": { "code": -32001, "message": "Invalid password" } });
+        }
+        "Authenticate" => {
+            let n = auths.fetch_add(1, Ordering::SeqCst) + 1;
+            json!({ "API": 1, "Token": format!("t{}", n) })
+        }
+        // The first token is reported expired to exercise renewal
+        _ if params["Token"] == "t1" => {
+            return json!({ "id": 1, "jsonrpc": "2.0",
+                "error": { "code": -32004, "message": "Token expired" } });
+        }
+        "RouterInfo" => json!({
+            "i2p.router.version": "2.54.0",
+            "i2p.router.status": "1",
+            "i2p.router.uptime": 123456,
+            "i2p.router.net.status": 2,
+            "i2p.router.net.tunnels.participating": 17,
+            "i2p.router.netdb.knownpeers": 3000,
+            "i2p.router.netdb.activepeers": 120,
+            "i2p.router.net.bw.inbound.1s": 2048.5,
+            "i2p.router.net.bw.outbound.1s": 1024.0,
+        }),
+        "RouterManager" => {
+            assert!(params.get("Reseed").is_some());
+            json!({ "Reseed": null })
+        }
+        other => panic!("unexpected method {}", other),
+    };
+    json!({ "id": 1, "jsonrpc": "2.0", "result": result })
+}
+
+async fn spawn_fake_i2pcontrol() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let auths = Arc::new(AtomicUsize::new(0));
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let auths = auths.clone();
+            tokio::spawn(async move {
+                let (rx, mut tx) = socket.into_split();
+                let mut rx = BufReader::new(rx);
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    rx.read_line(&mut line).await.unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((k, v)) = line.split_once(':') {
+                        if k.eq_ignore_ascii_case("content-length") {
+                            content_length = v.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                rx.read_exact(&mut body).await.unwrap();
+                let response = handle(serde_json::from_slice(&body).unwrap(), &auths).await;
+                let body = response.to_string();
+                let http = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                tx.write_all(http.as_bytes()).await.unwrap();
+            });
+        }
+    });
+    format!("http://{}/", addr)
+}
+
+#[tokio::test]
+async fn test_router_info_renews_expired_token() {
+    let url = spawn_fake_i2pcontrol().await;
+    let client = I2pControlClient::new(url, "secret").unwrap();
+
+    let stats = client.router_info().await.unwrap();
+    assert_eq!(stats.version, "2.54.0");
+    assert_eq!(stats.net_status, 2);
+    assert!(stats.network_ok());
+    assert!(stats.tunnels_ready());
+    assert_eq!(stats.participating_tunnels, 17);
+    assert_eq!(stats.active_peers, 120);
+    assert_eq!(stats.inbound_bps, 2048.5);
+
+    client.router_command(RouterCommand::Reseed).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_wrong_password_fails() {
+    let url = spawn_fake_i2pcontrol().await;
+    let client = I2pControlClient::new(url, "wrong").unwrap();
+    let err = client.router_info().await.unwrap_err();
+    assert!(err.to_string().contains("-32001"));
+}
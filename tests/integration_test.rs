@@ -1,4 +1,9 @@
+mod common;
+
+use chimera_node::adapters::Endpoint;
+use chimera_node::health_monitor::Protocol;
 use chimera_node::socks5::Socks5Server;
+use std::net::SocketAddr;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
 
@@ -61,18 +66,9 @@ async fn test_socks5_integration() {
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let proxy_addr = listener.local_addr().unwrap();
 
-    let server = Socks5Server::new(
-        proxy_addr.port(),
-        tor_port, // Mock Tor
-        0,
-        0,
-        0,
-        0,
-        0,
-        0,
-        0, // Unused ports
-        "http://unused".to_string(),
-        "http://unused".to_string(),
+    let server = Socks5Server::new(proxy_addr.port()).with_endpoint(
+        Protocol::Tor,
+        Endpoint::Socks5(SocketAddr::from(([127, 0, 0, 1], tor_port))),
     );
 
     tokio::spawn(async move {
@@ -110,3 +106,178 @@ async fn test_socks5_integration() {
     client.read_exact(&mut resp).await.unwrap();
     assert_eq!(&resp, b"Hello Chimera");
 }
+
+#[tokio::test]
+async fn test_socks5_stream_isolation() {
+    use chimera_node::config::IsolationMode;
+
+    // Mock Tor that reports the username each stream authenticated with
+    let (tor_addr, mut rx) = common::socks_proxy(common::SUCCEEDED).await;
+    let tor_port = tor_addr.port();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let server = Socks5Server::new(proxy_addr.port())
+        .with_endpoint(
+            Protocol::Tor,
+            Endpoint::Socks5(SocketAddr::from(([127, 0, 0, 1], tor_port))),
+        )
+        .with_isolation(IsolationMode::DestinationDomain);
+    tokio::spawn(async move {
+        server.serve(listener).await.unwrap();
+    });
+
+    async fn connect_via(proxy: std::net::SocketAddr, target: &str) {
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+        let mut buf = [0u8; 2];
+        client.read_exact(&mut buf).await.unwrap();
+        let mut pkt = vec![0x05, 0x01, 0x00, 0x03, target.len() as u8];
+        pkt.extend_from_slice(target.as_bytes());
+        pkt.extend_from_slice(&80u16.to_be_bytes());
+        client.write_all(&pkt).await.unwrap();
+        let mut reply = [0u8; 10];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[1], 0x00);
+    }
+
+    connect_via(proxy_addr, "a.onion").await;
+    let first = rx.recv().await.unwrap().username.unwrap();
+    connect_via(proxy_addr, "a.onion").await;
+    let again = rx.recv().await.unwrap().username.unwrap();
+    connect_via(proxy_addr, "b.onion").await;
+    let other = rx.recv().await.unwrap().username.unwrap();
+
+    assert_eq!(first, again);
+    assert_ne!(first, other);
+}
+
+#[tokio::test]
+async fn test_socks5_tor_extended_errors() {
+    // Mock Tor that fails every CONNECT with 0xF4 (client authorization required)
+    let (tor_addr, _connects) = common::socks_proxy(0xF4).await;
+    let tor_port = tor_addr.port();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let server = Socks5Server::new(proxy_addr.port()).with_endpoint(
+        Protocol::Tor,
+        Endpoint::Socks5(SocketAddr::from(([127, 0, 0, 1], tor_port))),
+    );
+    let stats = server.stats();
+    tokio::spawn(async move {
+        server.serve(listener).await.unwrap();
+    });
+
+    // Returns the reply code the client sees
+    async fn connect_via(proxy: std::net::SocketAddr, username: Option<&str>) -> u8 {
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        let mut buf = [0u8; 2];
+        match username {
+            Some(user) => {
+                client.write_all(&[0x05, 0x01, 0x02]).await.unwrap();
+                client.read_exact(&mut buf).await.unwrap();
+                assert_eq!(buf, [0x05, 0x02]);
+                let mut auth = vec![0x01, user.len() as u8];
+                auth.extend_from_slice(user.as_bytes());
+                auth.extend_from_slice(&[0x01, b'x']);
+                client.write_all(&auth).await.unwrap();
+                client.read_exact(&mut buf).await.unwrap();
+            }
+            None => {
+                client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+                client.read_exact(&mut buf).await.unwrap();
+            }
+        }
+        let target = "locked.onion";
+        let mut pkt = vec![0x05, 0x01, 0x00, 0x03, target.len() as u8];
+        pkt.extend_from_slice(target.as_bytes());
+        pkt.extend_from_slice(&80u16.to_be_bytes());
+        client.write_all(&pkt).await.unwrap();
+        let mut reply = [0u8; 10];
+        client.read_exact(&mut reply).await.unwrap();
+        reply[1]
+    }
+
+    // Clients that negotiated Tor's extensions see the extended code as-is
+    assert_eq!(connect_via(proxy_addr, Some("<torS0X>0")).await, 0xF4);
+    // Everyone else gets the closest standard code
+    assert_eq!(connect_via(proxy_addr, None).await, 0x04);
+    assert_eq!(connect_via(proxy_addr, Some("alice")).await, 0x04);
+
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.connections_failed, 3);
+    assert_eq!(snapshot.errors["client authorization required"], 3);
+}
+
+#[tokio::test]
+async fn test_socks5_chain_nested_connects() {
+    use chimera_node::config::RouteRule;
+    use std::collections::HashMap;
+
+    // First hop (fake Tor): a real relay that dials whatever it is asked to
+    let hop1 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let tor_port = hop1.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (mut socket, _) = hop1.accept().await.unwrap();
+        let target = common::accept_connect(&mut socket).await.unwrap().target();
+        let mut next = TcpStream::connect(target).await.unwrap();
+        common::reply(&mut socket, common::SUCCEEDED).await.unwrap();
+        tokio::io::copy_bidirectional(&mut socket, &mut next)
+            .await
+            .ok();
+    });
+
+    // Second hop: reports the final destination, then echoes
+    let (hop2_addr, mut rx) = common::socks_proxy(common::SUCCEEDED).await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let chains = HashMap::from([(
+        "two".to_string(),
+        vec!["tor".to_string(), format!("socks5://{}", hop2_addr)],
+    )]);
+    let server = Socks5Server::new(proxy_addr.port())
+        .with_endpoint(
+            Protocol::Tor,
+            Endpoint::Socks5(SocketAddr::from(([127, 0, 0, 1], tor_port))),
+        )
+        .with_chains(
+            &chains,
+            &[RouteRule {
+                host: "*.example.com".to_string(),
+                chain: "two".to_string(),
+            }],
+        )
+        .unwrap();
+    let stats = server.stats();
+    tokio::spawn(async move {
+        server.serve(listener).await.unwrap();
+    });
+
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut buf = [0u8; 2];
+    client.read_exact(&mut buf).await.unwrap();
+    let target = "www.example.com";
+    let mut pkt = vec![0x05, 0x01, 0x00, 0x03, target.len() as u8];
+    pkt.extend_from_slice(target.as_bytes());
+    pkt.extend_from_slice(&443u16.to_be_bytes());
+    client.write_all(&pkt).await.unwrap();
+    let mut reply = [0u8; 10];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[1], 0x00);
+
+    // The last hop was asked for the destination, through the first
+    assert_eq!(rx.recv().await.unwrap().target(), "www.example.com:443");
+    client.write_all(b"nested").await.unwrap();
+    let mut echo = [0u8; 6];
+    client.read_exact(&mut echo).await.unwrap();
+    assert_eq!(&echo, b"nested");
+
+    let hops = &stats.snapshot().chains["two"];
+    assert_eq!(hops.len(), 2);
+    assert_eq!(hops[0].hop, "tor");
+    assert_eq!(hops[1].hop, hop2_addr.to_string());
+    assert!(hops.iter().all(|h| h.samples == 1));
+}
@@ -0,0 +1,106 @@
+use chimera_node::adapters::Endpoint;
+use chimera_node::health_monitor::Protocol;
+use chimera_node::socks5::Socks5Server;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+/// Stand-in for Lokinet's resolver: `echo.loki` is 127.0.0.1, anything else
+/// is NXDOMAIN
+async fn spawn_lokinet_dns() -> u16 {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let port = socket.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 512];
+        loop {
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let query = &buf[..len];
+
+            let mut labels = Vec::new();
+            let mut pos = 12;
+            while query[pos] != 0 {
+                let l = query[pos] as usize;
+                labels.push(String::from_utf8_lossy(&query[pos + 1..pos + 1 + l]).to_string());
+                pos += 1 + l;
+            }
+            let question_end = pos + 5;
+
+            let mut reply = query[..question_end].to_vec();
+            reply[2] = 0x81;
+            if labels.join(".") == "echo.loki" {
+                reply[3] = 0x80;
+                reply[7] = 1; // ANCOUNT
+                reply.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 127, 0, 0, 1]);
+            } else {
+                reply[3] = 0x83; // NXDOMAIN
+            }
+            socket.send_to(&reply, peer).await.unwrap();
+        }
+    });
+    port
+}
+
+/// SOCKS5 CONNECT through the proxy; returns the reply code and the stream
+async fn connect(proxy: std::net::SocketAddr, host: &str, port: u16) -> (u8, TcpStream) {
+    let mut client = TcpStream::connect(proxy).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut buf = [0u8; 2];
+    client.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, [0x05, 0x00]);
+
+    let mut pkt = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+    pkt.extend_from_slice(host.as_bytes());
+    pkt.extend_from_slice(&port.to_be_bytes());
+    client.write_all(&pkt).await.unwrap();
+
+    let mut reply = [0u8; 10];
+    client.read_exact(&mut reply).await.unwrap();
+    (reply[1], client)
+}
+
+#[tokio::test]
+async fn test_loki_names_resolve_through_lokinet_dns() {
+    let dns_port = spawn_lokinet_dns().await;
+
+    // The service behind the range address: a plain TCP echo server, with no
+    // SOCKS handshake in front of it
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target_port = target.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (mut socket, _) = target.accept().await.unwrap();
+        let (mut rd, mut wr) = socket.split();
+        tokio::io::copy(&mut rd, &mut wr).await.unwrap();
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    // Lokinet's interface, with the stand-in as its resolver
+    let lokinet = Endpoint::Tun {
+        interface: "chimera0".to_string(),
+        prefixes: Vec::new(),
+        dns: Some(SocketAddr::from(([127, 0, 0, 1], dns_port))),
+    };
+    let server = Socks5Server::new(proxy_addr.port()).with_endpoint(Protocol::Lokinet, lokinet);
+
+    let plan = server.plan_route("echo.loki").await;
+    assert_eq!(plan.connect_host, "127.0.0.1");
+    assert!(plan
+        .steps
+        .iter()
+        .any(|s| s.ends_with("echo.loki = 127.0.0.1")));
+
+    tokio::spawn(async move {
+        server.serve(listener).await.unwrap();
+    });
+
+    let (code, mut client) = connect(proxy_addr, "echo.loki", target_port).await;
+    assert_eq!(code, 0x00);
+    client.write_all(b"Hello Lokinet").await.unwrap();
+    let mut resp = [0u8; 13];
+    client.read_exact(&mut resp).await.unwrap();
+    assert_eq!(&resp, b"Hello Lokinet");
+
+    // Unknown names are reported as unreachable hosts
+    let (code, _) = connect(proxy_addr, "missing.snode", target_port).await;
+    assert_eq!(code, 0x04);
+}
@@ -0,0 +1,129 @@
+#![cfg(unix)]
+
+use chimera_node::adapters::meek::MeekAdapter;
+use chimera_node::config::PtSettings;
+use chimera_node::pt_manager::{ManagedTransport, PtManager, SocksVersion};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Write a scripted fake PT executable into a fresh temp directory
+fn fake_pt(name: &str, body: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chimera-pt-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fake-pt.sh");
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+fn settings(path: &Path, transports: &[&str], upstream_proxy: Option<&str>) -> PtSettings {
+    PtSettings {
+        binary_path: path.to_string_lossy().to_string(),
+        transports: transports.iter().map(|t| t.to_string()).collect(),
+        state_dir: Some(
+            path.parent()
+                .unwrap()
+                .join("state")
+                .to_string_lossy()
+                .to_string(),
+        ),
+        upstream_proxy: upstream_proxy.map(|p| p.to_string()),
+    }
+}
+
+// Speaks the managed-proxy protocol and only answers if the environment is right
+const WELL_BEHAVED: &str = r#"
+[ "$TOR_PT_MANAGED_TRANSPORT_VER" = "1" ] || { echo "VERSION-ERROR no-version"; exit 1; }
+[ -d "$TOR_PT_STATE_LOCATION" ] || { echo "ENV-ERROR missing state dir"; exit 1; }
+echo "VERSION 1"
+port=47100
+for t in $(echo "$TOR_PT_CLIENT_TRANSPORTS" | tr ',' ' '); do
+  if [ "$t" = "bogus" ]; then
+    echo "CMETHOD-ERROR bogus not supported"
+  else
+    port=$((port + 1))
+    echo "CMETHOD $t socks5 127.0.0.1:$port"
+  fi
+done
+echo "CMETHODS DONE"
+echo "LOG SEVERITY=notice MESSAGE=\"ready\""
+cat > /dev/null
+"#;
+
+#[tokio::test]
+async fn test_pt_handshake_exposes_endpoints() {
+    let path = fake_pt("ok", WELL_BEHAVED);
+    let transport = ManagedTransport::new(settings(&path, &["obfs4", "bogus", "meek_lite"], None));
+
+    let methods = transport.launch().await.unwrap();
+    assert_eq!(methods.len(), 2);
+    assert_eq!(methods[0].transport, "obfs4");
+    assert_eq!(methods[0].protocol, SocksVersion::Socks5);
+    assert_eq!(transport.endpoint("obfs4").unwrap().port(), 47101);
+    assert_eq!(transport.endpoint("meek_lite").unwrap().port(), 47102);
+    assert!(transport.endpoint("bogus").is_none());
+    assert!(transport.is_running().await);
+
+    transport.shutdown().await.unwrap();
+    assert!(transport.endpoint("obfs4").is_none());
+}
+
+#[tokio::test]
+async fn test_pt_version_error_fails_launch() {
+    let path = fake_pt("version", "echo 'VERSION-ERROR no-version'\nexit 1");
+    let transport = ManagedTransport::new(settings(&path, &["obfs4"], None));
+
+    let err = transport.launch().await.unwrap_err();
+    assert!(err.to_string().contains("VERSION-ERROR"));
+    assert!(!transport.is_running().await);
+}
+
+#[tokio::test]
+async fn test_pt_upstream_proxy_must_be_acknowledged() {
+    let body = r#"
+echo "VERSION 1"
+[ "$TOR_PT_PROXY" = "socks5://127.0.0.1:1080" ] && echo "PROXY DONE"
+echo "CMETHOD obfs4 socks5 127.0.0.1:47200"
+echo "CMETHODS DONE"
+cat > /dev/null
+"#;
+    let path = fake_pt("proxy", body);
+    let transport =
+        ManagedTransport::new(settings(&path, &["obfs4"], Some("socks5://127.0.0.1:1080")));
+    transport.launch().await.unwrap();
+    transport.shutdown().await.unwrap();
+
+    // A transport that ignores TOR_PT_PROXY must not be used
+    let path = fake_pt("noproxy", WELL_BEHAVED);
+    let transport =
+        ManagedTransport::new(settings(&path, &["obfs4"], Some("socks5://127.0.0.1:1080")));
+    assert!(transport.launch().await.is_err());
+}
+
+#[tokio::test]
+async fn test_pt_manager_collects_client_methods() {
+    let path = fake_pt("manager", WELL_BEHAVED);
+    let manager = PtManager::new(vec![settings(&path, &["obfs4"], None)]);
+
+    let methods = manager.start_all().await;
+    assert_eq!(methods.len(), 1);
+    assert_eq!(methods[0].tor_plugin_line(), "obfs4 socks5 127.0.0.1:47101");
+    assert_eq!(manager.endpoint("obfs4").unwrap().port(), 47101);
+
+    manager.stop_all().await.unwrap();
+}
+
+#[test]
+fn test_meek_socks_args_carry_front_domain() {
+    let meek = MeekAdapter::new(
+        true,
+        "bin/meek-client".to_string(),
+        "ajax.aspnetcdn.com".to_string(),
+    );
+    let (user, pass) = meek.socks_args("https://meek.example.net/").unwrap();
+    assert_eq!(
+        user,
+        b"url=https://meek.example.net/;front=ajax.aspnetcdn.com".to_vec()
+    );
+    assert_eq!(pass, vec![0]);
+}